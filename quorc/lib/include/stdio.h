@@ -0,0 +1,39 @@
+/* Subset of <stdio.h> for @cimport. */
+#ifndef _QUOR_STDIO_H
+#define _QUOR_STDIO_H
+
+typedef unsigned long size_t;
+typedef struct _IO_FILE FILE;
+
+#define EOF (-1)
+#define BUFSIZ 8192
+
+#define SEEK_SET 0
+#define SEEK_CUR 1
+#define SEEK_END 2
+
+int printf(const char *format, ...);
+int fprintf(FILE *stream, const char *format, ...);
+int sprintf(char *str, const char *format, ...);
+int snprintf(char *str, size_t size, const char *format, ...);
+
+int puts(const char *s);
+int putchar(int c);
+int getchar(void);
+int fputs(const char *s, FILE *stream);
+int fputc(int c, FILE *stream);
+int fgetc(FILE *stream);
+char *fgets(char *s, int size, FILE *stream);
+
+FILE *fopen(const char *path, const char *mode);
+int fclose(FILE *stream);
+int fflush(FILE *stream);
+size_t fread(void *ptr, size_t size, size_t nmemb, FILE *stream);
+size_t fwrite(const void *ptr, size_t size, size_t nmemb, FILE *stream);
+int fseek(FILE *stream, long offset, int whence);
+long ftell(FILE *stream);
+
+int remove(const char *path);
+void perror(const char *s);
+
+#endif
@@ -0,0 +1,40 @@
+/* Subset of <stdlib.h> for @cimport. */
+#ifndef _QUOR_STDLIB_H
+#define _QUOR_STDLIB_H
+
+typedef unsigned long size_t;
+
+#define EXIT_SUCCESS 0
+#define EXIT_FAILURE 1
+#define RAND_MAX 2147483647
+
+typedef struct {
+    int quot;
+    int rem;
+} div_t;
+
+void *malloc(size_t size);
+void *calloc(size_t nmemb, size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+
+void exit(int status);
+void abort(void);
+char *getenv(const char *name);
+int system(const char *command);
+
+int atoi(const char *s);
+long atol(const char *s);
+long strtol(const char *s, char **end, int base);
+double atof(const char *s);
+
+int abs(int n);
+long labs(long n);
+div_t div(int numerator, int denominator);
+
+int rand(void);
+void srand(unsigned int seed);
+
+void qsort(void *base, size_t nmemb, size_t size, int (*compar)(const void *, const void *));
+
+#endif
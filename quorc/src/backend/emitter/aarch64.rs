@@ -5,8 +5,8 @@ use crate::{
         lir::{
             aarch64::{A64RegFpr, A64RegGpr, A64Regs},
             regalloc::{
-                Addr, CallTarget, CmpOp, LFunction, LInst, LTerm, Loc, Operand, RegType, RegWidth,
-                TargetRegs,
+                Addr, CallTarget, CmpOp, LFunction, LInst, LTerm, Loc, Operand, RegRef, RegType,
+                RegWidth, TargetRegs,
             },
         },
         target::TargetEmitter,
@@ -14,6 +14,7 @@ use crate::{
     },
     frontend::ast::Type,
     midend::mir::block::{GlobalDef, GlobalValue},
+    target::c_symbol,
 };
 
 #[derive(Debug, Default)]
@@ -23,6 +24,21 @@ pub struct ARMEmitter {
 }
 
 impl ARMEmitter {
+    /// Room below the frame for the callee-saved registers, keeping sp 16-byte aligned.
+    fn save_size(func: &LFunction<A64RegGpr, A64RegFpr>) -> i32 {
+        ((func.saved.len() + func.saved_fp.len()) as i32 * 8 + 15) & !15
+    }
+
+    /// Stores (`str`) or reloads (`ldr`) the callee-saved registers at the bottom of the frame.
+    fn saved_regs(&self, op: &str, func: &LFunction<A64RegGpr, A64RegFpr>) -> String {
+        let gprs = func.saved.iter().map(|r| self.target_regs.reg64(*r).to_string());
+        let fprs = func.saved_fp.iter().map(|f| self.target_regs.fpr_asm_name(*f));
+        gprs.chain(fprs)
+            .enumerate()
+            .map(|(i, reg)| format!("{op} {reg}, [sp, #{}]\n", i * 8))
+            .collect()
+    }
+
     fn loc_width(loc: &Loc<A64RegGpr, A64RegFpr>) -> RegWidth {
         match loc {
             Loc::PhysReg(rr) => rr.size,
@@ -37,6 +53,17 @@ impl ARMEmitter {
         }
     }
 
+    // A double in a v register or a spilled one's W128 stack slot
+    fn loc_is_float(loc: &Loc<A64RegGpr, A64RegFpr>) -> bool {
+        matches!(loc, Loc::PhysReg(rr) if rr.is_fpr())
+            || matches!(loc, Loc::Stack(_, RegWidth::W128))
+    }
+
+    fn operand_is_float(op: &Operand<A64RegGpr, A64RegFpr>) -> bool {
+        matches!(op, Operand::ImmF64(_))
+            || matches!(op, Operand::Loc(loc) if Self::loc_is_float(loc))
+    }
+
     fn scratch_at(n: u8, w: RegWidth) -> &'static str {
         match (n, w) {
             (16, RegWidth::W8 | RegWidth::W16 | RegWidth::W32) => "w16",
@@ -47,6 +74,23 @@ impl ARMEmitter {
         }
     }
 
+    fn sized_reg(reg: &str, w: RegWidth) -> String {
+        let prefix = if w == RegWidth::W64 { 'x' } else { 'w' };
+        format!("{}{}", prefix, &reg[1..])
+    }
+
+    fn exclusive_suffix(w: RegWidth) -> &'static str {
+        if w == RegWidth::W8 { "b" } else { "" }
+    }
+
+    // Load/store exclusive instructions for an atomic read-modify-write
+    fn exclusive_pair(order: MemOrder, w: RegWidth) -> (String, String) {
+        let load = if order.acquires() { "ldaxr" } else { "ldxr" };
+        let store = if order.releases() { "stlxr" } else { "stxr" };
+        let suffix = Self::exclusive_suffix(w);
+        (format!("{load}{suffix}"), format!("{store}{suffix}"))
+    }
+
     fn intern_f64(&self, f: f64) -> String {
         let bits = f.to_bits();
         let mut map = self.float_consts.borrow_mut();
@@ -94,6 +138,74 @@ impl ARMEmitter {
         }
     }
 
+    // Ensure a double is in a d register, loading it into `scratch` (d30,
+    // d31 or the destination) when it is not. Returns (preamble_asm, register_name).
+    fn float_operand_to_reg(
+        &self,
+        operand: &Operand<A64RegGpr, A64RegFpr>,
+        scratch: &str,
+    ) -> (String, String) {
+        match operand {
+            Operand::Loc(Loc::PhysReg(RegRef {
+                ty: RegType::FprReg(r),
+                ..
+            })) => (String::new(), self.target_regs.fpr_asm_name(*r)),
+            Operand::Loc(Loc::Stack(offset, _)) => (
+                format!("ldur {}, [x29, #-{}]\n", scratch, offset),
+                scratch.to_string(),
+            ),
+            _ => {
+                // Constants and general registers hold the double's bits
+                let (mut setup, bits) = self.operand_to_reg(operand, "x16");
+                setup.push_str(&format!(
+                    "fmov {}, {}\n",
+                    scratch,
+                    Self::sized_reg(&bits, RegWidth::W64)
+                ));
+                (setup, scratch.to_string())
+            }
+        }
+    }
+
+    // Moves the double in d register `src_reg` into `dst`.
+    fn store_float_to_loc(&self, dst: &Loc<A64RegGpr, A64RegFpr>, src_reg: &str) -> String {
+        match dst {
+            Loc::PhysReg(rr) => {
+                let dst_reg = match &rr.ty {
+                    RegType::GprReg(r) => self.target_regs.reg64(*r).to_string(),
+                    RegType::FprReg(r) => self.target_regs.fpr_asm_name(*r),
+                };
+                if dst_reg == src_reg {
+                    String::new()
+                } else {
+                    format!("fmov {}, {}\n", dst_reg, src_reg)
+                }
+            }
+            Loc::Stack(offset, _) => format!("stur {}, [x29, #-{}]\n", src_reg, offset),
+        }
+    }
+
+    fn emit_float_binop(
+        &self,
+        op: &str,
+        dst: &Loc<A64RegGpr, A64RegFpr>,
+        a: &Operand<A64RegGpr, A64RegFpr>,
+        b: &Operand<A64RegGpr, A64RegFpr>,
+    ) -> String {
+        let (setup_a, reg_a) = self.float_operand_to_reg(a, "d30");
+        let (setup_b, reg_b) = self.float_operand_to_reg(b, "d31");
+        let dst_reg = match dst {
+            Loc::PhysReg(RegRef {
+                ty: RegType::FprReg(r),
+                ..
+            }) => self.target_regs.fpr_asm_name(*r),
+            _ => "d30".to_string(),
+        };
+        let mut out = format!("{setup_a}{setup_b}{op} {dst_reg}, {reg_a}, {reg_b}\n");
+        out.push_str(&self.store_float_to_loc(dst, &dst_reg));
+        out
+    }
+
     fn loc_to_reg(&self, loc: &Loc<A64RegGpr, A64RegFpr>, scratch: &str) -> (String, String) {
         match loc {
             Loc::PhysReg(rr) => {
@@ -185,7 +297,8 @@ impl ARMEmitter {
                 out
             }
             _ => {
-                format!("ldr {}, {}\n", scratch, self.addr_str(addr))
+                let (setup, addr_s) = self.mem_operand("x17", addr);
+                format!("{}ldr {}, {}\n", setup, scratch, addr_s)
             }
         }
     }
@@ -205,6 +318,56 @@ impl ARMEmitter {
         }
     }
 
+    // Compute the effective address of `addr` into `dst_reg`.
+    fn addr_to_reg(&self, dst_reg: &str, addr: &Addr<A64RegGpr>) -> String {
+        match addr {
+            Addr::Global { sym, off } => {
+                let mut out = self.load_global_addr(dst_reg, *sym);
+                if *off != 0 {
+                    out.push_str(&format!("add {}, {}, #{}\n", dst_reg, dst_reg, off));
+                }
+                out
+            }
+            Addr::BaseOff { base, off } => {
+                let base_reg = self.target_regs.reg64(*base);
+                match off {
+                    0 => format!("mov {}, {}\n", dst_reg, base_reg),
+                    off if *off < 0 => format!("add {}, {}, #{}\n", dst_reg, base_reg, -off),
+                    off => format!("sub {}, {}, #{}\n", dst_reg, base_reg, off),
+                }
+            }
+            Addr::BaseIndex {
+                base,
+                index,
+                scale,
+                off,
+            } => {
+                let mut out = format!(
+                    "add {}, {}, {}, lsl #{}\n",
+                    dst_reg,
+                    self.target_regs.reg64(*base),
+                    self.target_regs.reg64(*index),
+                    (*scale).trailing_zeros()
+                );
+                if *off != 0 {
+                    out.push_str(&format!("sub {}, {}, #{}\n", dst_reg, dst_reg, off));
+                }
+                out
+            }
+        }
+    }
+
+    // Like `addr_str`, but frame offsets past the ±256 byte ldur/stur range
+    // are first materialized into `scratch`.
+    fn mem_operand(&self, scratch: &str, addr: &Addr<A64RegGpr>) -> (String, String) {
+        match addr {
+            Addr::BaseOff { off, .. } if !(-255..=256).contains(off) => {
+                (self.addr_to_reg(scratch, addr), format!("[{}]", scratch))
+            }
+            _ => (String::new(), self.addr_str(addr)),
+        }
+    }
+
     // Format an address for use in ldr/str (non-global).
     fn addr_str(&self, addr: &Addr<A64RegGpr>) -> String {
         match addr {
@@ -213,7 +376,7 @@ impl ARMEmitter {
                 if *off == 0 {
                     format!("[{}]", base_reg)
                 } else {
-                    format!("[{}, #-{}]", base_reg, off)
+                    format!("[{}, #{}]", base_reg, -off)
                 }
             }
             Addr::BaseIndex {
@@ -256,10 +419,11 @@ impl ARMEmitter {
     ) -> String {
         let mut out = String::new();
 
-        let is_lea = matches!(ty, Type::Array(_, _) | Type::Pointer(_));
+        let is_lea = matches!(ty, Type::Array(_, _))
+            || matches!((ty, addr), (Type::Pointer(_), Addr::Global { .. }));
 
         if is_lea {
-            // Pointer/array types: compute effective address (LEA equivalent)
+            // Arrays and string globals: compute effective address (LEA equivalent)
             match addr {
                 Addr::Global { sym, off } => {
                     out.push_str(&self.load_global_addr("x16", *sym));
@@ -271,6 +435,8 @@ impl ARMEmitter {
                     let base_reg = self.target_regs.reg64(*base);
                     if *off == 0 {
                         out.push_str(&format!("mov x16, {}\n", base_reg));
+                    } else if *off < 0 {
+                        out.push_str(&format!("add x16, {}, #{}\n", base_reg, -off));
                     } else {
                         out.push_str(&format!("sub x16, {}, #{}\n", base_reg, off));
                     }
@@ -317,7 +483,8 @@ impl ARMEmitter {
                     out.push_str(&format!("{} {}, [x16]\n", load_instr, tmp));
                 }
                 _ => {
-                    let addr_s = self.addr_str(addr);
+                    let (setup, addr_s) = self.mem_operand("x17", addr);
+                    out.push_str(&setup);
                     let tmp = if use_w { "w16" } else { "x16" };
                     out.push_str(&format!("{} {}, {}\n", load_instr, tmp, addr_s));
                 }
@@ -361,7 +528,8 @@ impl ARMEmitter {
                 out.push_str(&format!("{} {}, [x17]\n", store_instr, sized_src));
             }
             _ => {
-                let addr_s = self.addr_str(addr);
+                let (setup, addr_s) = self.mem_operand("x17", addr);
+                out.push_str(&setup);
                 out.push_str(&format!("{} {}, {}\n", store_instr, sized_src, addr_s));
             }
         }
@@ -372,8 +540,10 @@ impl ARMEmitter {
     fn emit_call(
         &self,
         dst: &Option<Loc<A64RegGpr, A64RegFpr>>,
+        dst_hi: &Option<Loc<A64RegGpr, A64RegFpr>>,
         func: &CallTarget<A64RegGpr>,
         args: &[Operand<A64RegGpr, A64RegFpr>],
+        sret: &Option<Operand<A64RegGpr, A64RegFpr>>,
     ) -> String {
         let mut out = String::new();
         let arg_regs = self.target_regs.arg_regs();
@@ -382,15 +552,9 @@ impl ARMEmitter {
         let mut fp_idx = 0;
 
         for arg in args {
-            let is_fp = match arg {
-                Operand::Loc(Loc::PhysReg(rr)) => rr.is_fpr(),
-                Operand::ImmF64(_) => true,
-                _ => false,
-            };
-
-            if is_fp {
-                let reg = self.target_regs.float128(fp_regs[fp_idx]);
-                let (setup, src_reg) = self.operand_to_reg(arg, "x16");
+            if Self::operand_is_float(arg) {
+                let reg = self.target_regs.fpr_asm_name(fp_regs[fp_idx]);
+                let (setup, src_reg) = self.float_operand_to_reg(arg, &reg);
                 out.push_str(&setup);
                 if src_reg != reg {
                     out.push_str(&format!("fmov {}, {}\n", reg, src_reg));
@@ -408,17 +572,34 @@ impl ARMEmitter {
                 gp_idx += 1;
             }
         }
+        // Argument registers and x8 are never allocated, so no source was overwritten
+        if let Some(ptr) = sret {
+            let (setup, src_reg) = self.operand_to_reg(ptr, "x8");
+            out.push_str(&setup);
+            if src_reg != "x8" {
+                out.push_str(&format!("mov x8, {}\n", src_reg));
+            }
+        }
 
         match func {
             CallTarget::Direct(sym) => {
                 out.push_str(&format!("bl __q_f_{}\n", sym));
             }
+            CallTarget::Extern(sym) => {
+                out.push_str(&format!("bl {}\n", c_symbol(sym)));
+            }
             CallTarget::Indirect(reg) => {
                 out.push_str(&format!("blr {}\n", self.target_regs.reg64(*reg)));
             }
         }
 
-        if let Some(d) = dst {
+        if dst_hi.is_some() {
+            out.push_str("mov x17, x1\n");
+        }
+
+        if let Some(d) = dst.as_ref().filter(|d| Self::loc_is_float(d)) {
+            out.push_str(&self.store_float_to_loc(d, "d0"));
+        } else if let Some(d) = dst {
             let dst_w = Self::loc_width(d);
             let ret_reg = match dst_w {
                 RegWidth::W8 | RegWidth::W16 | RegWidth::W32 => "w0",
@@ -427,49 +608,61 @@ impl ARMEmitter {
             out.push_str(&self.store_to_loc(d, ret_reg));
         }
 
+        if let Some(d) = dst_hi {
+            out.push_str(&self.store_to_loc(d, Self::scratch_at(17, Self::loc_width(d))));
+        }
+
         out
     }
 
+    /// Converts `src` of type `from` to `ty`: integers are first widened into
+    /// x16 (zero-extending char and bool, sign-extending int), then truncated
+    /// to the destination or converted to a double.
     fn emit_cast(
         &self,
         dst: &Loc<A64RegGpr, A64RegFpr>,
         src: &Operand<A64RegGpr, A64RegFpr>,
+        from: &Type,
         ty: &Type,
     ) -> String {
         let mut out = String::new();
-
-        match ty {
-            Type::Long | Type::Pointer(_) => {
-                // Sign-extend 32-bit to 64-bit: sxtw uses w-register source
-                let (setup, src_reg) = self.operand_to_reg(src, "w16");
-                out.push_str(&setup);
-                let w_reg = if src_reg.starts_with('x') {
-                    format!("w{}", &src_reg[1..])
-                } else {
-                    src_reg
-                };
-                out.push_str(&format!("sxtw x16, {}\n", w_reg));
-                out.push_str(&self.store_to_loc(dst, "x16"));
-            }
-            Type::Bool | Type::Char => {
-                // Mask to 8-bit: and uses x-register form
-                let (setup, src_reg) = self.operand_to_reg(src, "x16");
-                out.push_str(&setup);
-                let dst_w = Self::loc_width(dst);
-                let ds16 = Self::scratch_at(16, dst_w);
-                out.push_str(&format!("and {}, {}, #0xff\n", ds16, src_reg));
-                out.push_str(&self.store_to_loc(dst, ds16));
-            }
-            _ => {
-                // Default: move at dst width
-                let dst_w = Self::loc_width(dst);
-                let s16 = Self::scratch_at(16, dst_w);
-                let (setup, src_reg) = self.operand_to_reg(src, s16);
-                out.push_str(&setup);
-                out.push_str(&self.store_to_loc(dst, &src_reg));
+        if *from == Type::float {
+            // Truncates toward zero
+            let (setup, reg) = self.float_operand_to_reg(src, "d31");
+            out.push_str(&setup);
+            out.push_str(&format!("fcvtzs x16, {}\n", reg));
+        } else {
+            let (setup, reg) = self.operand_to_reg(src, "x16");
+            out.push_str(&setup);
+            match from {
+                _ if matches!(src, Operand::ImmI64(_)) => {}
+                Type::Char | Type::Bool => out.push_str(&format!(
+                    "and w16, {}, #0xff\n",
+                    Self::sized_reg(&reg, RegWidth::W32)
+                )),
+                Type::int => out.push_str(&format!(
+                    "sxtw x16, {}\n",
+                    Self::sized_reg(&reg, RegWidth::W32)
+                )),
+                _ if reg != "x16" => out.push_str(&format!(
+                    "mov x16, {}\n",
+                    Self::sized_reg(&reg, RegWidth::W64)
+                )),
+                _ => {}
             }
         }
-
+        if *ty != Type::float {
+            out.push_str(&self.store_to_loc(dst, Self::scratch_at(16, Self::loc_width(dst))));
+        } else if let Loc::PhysReg(RegRef {
+            ty: RegType::FprReg(r),
+            ..
+        }) = dst
+        {
+            out.push_str(&format!("scvtf {}, x16\n", self.target_regs.fpr_asm_name(*r)));
+        } else {
+            out.push_str("scvtf d30, x16\n");
+            out.push_str(&self.store_float_to_loc(dst, "d30"));
+        }
         out
     }
 }
@@ -539,7 +732,7 @@ impl TargetEmitter for ARMEmitter {
     fn t_prologue(
         &self,
         ctx: &mut CodegenCtx<Self::Reg, Self::FpReg>,
-        _func: &LFunction<Self::Reg, Self::FpReg>,
+        func: &LFunction<Self::Reg, Self::FpReg>,
     ) -> String {
         let mut out = String::new();
 
@@ -547,10 +740,12 @@ impl TargetEmitter for ARMEmitter {
         out.push_str("stp x29, x30, [sp, #-16]!\n");
         out.push_str("mov x29, sp\n");
 
-        // Allocate stack frame for locals
-        if ctx.frame.frame_size > 0 {
-            out.push_str(&format!("sub sp, sp, #{}\n", ctx.frame.frame_size));
+        // Allocate stack frame for locals, with the callee-saved registers below them
+        let size = ctx.frame.frame_size + Self::save_size(func);
+        if size > 0 {
+            out.push_str(&format!("sub sp, sp, #{}\n", size));
         }
+        out.push_str(&self.saved_regs("str", func));
 
         out
     }
@@ -563,8 +758,17 @@ impl TargetEmitter for ARMEmitter {
         let mut out = String::new();
         out.push_str(&format!(".Lret_{}:\n", func.name));
 
+        let save_size = Self::save_size(func);
+        if save_size > 0 {
+            out.push_str(&format!(
+                "sub sp, x29, #{}\n",
+                ctx.frame.frame_size + save_size
+            ));
+            out.push_str(&self.saved_regs("ldr", func));
+        }
+
         // Deallocate stack frame
-        if ctx.frame.frame_size > 0 {
+        if ctx.frame.frame_size + save_size > 0 {
             out.push_str("mov sp, x29\n");
         }
 
@@ -581,6 +785,18 @@ impl TargetEmitter for ARMEmitter {
         _ctx: &mut CodegenCtx<Self::Reg, Self::FpReg>,
     ) -> String {
         match inst {
+            LInst::Add { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("fadd", dst, a, b)
+            }
+            LInst::Sub { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("fsub", dst, a, b)
+            }
+            LInst::Mul { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("fmul", dst, a, b)
+            }
+            LInst::Div { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("fdiv", dst, a, b)
+            }
             LInst::Add { dst, a, b } => self.emit_binop("add", dst, a, b),
             LInst::Sub { dst, a, b } => self.emit_binop("sub", dst, a, b),
             LInst::Mul { dst, a, b } => self.emit_binop("mul", dst, a, b),
@@ -622,6 +838,26 @@ impl TargetEmitter for ARMEmitter {
 
                 out
             }
+            LInst::CmpSet { dst, op, a, b }
+                if Self::operand_is_float(a) || Self::operand_is_float(b) =>
+            {
+                // An unordered fcmp sets C and V, so mi and ls are false for a NaN
+                let cond = match op {
+                    CmpOp::Eq => "eq",
+                    CmpOp::Ne => "ne",
+                    CmpOp::Lt => "mi",
+                    CmpOp::Le => "ls",
+                    CmpOp::Gt => "gt",
+                    CmpOp::Ge => "ge",
+                };
+                let (setup_a, reg_a) = self.float_operand_to_reg(a, "d30");
+                let (setup_b, reg_b) = self.float_operand_to_reg(b, "d31");
+                let mut out = format!("{setup_a}{setup_b}fcmp {reg_a}, {reg_b}\n");
+                let w = Self::loc_width(dst);
+                out.push_str(&format!("cset {}, {}\n", Self::scratch_at(16, w), cond));
+                out.push_str(&self.store_to_loc(dst, Self::scratch_at(16, w)));
+                out
+            }
             LInst::CmpSet { dst, op, a, b } => {
                 let mut out = String::new();
                 // cmp operands use their own width
@@ -668,10 +904,28 @@ impl TargetEmitter for ARMEmitter {
 
                 out
             }
-            LInst::Cast { dst, src, ty } => self.emit_cast(dst, src, ty),
+            LInst::Cast { dst, src, from, ty } => self.emit_cast(dst, src, from, ty),
             LInst::Load { dst, addr, ty } => self.emit_load(dst, addr, ty),
             LInst::Store { src, addr, ty } => self.emit_store(src, addr, ty),
-            LInst::Call { dst, func, args } => self.emit_call(dst, func, args),
+            LInst::Call {
+                dst,
+                dst_hi,
+                func,
+                args,
+                sret,
+            } => self.emit_call(dst, dst_hi, func, args, sret),
+            LInst::Mov { dst, src } if Self::loc_is_float(dst) || Self::operand_is_float(src) => {
+                let scratch = match dst {
+                    Loc::PhysReg(RegRef {
+                        ty: RegType::FprReg(r),
+                        ..
+                    }) => self.target_regs.fpr_asm_name(*r),
+                    _ => "d30".to_string(),
+                };
+                let (mut out, src_reg) = self.float_operand_to_reg(src, &scratch);
+                out.push_str(&self.store_float_to_loc(dst, &src_reg));
+                out
+            }
             LInst::Mov { dst, src } => {
                 let mut out = String::new();
                 let w = Self::loc_width(dst);
@@ -696,7 +950,11 @@ impl TargetEmitter for ARMEmitter {
                         if *off == 0 {
                             out.push_str(&self.store_to_loc(dst, base_reg));
                         } else {
-                            out.push_str(&format!("sub x16, {}, #{}\n", base_reg, off));
+                            if *off < 0 {
+                                out.push_str(&format!("add x16, {}, #{}\n", base_reg, -off));
+                            } else {
+                                out.push_str(&format!("sub x16, {}, #{}\n", base_reg, off));
+                            }
                             out.push_str(&self.store_to_loc(dst, "x16"));
                         }
                     }
@@ -727,6 +985,114 @@ impl TargetEmitter for ARMEmitter {
                 out
             }
             LInst::InlineAsm { asm } => format!("{}\n", asm),
+            LInst::AtomicLoad {
+                dst,
+                addr,
+                ty,
+                order,
+            } => {
+                let w = type_to_reg_width(ty);
+                let instr = if order.is_some_and(|order| order.acquires()) {
+                    "ldar"
+                } else {
+                    "ldr"
+                };
+                let mut out = self.addr_to_reg("x17", addr);
+                let tmp = Self::scratch_at(16, w);
+                out.push_str(&format!(
+                    "{}{} {}, [x17]\n",
+                    instr,
+                    Self::exclusive_suffix(w),
+                    tmp
+                ));
+                out.push_str(&self.store_to_loc(dst, tmp));
+                out
+            }
+            LInst::AtomicStore {
+                src,
+                addr,
+                ty,
+                order,
+            } => {
+                let w = type_to_reg_width(ty);
+                let instr = if order.is_some_and(|order| order.releases()) {
+                    "stlr"
+                } else {
+                    "str"
+                };
+                // The address goes first: a spilled base is reloaded into x16
+                let mut out = self.addr_to_reg("x17", addr);
+                let (setup, src_reg) = self.operand_to_reg(src, "x16");
+                out.push_str(&setup);
+                out.push_str(&format!(
+                    "{}{} {}, [x17]\n",
+                    instr,
+                    Self::exclusive_suffix(w),
+                    Self::sized_reg(&src_reg, w)
+                ));
+                out
+            }
+            LInst::AtomicRmw {
+                dst,
+                op,
+                addr,
+                src,
+                ty,
+                order,
+            } => {
+                let w = type_to_reg_width(ty);
+                let (load, store) = Self::exclusive_pair(*order, w);
+                let old = Self::sized_reg("x14", w);
+                let mut out = self.addr_to_reg("x17", addr);
+                let (setup, src_reg) = self.operand_to_reg(src, "x16");
+                out.push_str(&setup);
+                let value = Self::sized_reg(&src_reg, w);
+
+                out.push_str(&format!("1:\n{} {}, [x17]\n", load, old));
+                let new = match op {
+                    RmwOp::Swap => value,
+                    RmwOp::Add => {
+                        let new = Self::sized_reg("x13", w);
+                        out.push_str(&format!("add {}, {}, {}\n", new, old, value));
+                        new
+                    }
+                };
+                out.push_str(&format!("{} w15, {}, [x17]\ncbnz w15, 1b\n", store, new));
+                out.push_str(&self.store_to_loc(dst, &old));
+                out
+            }
+            LInst::AtomicCmpXchg {
+                dst,
+                addr,
+                expected,
+                desired,
+                ty,
+                order,
+            } => {
+                let w = type_to_reg_width(ty);
+                let (load, store) = Self::exclusive_pair(*order, w);
+                let old = Self::sized_reg("x14", w);
+                let mut out = self.addr_to_reg("x17", addr);
+                let (setup, desired_reg) = self.operand_to_reg(desired, "x16");
+                out.push_str(&setup);
+                let (setup, expected_reg) = self.operand_to_reg(expected, "x13");
+                out.push_str(&setup);
+                let expected_reg = Self::sized_reg(&expected_reg, w);
+                // Only the low byte of a char or bool is meaningful
+                let extend = if w == RegWidth::W8 { ", uxtb" } else { "" };
+
+                out.push_str(&format!(
+                    "1:\n{} {}, [x17]\ncmp {}, {}{}\nb.ne 2f\n",
+                    load, old, old, expected_reg, extend
+                ));
+                out.push_str(&format!(
+                    "{} w15, {}, [x17]\ncbnz w15, 1b\n2:\n",
+                    store,
+                    Self::sized_reg(&desired_reg, w)
+                ));
+                out.push_str(&self.store_to_loc(dst, &old));
+                out
+            }
         }
     }
 
@@ -737,8 +1103,24 @@ impl TargetEmitter for ARMEmitter {
     ) -> String {
         let mut asm = String::new();
         match term {
-            LTerm::Ret { value } => {
-                if let Some(operand) = value {
+            LTerm::Ret { value, value_hi } => {
+                // Park the high half first so loading x0 can't clobber it
+                let hi_w = value_hi.as_ref().map(Self::operand_width);
+                if let (Some(hi), Some(w)) = (value_hi, hi_w) {
+                    let s17 = Self::scratch_at(17, w);
+                    let (setup, src_reg) = self.operand_to_reg(hi, s17);
+                    asm.push_str(&setup);
+                    if src_reg != s17 {
+                        asm.push_str(&format!("mov {}, {}\n", s17, src_reg));
+                    }
+                }
+                if let Some(operand) = value.as_ref().filter(|op| Self::operand_is_float(op)) {
+                    let (setup, src_reg) = self.float_operand_to_reg(operand, "d0");
+                    asm.push_str(&setup);
+                    if src_reg != "d0" {
+                        asm.push_str(&format!("fmov d0, {}\n", src_reg));
+                    }
+                } else if let Some(operand) = value {
                     let w = Self::operand_width(operand);
                     let s16 = Self::scratch_at(16, w);
                     let ret_reg = match w {
@@ -751,6 +1133,13 @@ impl TargetEmitter for ARMEmitter {
                         asm.push_str(&format!("mov {}, {}\n", ret_reg, src_reg));
                     }
                 }
+                if let Some(w) = hi_w {
+                    let ret_hi = match w {
+                        RegWidth::W8 | RegWidth::W16 | RegWidth::W32 => "w1",
+                        _ => "x1",
+                    };
+                    asm.push_str(&format!("mov {}, {}\n", ret_hi, Self::scratch_at(17, w)));
+                }
                 asm.push_str(&format!("b .Lret_{}\n", ctx.func.name));
             }
             LTerm::Jump { target } => {
@@ -891,6 +1280,10 @@ impl TargetEmitter for ARMEmitter {
                 func_asm.push_str(".globl main\nmain:\n");
             }
         } else {
+            if let Some(symbol) = &func.export {
+                let symbol = c_symbol(symbol);
+                func_asm.push_str(&format!(".globl {symbol}\n{symbol}:\n"));
+            }
             func_asm.push_str(&format!("__q_f_{}:\n", func.name));
         }
         if func.has_frame {
@@ -922,10 +1315,7 @@ impl TargetEmitter for ARMEmitter {
     }
 
     fn t_extern(&self, ext: String) -> String {
-        if cfg!(target_os = "macos") {
-            return format!(".extern _{ext}");
-        }
-        format!(".extern {ext}")
+        format!(".extern {}", c_symbol(&ext))
     }
 }
 
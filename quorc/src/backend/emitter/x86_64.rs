@@ -93,14 +93,21 @@ impl TargetEmitter for X86Emitter {
     fn t_prologue(
         &self,
         ctx: &mut CodegenCtx<Self::Reg, Self::FpReg>,
-        _func: &crate::backend::lir::regalloc::LFunction<Self::Reg, Self::FpReg>,
+        func: &crate::backend::lir::regalloc::LFunction<Self::Reg, Self::FpReg>,
     ) -> String {
         let mut prologue = String::new();
 
         prologue.push_str(&format!(
             "push rbp\nmov rbp, rsp\nsub rsp, {}\n",
-            ctx.frame.frame_size
+            ctx.frame.frame_size + Self::save_size(func)
         ));
+        for (i, reg) in func.saved.iter().enumerate() {
+            prologue.push_str(&format!(
+                "mov qword [rbp - {}], {}\n",
+                ctx.frame.frame_size + 8 * (i as i32 + 1),
+                self.target_args.reg64(*reg)
+            ));
+        }
         prologue
     }
 
@@ -111,6 +118,13 @@ impl TargetEmitter for X86Emitter {
     ) -> String {
         let mut prologue = String::new();
         prologue.push_str(&format!(".Lret_{}:\n", func.name));
+        for (i, reg) in func.saved.iter().enumerate() {
+            prologue.push_str(&format!(
+                "mov {}, qword [rbp - {}]\n",
+                self.target_args.reg64(*reg),
+                ctx.frame.frame_size + 8 * (i as i32 + 1)
+            ));
+        }
         prologue.push_str("mov rsp, rbp\npop rbp\nret\n");
         prologue
     }
@@ -121,57 +135,58 @@ impl TargetEmitter for X86Emitter {
         _ctx: &mut CodegenCtx<Self::Reg, Self::FpReg>,
     ) -> String {
         match inst {
+            LInst::Add { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("addsd", dst, a, b)
+            }
             LInst::Add { dst, a, b } => {
                 let w = Self::loc_width(dst);
-                let (mv, arith) = if Self::loc_is_fpr(dst) { ("movsd", "addsd") } else { ("mov", "add") };
                 format!(
-                    "{} {}, {}\n{} {}, {}\n",
-                    mv, self.t_loc_at(dst, w), self.t_operand_at(a, w),
-                    arith, self.t_loc_at(dst, w), self.t_operand_at(b, w)
+                    "mov {}, {}\nadd {}, {}\n",
+                    self.t_loc_at(dst, w), self.t_operand_at(a, w),
+                    self.t_loc_at(dst, w), self.t_operand_at(b, w)
                 )
             }
+            LInst::Sub { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("subsd", dst, a, b)
+            }
             LInst::Sub { dst, a, b } => {
                 let w = Self::loc_width(dst);
-                let (mv, arith) = if Self::loc_is_fpr(dst) { ("movsd", "subsd") } else { ("mov", "sub") };
                 format!(
-                    "{} {}, {}\n{} {}, {}\n",
-                    mv, self.t_loc_at(dst, w), self.t_operand_at(a, w),
-                    arith, self.t_loc_at(dst, w), self.t_operand_at(b, w)
+                    "mov {}, {}\nsub {}, {}\n",
+                    self.t_loc_at(dst, w), self.t_operand_at(a, w),
+                    self.t_loc_at(dst, w), self.t_operand_at(b, w)
                 )
             }
+            LInst::Mul { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("mulsd", dst, a, b)
+            }
             LInst::Mul { dst, a, b } => {
                 let w = Self::loc_width(dst);
-                let (mv, arith) = if Self::loc_is_fpr(dst) { ("movsd", "mulsd") } else { ("mov", "imul") };
                 format!(
-                    "{} {}, {}\n{} {}, {}\n",
-                    mv, self.t_loc_at(dst, w), self.t_operand_at(a, w),
-                    arith, self.t_loc_at(dst, w), self.t_operand_at(b, w)
+                    "mov {}, {}\nimul {}, {}\n",
+                    self.t_loc_at(dst, w), self.t_operand_at(a, w),
+                    self.t_loc_at(dst, w), self.t_operand_at(b, w)
                 )
             }
+            LInst::Div { dst, a, b } if Self::loc_is_float(dst) => {
+                self.emit_float_binop("divsd", dst, a, b)
+            }
             LInst::Div { dst, a, b } => {
                 let w = Self::loc_width(dst);
-                if Self::loc_is_fpr(dst) {
-                    format!(
-                        "movsd {}, {}\ndivsd {}, {}\n",
-                        self.t_loc_at(dst, w), self.t_operand_at(a, w),
-                        self.t_loc_at(dst, w), self.t_operand_at(b, w)
-                    )
-                } else {
-                    let rax = self.target_args.reg_by_width(X86RegGpr::RAX, w);
-                    let sign_ext = match w {
-                        RegWidth::W8 => "cbw",
-                        RegWidth::W16 => "cwd",
-                        RegWidth::W32 => "cdq",
-                        _ => "cqo",
-                    };
-                    format!(
-                        "mov {}, {}\n{}\nidiv {}\nmov {}, {}\n",
-                        rax, self.t_operand_at(a, w),
-                        sign_ext,
-                        self.t_operand_at(b, w),
-                        self.t_loc_at(dst, w), rax
-                    )
-                }
+                let rax = self.target_args.reg_by_width(X86RegGpr::RAX, w);
+                let sign_ext = match w {
+                    RegWidth::W8 => "cbw",
+                    RegWidth::W16 => "cwd",
+                    RegWidth::W32 => "cdq",
+                    _ => "cqo",
+                };
+                format!(
+                    "mov {}, {}\n{}\nidiv {}\nmov {}, {}\n",
+                    rax, self.t_operand_at(a, w),
+                    sign_ext,
+                    self.t_operand_at(b, w),
+                    self.t_loc_at(dst, w), rax
+                )
             }
             LInst::Mod { dst, a, b } => {
                 let w = Self::loc_width(dst);
@@ -193,6 +208,11 @@ impl TargetEmitter for X86Emitter {
                     rdx
                 )
             }
+            LInst::CmpSet { dst, op, a, b }
+                if Self::operand_is_float(a) || Self::operand_is_float(b) =>
+            {
+                self.emit_float_cmp(dst, op, a, b)
+            }
             LInst::CmpSet { dst, op, a, b } => {
                 let setcc = match op {
                     CmpOp::Eq => "sete",
@@ -202,29 +222,51 @@ impl TargetEmitter for X86Emitter {
                     CmpOp::Gt => "setg",
                     CmpOp::Ge => "setge",
                 };
-                // cmp operands must match each other's width
-                let cmp_w = Self::operand_width(a);
+                // cmp operands must match each other's width; the flag goes
+                // through scratch so no allocated register is clobbered
+                let cmp_w = match a {
+                    Operand::ImmI64(_) => Self::operand_width(b),
+                    _ => Self::operand_width(a),
+                };
                 let dst_w = Self::loc_width(dst);
+                // The left operand cannot be an immediate, nor memory when the
+                // right one is too, so those are loaded into r11 first
+                let (setup, left) = match a {
+                    Operand::ImmI64(_) | Operand::Loc(Loc::Stack(..)) => {
+                        let r11 = Self::scratch_at(11, cmp_w);
+                        (
+                            format!("mov {}, {}\n", r11, self.t_operand_at(a, cmp_w)),
+                            r11.to_string(),
+                        )
+                    }
+                    _ => (String::new(), self.t_operand_at(a, cmp_w)),
+                };
                 format!(
-                    "cmp {}, {}\n{} al\nmovzx {}, al\n",
-                    self.t_operand_at(a, cmp_w),
+                    "{}cmp {}, {}\n{} r10b\nmovzx r10d, r10b\nmov {}, {}\n",
+                    setup,
+                    left,
                     self.t_operand_at(b, cmp_w),
                     setcc,
                     self.t_loc_at(dst, dst_w),
+                    Self::scratch_at(10, dst_w),
                 )
             }
-            LInst::Cast { dst, src, ty } => self.emit_cast(dst, src, ty),
+            LInst::Cast { dst, src, from, ty } => self.emit_cast(dst, src, from, ty),
             LInst::Load { dst, addr, ty } => self.emit_load(dst, addr, ty),
             LInst::Store { src, addr, ty } => self.emit_store(src, addr, ty),
-            LInst::Call { dst, func, args } => self.emit_call(dst, func, args),
+            LInst::Call {
+                dst,
+                dst_hi,
+                func,
+                args,
+                sret,
+            } => self.emit_call(dst, dst_hi, func, args, sret),
+            LInst::Mov { dst, src } if Self::loc_is_float(dst) || Self::operand_is_float(src) => {
+                self.emit_float_mov(dst, src)
+            }
             LInst::Mov { dst, src } => {
                 let w = Self::loc_width(dst);
-                format!(
-                    "{} {}, {}\n",
-                    Self::mov_mnem(dst),
-                    self.t_loc_at(dst, w),
-                    self.t_operand_at(src, w)
-                )
+                format!("mov {}, {}\n", self.t_loc_at(dst, w), self.t_operand_at(src, w))
             }
             LInst::Lea { dst, addr } => {
                 // LEA always operates on addresses (64-bit)
@@ -235,6 +277,29 @@ impl TargetEmitter for X86Emitter {
                 )
             }
             LInst::InlineAsm { asm } => format!("{}\n", asm),
+            LInst::AtomicLoad { dst, addr, ty, .. } => self.emit_atomic_load(dst, addr, ty),
+            LInst::AtomicStore {
+                src,
+                addr,
+                ty,
+                order,
+            } => self.emit_atomic_store(src, addr, ty, *order),
+            LInst::AtomicRmw {
+                dst,
+                op,
+                addr,
+                src,
+                ty,
+                ..
+            } => self.emit_atomic_rmw(dst, *op, addr, src, ty),
+            LInst::AtomicCmpXchg {
+                dst,
+                addr,
+                expected,
+                desired,
+                ty,
+                ..
+            } => self.emit_atomic_cmpxchg(dst, addr, expected, desired, ty),
         }
     }
 
@@ -284,9 +349,18 @@ impl TargetEmitter for X86Emitter {
     ) -> String {
         let mut asm = String::new();
         match term {
-            LTerm::Ret { value } => {
+            LTerm::Ret { value, value_hi } => {
+                // Park the high half first so loading rax can't clobber it
+                let hi_w = value_hi.as_ref().map(Self::operand_width);
+                if let (Some(hi), Some(w)) = (value_hi, hi_w) {
+                    asm.push_str(&format!(
+                        "mov {}, {}\n",
+                        Self::scratch_at(11, w),
+                        self.t_operand_at(hi, w)
+                    ));
+                }
                 if let Some(operand) = value {
-                    if Self::operand_is_fpr(operand) {
+                    if Self::operand_is_float(operand) {
                         asm.push_str(&format!(
                             "movsd xmm0, {}\n",
                             self.t_operand_at(operand, RegWidth::W64)
@@ -297,6 +371,10 @@ impl TargetEmitter for X86Emitter {
                         asm.push_str(&format!("mov {}, {}\n", rax, self.t_operand_at(operand, w)));
                     }
                 }
+                if let Some(w) = hi_w {
+                    let rdx = self.target_args.reg_by_width(X86RegGpr::RDX, w);
+                    asm.push_str(&format!("mov {}, {}\n", rdx, Self::scratch_at(11, w)));
+                }
                 if ctx.func.has_frame {
                     asm.push_str(&format!("jmp .Lret_{}\n", ctx.func.name));
                 } else {
@@ -347,17 +425,66 @@ impl TargetEmitter for X86Emitter {
 }
 
 impl X86Emitter {
+    /// Room below the frame for the callee-saved registers, keeping rsp 16-byte aligned.
+    fn save_size(func: &LFunction<X86RegGpr, X86RegFpr>) -> i32 {
+        (func.saved.len() as i32 * 8 + 15) & !15
+    }
+
     fn loc_is_fpr(loc: &Loc<X86RegGpr, X86RegFpr>) -> bool {
         matches!(loc, Loc::PhysReg(rr) if rr.is_fpr())
     }
 
-    fn operand_is_fpr(op: &Operand<X86RegGpr, X86RegFpr>) -> bool {
+    // A double in an xmm register or a spilled one's W128 stack slot
+    fn loc_is_float(loc: &Loc<X86RegGpr, X86RegFpr>) -> bool {
+        Self::loc_is_fpr(loc) || matches!(loc, Loc::Stack(_, RegWidth::W128))
+    }
+
+    fn operand_is_float(op: &Operand<X86RegGpr, X86RegFpr>) -> bool {
         matches!(op, Operand::ImmF64(_))
-            || matches!(op, Operand::Loc(loc) if Self::loc_is_fpr(loc))
+            || matches!(op, Operand::Loc(loc) if Self::loc_is_float(loc))
     }
 
-    fn mov_mnem(dst: &Loc<X86RegGpr, X86RegFpr>) -> &'static str {
-        if Self::loc_is_fpr(dst) { "movsd" } else { "mov" }
+    // SSE arithmetic needs a register destination, so a spilled result (or one
+    // whose register also holds `b`) is computed in xmm15, which is never
+    // allocated, and stored after
+    fn emit_float_binop(
+        &self,
+        op: &str,
+        dst: &Loc<X86RegGpr, X86RegFpr>,
+        a: &Operand<X86RegGpr, X86RegFpr>,
+        b: &Operand<X86RegGpr, X86RegFpr>,
+    ) -> String {
+        let dst_s = self.t_loc_at(dst, RegWidth::W64);
+        let a_s = self.t_operand_at(a, RegWidth::W64);
+        let b_s = self.t_operand_at(b, RegWidth::W64);
+        if Self::loc_is_fpr(dst) && (b_s != dst_s || a_s == dst_s) {
+            format!("movsd {dst_s}, {a_s}\n{op} {dst_s}, {b_s}\n")
+        } else {
+            format!("movsd xmm15, {a_s}\n{op} xmm15, {b_s}\nmovsd {dst_s}, xmm15\n")
+        }
+    }
+
+    // movsd cannot copy memory to memory or reach general registers, so those
+    // go through xmm15 and movq
+    fn emit_float_mov(
+        &self,
+        dst: &Loc<X86RegGpr, X86RegFpr>,
+        src: &Operand<X86RegGpr, X86RegFpr>,
+    ) -> String {
+        let dst_s = self.t_loc_at(dst, RegWidth::W64);
+        let src_s = self.t_operand_at(src, RegWidth::W64);
+        match (dst, src) {
+            (Loc::PhysReg(rr), _) if !rr.is_fpr() => format!("movq {dst_s}, {src_s}\n"),
+            (Loc::PhysReg(_), Operand::Loc(Loc::PhysReg(rr))) if !rr.is_fpr() => {
+                format!("movq {dst_s}, {src_s}\n")
+            }
+            (Loc::PhysReg(_), Operand::ImmI64(i)) => format!("mov r10, {i}\nmovq {dst_s}, r10\n"),
+            (Loc::PhysReg(_), _) => format!("movsd {dst_s}, {src_s}\n"),
+            (Loc::Stack(..), Operand::Loc(loc)) if Self::loc_is_fpr(loc) => {
+                format!("movsd {dst_s}, {src_s}\n")
+            }
+            (Loc::Stack(..), _) => format!("movsd xmm15, {src_s}\nmovsd {dst_s}, xmm15\n"),
+        }
     }
 
     fn scratch_at(n: u8, w: RegWidth) -> &'static str {
@@ -444,6 +571,9 @@ impl X86Emitter {
 
     fn mem_ref_sized(&self, addr: &Addr<X86RegGpr>, size: &str) -> String {
         match addr {
+            Addr::BaseOff { base, off } if *off < 0 => {
+                format!("{} [{} + {}]", size, self.target_args.reg64(*base), -off)
+            }
             Addr::BaseOff { base, off } => {
                 format!("{} [{} - {}]", size, self.target_args.reg64(*base), off)
             }
@@ -470,47 +600,95 @@ impl X86Emitter {
         }
     }
 
+    /// Converts `src` of type `from` to `ty`: integers are first widened into
+    /// r10 (zero-extending char and bool, sign-extending int), then truncated
+    /// to the destination or converted to a double.
+    /// Compares two doubles with `ucomisd`, which reports a NaN on either side
+    /// as unordered by setting every flag: `<` and `<=` swap their operands so
+    /// the unsigned `seta`/`setae` come out false, and `==`/`!=` also check
+    /// the parity flag.
+    fn emit_float_cmp(
+        &self,
+        dst: &Loc<X86RegGpr, X86RegFpr>,
+        op: &CmpOp,
+        a: &Operand<X86RegGpr, X86RegFpr>,
+        b: &Operand<X86RegGpr, X86RegFpr>,
+    ) -> String {
+        let (left, right, setcc, parity) = match op {
+            CmpOp::Eq => (a, b, "sete", Some(("setnp", "and"))),
+            CmpOp::Ne => (a, b, "setne", Some(("setp", "or"))),
+            CmpOp::Lt => (b, a, "seta", None),
+            CmpOp::Le => (b, a, "setae", None),
+            CmpOp::Gt => (a, b, "seta", None),
+            CmpOp::Ge => (a, b, "setae", None),
+        };
+        let right = self.t_operand_at(right, RegWidth::W64);
+        let mut out = match left {
+            Operand::Loc(loc) if Self::loc_is_fpr(loc) => {
+                format!("ucomisd {}, {}
+", self.t_loc_at(loc, RegWidth::W64), right)
+            }
+            // The left side must be a register
+            _ => format!(
+                "movsd xmm15, {}\nucomisd xmm15, {}\n",
+                self.t_operand_at(left, RegWidth::W64),
+                right
+            ),
+        };
+        out.push_str(&format!("{setcc} r10b\n"));
+        if let Some((flag, combine)) = parity {
+            out.push_str(&format!("{flag} r11b\n{combine} r10b, r11b\n"));
+        }
+        let dst_w = Self::loc_width(dst);
+        out.push_str(&format!(
+            "movzx r10d, r10b\nmov {}, {}\n",
+            self.t_loc_at(dst, dst_w),
+            Self::scratch_at(10, dst_w)
+        ));
+        out
+    }
+
     fn emit_cast(
         &self,
         dst: &Loc<X86RegGpr, X86RegFpr>,
         src: &Operand<X86RegGpr, X86RegFpr>,
+        from: &Type,
         ty: &Type,
     ) -> String {
-        let dst_w = Self::loc_width(dst);
-        match ty {
-            Type::Long | Type::Pointer(_) => {
-                // Sign-extend to 64-bit: movsxd uses 32-bit source, 64-bit dest
-                format!(
-                    "movsxd {}, {}\n",
-                    self.t_loc_at(dst, RegWidth::W64),
-                    self.t_operand_at(src, RegWidth::W32)
-                )
-            }
-            Type::Bool | Type::Char => {
-                // Zero-extend byte to dst width
-                format!(
-                    "movzx {}, {}\n",
-                    self.t_loc_at(dst, dst_w),
-                    self.t_operand_at(src, RegWidth::W8)
-                )
+        let mut out = String::new();
+        let src_at = |w| match src {
+            Operand::Indirect(addr) => self.mem_ref_sized(addr, Self::width_to_size_prefix(w)),
+            _ => self.t_operand_at(src, w),
+        };
+        match (src, from) {
+            (_, Type::float) => {
+                // Truncates toward zero
+                out.push_str(&format!("cvttsd2si r10, {}\n", src_at(RegWidth::W64)))
             }
-            Type::int => {
-                // Truncate or same-size move at 32-bit
-                format!(
-                    "mov {}, {}\n",
-                    self.t_loc_at(dst, RegWidth::W32),
-                    self.t_operand_at(src, RegWidth::W32)
-                )
-            }
-            _ => {
-                // Default: move at dst width
-                format!(
-                    "mov {}, {}\n",
-                    self.t_loc_at(dst, dst_w),
-                    self.t_operand_at(src, dst_w)
-                )
+            (Operand::ImmI64(i), _) => out.push_str(&format!("mov r10, {i}\n")),
+            (_, Type::Char | Type::Bool) => {
+                out.push_str(&format!("movzx r10d, {}\n", src_at(RegWidth::W8)))
             }
+            (_, Type::int) => out.push_str(&format!("movsxd r10, {}\n", src_at(RegWidth::W32))),
+            _ => out.push_str(&format!("mov r10, {}\n", src_at(RegWidth::W64))),
+        }
+        if *ty != Type::float {
+            let dst_w = Self::loc_width(dst);
+            out.push_str(&format!(
+                "mov {}, {}\n",
+                self.t_loc_at(dst, dst_w),
+                Self::scratch_at(10, dst_w)
+            ));
+        } else if Self::loc_is_fpr(dst) {
+            out.push_str(&format!("cvtsi2sd {}, r10\n", self.t_loc_at(dst, RegWidth::W64)));
+        } else {
+            // No free xmm register: borrow xmm15 and park its value in r11
+            out.push_str(&format!(
+                "movq r11, xmm15\ncvtsi2sd xmm15, r10\nmovsd {}, xmm15\nmovq xmm15, r11\n",
+                self.t_loc_at(dst, RegWidth::W64)
+            ));
         }
+        out
     }
 
     fn emit_load(
@@ -521,24 +699,21 @@ impl X86Emitter {
     ) -> String {
         let size = Self::type_size_suffix(ty);
         let w = Self::loc_width(dst);
-        let rax = self.target_args.reg_by_width(X86RegGpr::RAX, w);
         let mem = self.mem_ref_sized(addr, size);
+        // Arrays and string globals evaluate to their address
+        let is_lea = matches!(ty, Type::Array(_, _))
+            || matches!((ty, addr), (Type::Pointer(_), Addr::Global { .. }));
         let mut ret = match size {
-            "byte" => format!("movzx {}, {}\n", rax, mem),
-            "dword" => format!(
-                "mov {}, {}\n",
-                self.target_args.reg_by_width(X86RegGpr::RAX, RegWidth::W32),
-                mem
-            ),
-            _ => format!("lea {}, {}\n", rax, mem),
+            "byte" => format!("movzx {}, {}\n", Self::scratch_at(10, RegWidth::W32), mem),
+            "dword" => format!("mov {}, {}\n", Self::scratch_at(10, RegWidth::W32), mem),
+            _ if is_lea => format!("lea {}, {}\n", Self::scratch_at(10, RegWidth::W64), mem),
+            _ => format!("mov {}, {}\n", Self::scratch_at(10, RegWidth::W64), mem),
         };
-        if let Loc::PhysReg(rr) = dst
-            && let Some(r) = rr.as_gpr()
-            && *r == X86RegGpr::RAX
-        {
-            return ret;
-        }
-        ret.push_str(&format!("mov {}, {}\n", self.t_loc_at(dst, w), rax));
+        ret.push_str(&format!(
+            "mov {}, {}\n",
+            self.t_loc_at(dst, w),
+            Self::scratch_at(10, w)
+        ));
         ret
     }
 
@@ -559,57 +734,199 @@ impl X86Emitter {
         format!("mov {}, {}\n", mem, self.t_operand_at(src, w))
     }
 
+    // Plain loads already have acquire semantics on x86
+    fn emit_atomic_load(
+        &self,
+        dst: &Loc<X86RegGpr, X86RegFpr>,
+        addr: &Addr<X86RegGpr>,
+        ty: &Type,
+    ) -> String {
+        let w = type_to_reg_width(ty);
+        let mem = self.mem_ref_sized(addr, Self::type_size_suffix(ty));
+        let load = if w == RegWidth::W8 {
+            format!("movzx {}, {}\n", Self::scratch_at(11, RegWidth::W32), mem)
+        } else {
+            format!("mov {}, {}\n", Self::scratch_at(11, w), mem)
+        };
+        format!(
+            "{}mov {}, {}\n",
+            load,
+            self.t_loc_at(dst, w),
+            Self::scratch_at(11, w)
+        )
+    }
+
+    // Plain stores already have release semantics; sequentially consistent
+    // ones use the implicitly locked `xchg` as a full barrier
+    fn emit_atomic_store(
+        &self,
+        src: &Operand<X86RegGpr, X86RegFpr>,
+        addr: &Addr<X86RegGpr>,
+        ty: &Type,
+        order: Option<MemOrder>,
+    ) -> String {
+        let w = type_to_reg_width(ty);
+        let mem = self.mem_ref_sized(addr, Self::type_size_suffix(ty));
+        let tmp = Self::scratch_at(11, w);
+        let store = if order == Some(MemOrder::SeqCst) {
+            format!("xchg {}, {}\n", mem, tmp)
+        } else {
+            format!("mov {}, {}\n", mem, tmp)
+        };
+        format!("mov {}, {}\n{}", tmp, self.t_operand_at(src, w), store)
+    }
+
+    fn emit_atomic_rmw(
+        &self,
+        dst: &Loc<X86RegGpr, X86RegFpr>,
+        op: RmwOp,
+        addr: &Addr<X86RegGpr>,
+        src: &Operand<X86RegGpr, X86RegFpr>,
+        ty: &Type,
+    ) -> String {
+        let w = type_to_reg_width(ty);
+        let mem = self.mem_ref_sized(addr, Self::type_size_suffix(ty));
+        let tmp = Self::scratch_at(11, w);
+        let rmw = match op {
+            RmwOp::Swap => "xchg",
+            RmwOp::Add => "lock xadd",
+        };
+        format!(
+            "mov {}, {}\n{} {}, {}\nmov {}, {}\n",
+            tmp,
+            self.t_operand_at(src, w),
+            rmw,
+            mem,
+            tmp,
+            self.t_loc_at(dst, w),
+            tmp
+        )
+    }
+
+    // rax is reserved around `cmpxchg` by the register allocator
+    fn emit_atomic_cmpxchg(
+        &self,
+        dst: &Loc<X86RegGpr, X86RegFpr>,
+        addr: &Addr<X86RegGpr>,
+        expected: &Operand<X86RegGpr, X86RegFpr>,
+        desired: &Operand<X86RegGpr, X86RegFpr>,
+        ty: &Type,
+    ) -> String {
+        let w = type_to_reg_width(ty);
+        let mem = self.mem_ref_sized(addr, Self::type_size_suffix(ty));
+        let tmp = Self::scratch_at(11, w);
+        let rax = self.target_args.reg_by_width(X86RegGpr::RAX, w);
+        format!(
+            "mov {}, {}\nmov {}, {}\nlock cmpxchg {}, {}\nmov {}, {}\n",
+            tmp,
+            self.t_operand_at(desired, w),
+            rax,
+            self.t_operand_at(expected, w),
+            mem,
+            tmp,
+            self.t_loc_at(dst, w),
+            rax
+        )
+    }
+
+    fn arg_move(
+        &self,
+        reg: &RegType<X86RegGpr, X86RegFpr>,
+        src: &Operand<X86RegGpr, X86RegFpr>,
+    ) -> String {
+        match reg {
+            RegType::FprReg(f) => {
+                format!("movsd {}, {}\n", self.target_args.float128(*f), self.t_operand(src))
+            }
+            RegType::GprReg(r) => {
+                let w = Self::operand_width(src);
+                format!(
+                    "mov {}, {}\n",
+                    self.target_args.reg_by_width(*r, w),
+                    self.t_operand_at(src, w)
+                )
+            }
+        }
+    }
+
     fn emit_call(
         &self,
         dst: &Option<Loc<X86RegGpr, X86RegFpr>>,
+        dst_hi: &Option<Loc<X86RegGpr, X86RegFpr>>,
         func: &CallTarget<X86RegGpr>,
         args: &[Operand<X86RegGpr, X86RegFpr>],
+        sret: &Option<Operand<X86RegGpr, X86RegFpr>>,
     ) -> String {
         let arg_regs = self.target_args.arg_regs();
         let fp_regs = self.target_args.fp_arg_regs();
         let mut gp_args = 0;
         let mut fp_args = 0;
-        let mut out = String::new();
-        for arg in args.iter() {
-            let is_fp = match arg {
-                Operand::Loc(loc) => match loc {
-                    Loc::PhysReg(rr) => rr.is_fpr(),
-                    Loc::Stack(_, _) => false,
-                },
-                Operand::ImmI64(_) => false,
-                Operand::ImmF64(_) => true,
-                _ => false,
-            };
-            if is_fp {
-                out.push_str(&format!(
-                    "movsd {}, {}\n",
-                    self.target_args.float128(fp_regs[fp_args]),
-                    self.t_operand(arg)
-                ));
+        // The hidden return pointer goes first, as the leading integer argument
+        let mut moves = vec![];
+        for arg in sret.iter().chain(args) {
+            if Self::operand_is_float(arg) {
+                moves.push((RegType::FprReg(fp_regs[fp_args]), arg.clone()));
                 fp_args += 1;
             } else {
-                let arg_w = Self::operand_width(arg);
-                out.push_str(&format!(
-                    "mov {}, {}\n",
-                    self.target_args.reg_by_width(arg_regs[gp_args], arg_w),
-                    self.t_operand_at(arg, arg_w)
-                ));
+                moves.push((RegType::GprReg(arg_regs[gp_args]), arg.clone()));
                 gp_args += 1;
             }
         }
+        // `mov eax` below may clobber the callee's register, so it is called through r11
         let target = match func {
             CallTarget::Direct(sym) => format!("__q_f_{}", sym),
-            CallTarget::Indirect(reg) => self.target_args.reg64(*reg).to_string(),
+            CallTarget::Extern(sym) => sym.clone(),
+            CallTarget::Indirect(reg) => {
+                moves.push((
+                    RegType::GprReg(X86RegGpr::R11),
+                    Operand::Loc(Loc::PhysReg(RegRef::gpr(*reg, RegWidth::W64))),
+                ));
+                "r11".to_string()
+            }
         };
+
+        let mut out = String::new();
+        if moves_overlap(&moves) {
+            // Park every register-borne value on the stack before any is overwritten
+            let (parked, direct): (Vec<_>, Vec<_>) =
+                moves.iter().partition(|(_, src)| !src.regs().is_empty());
+            for (_, src) in &parked {
+                out.push_str(&match src {
+                    Operand::Loc(Loc::PhysReg(rr)) if rr.is_fpr() => {
+                        format!("sub rsp, 8\nmovsd qword [rsp], {}\n", self.t_operand(src))
+                    }
+                    _ => format!("push {}\n", self.t_operand_at(src, RegWidth::W64)),
+                });
+            }
+            for (reg, _) in parked.iter().rev() {
+                out.push_str(&match reg {
+                    RegType::GprReg(r) => format!("pop {}\n", self.target_args.reg64(*r)),
+                    RegType::FprReg(f) => format!(
+                        "movsd {}, qword [rsp]\nadd rsp, 8\n",
+                        self.target_args.float128(*f)
+                    ),
+                });
+            }
+            for (reg, src) in direct {
+                out.push_str(&self.arg_move(reg, src));
+            }
+        } else {
+            for (reg, src) in &moves {
+                out.push_str(&self.arg_move(reg, src));
+            }
+        }
         if fp_args > 0 {
             out.push_str(&format!("mov eax, {}\n", fp_args));
         } else {
             out.push_str("xor eax, eax\n");
         }
         out.push_str(&format!("call {}\n", target));
+        if dst_hi.is_some() {
+            out.push_str("mov r11, rdx\n");
+        }
         if let Some(d) = dst {
             let dst_w = Self::loc_width(d);
-            if Self::loc_is_fpr(d) {
+            if Self::loc_is_float(d) {
                 let dst_loc = self.t_loc_at(d, dst_w);
                 if dst_loc != "xmm0" {
                     out.push_str(&format!("\nmovsd {}, xmm0\n", dst_loc));
@@ -619,6 +936,14 @@ impl X86Emitter {
                 out.push_str(&format!("\nmov {}, {}\n", self.t_loc_at(d, dst_w), rax));
             }
         }
+        if let Some(d) = dst_hi {
+            let dst_w = Self::loc_width(d);
+            out.push_str(&format!(
+                "mov {}, {}\n",
+                self.t_loc_at(d, dst_w),
+                Self::scratch_at(11, dst_w)
+            ));
+        }
         out
     }
 }
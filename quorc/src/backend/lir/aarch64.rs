@@ -1,4 +1,4 @@
-use crate::backend::lir::regalloc::TargetRegs;
+use crate::{backend::lir::regalloc::TargetRegs, midend::mir::block::IRInstruction};
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
 pub enum A64RegGpr {
@@ -177,8 +177,6 @@ impl A64RegGpr {
         A64RegGpr::X13,
         A64RegGpr::X14,
         A64RegGpr::X15,
-        A64RegGpr::X16,
-        A64RegGpr::X17,
         A64RegGpr::X19,
         A64RegGpr::X20,
         A64RegGpr::X21,
@@ -256,8 +254,9 @@ impl TargetRegs for A64Regs {
     type Reg = A64RegGpr;
     type FpReg = A64RegFpr;
 
-    const FPR_ALLOCATABLE: usize = 31;
-    const NUM_ALLOCATABLE: usize = 19;
+    // v30 and v31 are left out as float scratch registers
+    const FPR_ALLOCATABLE: usize = 30;
+    const NUM_ALLOCATABLE: usize = 17;
 
     fn all_regs(&self) -> &'static [Self::Reg] {
         A64RegGpr::ALL
@@ -519,4 +518,30 @@ impl TargetRegs for A64Regs {
     fn fp_arg_regs(&self) -> &'static [Self::FpReg] {
         A64RegFpr::ARG_REGS
     }
+
+    // Scalar double view, so templates can write `fadd {0}, {0}, {1}`
+    fn fpr_asm_name(&self, reg: Self::FpReg) -> String {
+        format!("d{}", &self.float128(reg)[1..])
+    }
+
+    fn copy_reg(&self) -> Self::Reg {
+        A64RegGpr::X15
+    }
+
+    // AAPCS64 passes the address of an indirect result in x8
+    fn sret_reg(&self) -> Option<Self::Reg> {
+        Some(A64RegGpr::X8)
+    }
+
+    // Exclusive load/store loops keep the old value, new value, expected
+    // value and store status in x13-x15
+    fn atomic_clobbers(&self, _inst: &IRInstruction) -> Vec<Self::Reg> {
+        vec![
+            A64RegGpr::X13,
+            A64RegGpr::X14,
+            A64RegGpr::X15,
+            A64RegGpr::X16,
+            A64RegGpr::X17,
+        ]
+    }
 }
@@ -3,6 +3,12 @@ pub mod interference;
 pub mod regalloc;
 pub mod x86_64;
 
+use crate::{
+    backend::lir::{aarch64::A64Regs, regalloc::TargetRegs, x86_64::X86Regs},
+    frontend::ast::AsmReg,
+    target::target_arch,
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SymId(pub usize);
 
@@ -36,3 +42,69 @@ impl Symbols {
         &self.vec[id.0]
     }
 }
+
+/// Checks that `name` is a register inline assembly may bind or clobber on
+/// the target architecture, returning whether it is a floating point one.
+pub fn asm_register(name: &str) -> Result<bool, String> {
+    match target_arch() {
+        "x86_64" => register_class(&X86Regs, name),
+        "aarch64" => register_class(&A64Regs, name),
+        arch => Err(format!("Inline assembly is not supported on {arch}")),
+    }
+}
+
+fn register_class<T: TargetRegs>(regs: &T, name: &str) -> Result<bool, String> {
+    if let Some(reg) = regs.gpr_by_name(name) {
+        if reg == regs.sp() || Some(reg) == regs.fp() || Some(reg) == regs.lr() {
+            return Err(format!("Register '{name}' cannot be used by inline assembly"));
+        }
+        Ok(false)
+    } else if regs.fpr_by_name(name).is_some() {
+        Ok(true)
+    } else {
+        Err(format!("Unknown register '{name}' for {}", target_arch()))
+    }
+}
+
+/// Checks that the target has a register left for every `reg` and `freg`
+/// operand of an `asm` statement once its named registers and clobbers are
+/// taken.
+pub fn check_asm_registers(constraints: &[&AsmReg], clobbers: &[String]) -> Result<(), String> {
+    match target_arch() {
+        "x86_64" => X86Regs.bind_asm_regs(constraints, clobbers).map(drop),
+        "aarch64" => A64Regs.bind_asm_regs(constraints, clobbers).map(drop),
+        arch => Err(format!("Inline assembly is not supported on {arch}")),
+    }
+}
+
+/// Substitutes `{N}` in an inline assembly template with the name of the
+/// register bound to operand `N`; `{{` and `}}` stand for literal braces.
+pub fn render_asm_template(template: &str, operands: &[String]) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let index: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let operand = index
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| operands.get(i))
+                    .ok_or_else(|| format!("Invalid asm operand reference '{{{index}}}'"))?;
+                out.push_str(operand);
+            }
+            '}' => return Err("Unmatched '}' in asm template; use '}}'".to_string()),
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
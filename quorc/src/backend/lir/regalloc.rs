@@ -6,12 +6,13 @@ use std::{
 use crate::{
     backend::lir::{
         SymId,
+        render_asm_template,
         interference::{InterferenceGraph, build_interference_graph},
     },
-    frontend::ast::Type,
+    frontend::ast::{AsmReg, Type},
     midend::mir::block::{
-        AtDecl, BlockId, GlobalValue, IRBlock, IRFunction, IRInstruction, IRProgram, Terminator,
-        VReg, VRegType, Value,
+        AtDecl, BlockId, GlobalValue, IRAsmOperand, IRBlock, IRFunction, IRInstruction,
+        IRProgram, MemOrder, RmwOp, Terminator, VReg, VRegType, Value,
     },
     target::target_os,
 };
@@ -105,6 +106,30 @@ pub enum Operand<
     Indirect(Addr<R>), // value at this memory address
 }
 
+impl<R: Copy + Eq + Hash + std::fmt::Debug, F: Copy + Eq + Hash + std::fmt::Debug> Operand<R, F> {
+    /// Registers read to produce this operand.
+    pub fn regs(&self) -> Vec<RegType<R, F>> {
+        match self {
+            Operand::Loc(Loc::PhysReg(rr)) => vec![rr.ty],
+            Operand::Indirect(Addr::BaseOff { base, .. }) => vec![RegType::GprReg(*base)],
+            Operand::Indirect(Addr::BaseIndex { base, index, .. }) => {
+                vec![RegType::GprReg(*base), RegType::GprReg(*index)]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Whether performing `moves` in order would overwrite a register a later move still reads.
+#[allow(clippy::type_complexity)]
+pub fn moves_overlap<R: Copy + Eq + Hash + std::fmt::Debug, F: Copy + Eq + Hash + std::fmt::Debug>(
+    moves: &[(RegType<R, F>, Operand<R, F>)],
+) -> bool {
+    moves.iter().enumerate().any(|(i, (dst, _))| {
+        moves[i + 1..].iter().any(|(_, src)| src.regs().contains(dst))
+    })
+}
+
 impl<R: Copy + Eq + Hash + std::fmt::Debug, F: Copy + Eq + Hash + std::fmt::Debug> From<Loc<R, F>>
     for Operand<R, F>
 {
@@ -134,6 +159,7 @@ pub enum Addr<R: Copy + Eq + std::hash::Hash + std::fmt::Debug> {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CallTarget<R> {
     Direct(String),
+    Extern(String), // a C symbol, called without the `__q_f_` prefix
     Indirect(R),
 }
 
@@ -174,6 +200,7 @@ pub enum LInst<R: Copy + Eq + Hash + std::fmt::Debug, F: Copy + Eq + Hash + std:
     Cast {
         dst: Loc<R, F>,
         src: Operand<R, F>,
+        from: Type,
         ty: Type,
     },
 
@@ -192,8 +219,10 @@ pub enum LInst<R: Copy + Eq + Hash + std::fmt::Debug, F: Copy + Eq + Hash + std:
     // Calls
     Call {
         dst: Option<Loc<R, F>>,
+        dst_hi: Option<Loc<R, F>>,
         func: CallTarget<R>,
         args: Vec<Operand<R, F>>,
+        sret: Option<Operand<R, F>>,
     },
 
     // Move / lea
@@ -209,6 +238,36 @@ pub enum LInst<R: Copy + Eq + Hash + std::fmt::Debug, F: Copy + Eq + Hash + std:
     InlineAsm {
         asm: String,
     },
+
+    // Volatile and atomic memory; never reordered with other memory accesses
+    AtomicLoad {
+        dst: Loc<R, F>,
+        addr: Addr<R>,
+        ty: Type,
+        order: Option<MemOrder>,
+    },
+    AtomicStore {
+        src: Operand<R, F>,
+        addr: Addr<R>,
+        ty: Type,
+        order: Option<MemOrder>,
+    },
+    AtomicRmw {
+        dst: Loc<R, F>,
+        op: RmwOp,
+        addr: Addr<R>,
+        src: Operand<R, F>,
+        ty: Type,
+        order: MemOrder,
+    },
+    AtomicCmpXchg {
+        dst: Loc<R, F>,
+        addr: Addr<R>,
+        expected: Operand<R, F>,
+        desired: Operand<R, F>,
+        ty: Type,
+        order: MemOrder,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -275,6 +334,141 @@ where
     fn fp_caller_saved(&self) -> &'static [Self::FpReg];
     fn fp_callee_saved(&self) -> &'static [Self::FpReg];
 
+    /// The general purpose register spelled `name` at any width.
+    fn gpr_by_name(&self, name: &str) -> Option<Self::Reg> {
+        self.all_regs().iter().copied().find(|&reg| {
+            [RegWidth::W8, RegWidth::W16, RegWidth::W32, RegWidth::W64]
+                .into_iter()
+                .any(|width| self.reg_by_width(reg, width) == name)
+        })
+    }
+
+    /// How a floating point register is spelled in inline assembly.
+    fn fpr_asm_name(&self, reg: Self::FpReg) -> String {
+        self.float128(reg).to_string()
+    }
+
+    fn fpr_by_name(&self, name: &str) -> Option<Self::FpReg> {
+        self.float_regs()
+            .iter()
+            .copied()
+            .find(|&reg| self.float128(reg) == name || self.fpr_asm_name(reg) == name)
+    }
+
+    fn reg_by_name(&self, name: &str) -> Option<RegType<Self::Reg, Self::FpReg>> {
+        self.gpr_by_name(name)
+            .map(RegType::GprReg)
+            .or_else(|| self.fpr_by_name(name).map(RegType::FprReg))
+    }
+
+    /// Registers bound to the operands of an `asm` statement, which the
+    /// analyzer has already checked with [`TargetRegs::bind_asm_regs`].
+    fn asm_operand_regs(
+        &self,
+        operands: &[IRAsmOperand],
+        clobbers: &[String],
+    ) -> Vec<RegType<Self::Reg, Self::FpReg>> {
+        let constraints: Vec<_> = operands.iter().map(|op| &op.reg).collect();
+        self.bind_asm_regs(&constraints, clobbers)
+            .expect("asm operands are checked against the free registers by the analyzer")
+    }
+
+    /// Registers bound to the operand `constraints` of an `asm` statement.
+    /// `reg` and `freg` operands take the first free allocatable register that
+    /// is neither a scratch register, clobbered, nor named by another operand.
+    #[allow(clippy::type_complexity)]
+    fn bind_asm_regs(
+        &self,
+        constraints: &[&AsmReg],
+        clobbers: &[String],
+    ) -> Result<Vec<RegType<Self::Reg, Self::FpReg>>, String> {
+        let mut taken: Vec<_> = clobbers
+            .iter()
+            .chain(constraints.iter().filter_map(|reg| match reg {
+                AsmReg::Named(name) => Some(name),
+                _ => None,
+            }))
+            .filter_map(|name| self.reg_by_name(name))
+            .collect();
+
+        let gprs: Vec<_> = self
+            .allocatable_regs()
+            .iter()
+            .filter(|r| !self.scratch_regs().contains(r))
+            .map(|&r| RegType::GprReg(r))
+            .filter(|r| !taken.contains(r))
+            .collect();
+        let fprs: Vec<_> = self.float_regs()[..Self::FPR_ALLOCATABLE]
+            .iter()
+            .map(|&f| RegType::FprReg(f))
+            .filter(|f| !taken.contains(f))
+            .collect();
+        for (class, free) in [(AsmReg::Any, &gprs), (AsmReg::Float, &fprs)] {
+            let wanted = constraints.iter().filter(|&&reg| *reg == class).count();
+            if wanted > free.len() {
+                let name = if class == AsmReg::Any { "reg" } else { "freg" };
+                return Err(format!(
+                    "asm needs {wanted} '{name}' operands, but only {} such registers are free \
+                     besides the named registers and clobbers",
+                    free.len()
+                ));
+            }
+        }
+
+        Ok(constraints
+            .iter()
+            .map(|reg| {
+                let reg = match reg {
+                    AsmReg::Named(name) => self.reg_by_name(name).expect("unknown asm register"),
+                    AsmReg::Any => *gprs.iter().find(|r| !taken.contains(r)).unwrap(),
+                    AsmReg::Float => *fprs.iter().find(|f| !taken.contains(f)).unwrap(),
+                };
+                taken.push(reg);
+                reg
+            })
+            .collect())
+    }
+
+    /// Registers the emitter uses while performing an atomic access.
+    fn atomic_clobbers(&self, _inst: &IRInstruction) -> Vec<Self::Reg> {
+        self.scratch_regs().to_vec()
+    }
+
+    /// Register a `Memcpy` moves each chunk through. The scratch registers
+    /// are taken by spilled addresses, so this must be an allocatable one.
+    fn copy_reg(&self) -> Self::Reg;
+
+    /// Registers an integer division or remainder overwrites besides its result.
+    fn div_clobbers(&self) -> &'static [Self::Reg] {
+        &[]
+    }
+
+    /// Register that carries the hidden pointer of a struct returned in memory,
+    /// when the ABI sets one apart; otherwise it is passed as the first argument.
+    fn sret_reg(&self) -> Option<Self::Reg> {
+        None
+    }
+
+    /// The register each parameter of `func` arrives in.
+    #[allow(clippy::type_complexity)]
+    fn param_regs(&self, func: &IRFunction) -> Vec<(VReg, RegRef<Self::Reg, Self::FpReg>)> {
+        let mut gp_args = self.arg_regs().iter();
+        let mut fp_args = self.fp_arg_regs().iter();
+        let mut regs = Vec::new();
+        if let Some(sret) = func.sret {
+            let reg = self.sret_reg().unwrap_or_else(|| *gp_args.next().unwrap());
+            regs.push((sret, RegRef::gpr(reg, RegWidth::W64)));
+        }
+        for param in &func.params {
+            let rr = match param.ty {
+                VRegType::Int => RegRef::gpr(*gp_args.next().unwrap(), param.width),
+                VRegType::Float => RegRef::fpr(*fp_args.next().unwrap(), RegWidth::W128),
+            };
+            regs.push((*param, rr));
+        }
+        regs
+    }
+
     fn regalloc(&self, func: &IRFunction) -> Allocation<Self::Reg, Self::FpReg> {
         let mut vreg_loc: HashMap<
             VReg,
@@ -283,33 +477,156 @@ where
         let mut used_callee_saved = Vec::new();
         let mut used_callee_saved_fp = Vec::new();
 
-        let gp_args = self.arg_regs();
-        let fp_args = self.fp_arg_regs();
+        let param_regs = self.param_regs(func);
+        for (param, rr) in &param_regs {
+            vreg_loc.insert(*param, Loc::PhysReg(*rr));
+        }
 
-        let (int_params, float_params): (Vec<_>, Vec<_>) = func
-            .params
+        let flattened_blocks = flatten_blocks(func.blocks.clone());
+        let asm_sites: Vec<_> = flattened_blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, inst)| match inst {
+                LifetimeInstr::IRInstruction(IRInstruction::InlineAsm {
+                    operands,
+                    clobbers,
+                    ..
+                }) => {
+                    let mut reserved = self.asm_operand_regs(operands, clobbers);
+                    reserved.extend(clobbers.iter().filter_map(|name| self.reg_by_name(name)));
+                    Some((idx, reserved))
+                }
+                LifetimeInstr::IRInstruction(
+                    inst @ (IRInstruction::AtomicLoad { .. }
+                    | IRInstruction::AtomicStore { .. }
+                    | IRInstruction::AtomicRmw { .. }
+                    | IRInstruction::AtomicCmpXchg { .. }),
+                ) => Some((
+                    idx,
+                    self.atomic_clobbers(inst)
+                        .into_iter()
+                        .map(RegType::GprReg)
+                        .collect(),
+                )),
+                LifetimeInstr::IRInstruction(IRInstruction::Memcpy { .. }) => {
+                    Some((idx, vec![RegType::GprReg(self.copy_reg())]))
+                }
+                LifetimeInstr::IRInstruction(
+                    IRInstruction::Div { reg, .. } | IRInstruction::Mod { reg, .. },
+                ) if reg.is_gpr() => Some((
+                    idx,
+                    self.div_clobbers().iter().map(|&r| RegType::GprReg(r)).collect(),
+                )),
+                // Casts widen their source through the scratch registers
+                LifetimeInstr::IRInstruction(IRInstruction::Cast { .. }) => Some((
+                    idx,
+                    self.scratch_regs().iter().map(|&r| RegType::GprReg(r)).collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+        let call_sites: Vec<_> = flattened_blocks
             .iter()
-            .partition(|p| matches!(p.ty, VRegType::Int));
-
-        // Assign integer params
-        for i in 0..int_params.len() {
-            let param: &VReg = int_params[i];
-            let width = param.width;
-            let reg = gp_args[i];
-            let rr: RegRef<Self::Reg, Self::FpReg> = RegRef::gpr(reg, width);
-            vreg_loc.insert(*param, Loc::PhysReg(rr));
+            .enumerate()
+            .filter_map(|(idx, inst)| match inst {
+                LifetimeInstr::IRInstruction(call @ IRInstruction::Call { .. }) => {
+                    Some((idx, call.defs()))
+                }
+                _ => None,
+            })
+            .collect();
+        let mut live_ranges = assign_live_ranges(flattened_blocks);
+        extend_over_blocks(&func.blocks, &mut live_ranges);
+        // Parameters hold their value from entry, not from their first use
+        for (param, _) in &param_regs {
+            if let Some(range) = live_ranges.get_mut(param) {
+                range.start = 0;
+            }
         }
 
-        // Assign float params
-        for i in 0..float_params.len() {
-            let param: &VReg = float_params[i];
-            let reg = fp_args[i];
-            let rr: RegRef<Self::Reg, Self::FpReg> = RegRef::fpr(reg, RegWidth::W128);
-            vreg_loc.insert(*param, Loc::PhysReg(rr));
+        // Nothing live across an `asm` statement, atomic access, division, block copy or cast
+        // may sit in a register it binds or clobbers
+        let mut forbidden: HashMap<VReg, HashSet<usize>> = HashMap::new();
+        for (idx, reserved) in &asm_sites {
+            for range in live_ranges
+                .values()
+                .filter(|range| range.start <= *idx && *idx <= range.end)
+            {
+                let colors = forbidden.entry(range.vreg).or_default();
+                for reg in reserved {
+                    let color = match reg {
+                        RegType::GprReg(r) if range.vreg.is_gpr() => {
+                            self.allocatable_regs().iter().position(|a| a == r)
+                        }
+                        RegType::FprReg(f) if range.vreg.is_fpr() => {
+                            self.float_regs().iter().position(|a| a == f)
+                        }
+                        _ => None,
+                    };
+                    colors.extend(color);
+                }
+            }
+            for reg in reserved {
+                match reg {
+                    RegType::GprReg(r)
+                        if self.is_callee_saved(r) && !used_callee_saved.contains(r) =>
+                    {
+                        used_callee_saved.push(*r)
+                    }
+                    RegType::FprReg(f)
+                        if self.fp_is_callee_saved(*f) && !used_callee_saved_fp.contains(f) =>
+                    {
+                        used_callee_saved_fp.push(*f)
+                    }
+                    _ => {}
+                }
+            }
         }
 
-        let flattened_blocks = flatten_blocks(func.blocks.clone());
-        let live_ranges = assign_live_ranges(flattened_blocks);
+        // A call clobbers every register its callee need not preserve. A range
+        // starting at the call still crosses it unless the call defines it,
+        // as values live into a block start at its first instruction
+        for (idx, defs) in &call_sites {
+            for range in live_ranges.values().filter(|range| {
+                (range.start < *idx || (range.start == *idx && !defs.contains(&range.vreg)))
+                    && *idx < range.end
+            }) {
+                let colors = forbidden.entry(range.vreg).or_default();
+                if range.vreg.is_gpr() {
+                    colors.extend(
+                        (self.allocatable_regs().iter().enumerate())
+                            .filter(|(_, r)| !self.is_callee_saved(r))
+                            .map(|(color, _)| color),
+                    );
+                } else {
+                    colors.extend(
+                        (self.float_regs().iter().enumerate())
+                            .filter(|(_, r)| !self.fp_is_callee_saved(**r))
+                            .map(|(color, _)| color),
+                    );
+                }
+            }
+        }
+
+        // Parameters are moved out of their argument registers one after the
+        // other on entry, so none may land in another parameter's register
+        for (param, _) in &param_regs {
+            let colors = forbidden.entry(*param).or_default();
+            for (other, rr) in &param_regs {
+                if other == param {
+                    continue;
+                }
+                match rr.ty {
+                    RegType::GprReg(r) if param.is_gpr() => {
+                        colors.extend(self.allocatable_regs().iter().position(|a| *a == r))
+                    }
+                    RegType::FprReg(r) if param.is_fpr() => {
+                        colors.extend(self.float_regs().iter().position(|a| *a == r))
+                    }
+                    _ => {}
+                }
+            }
+        }
 
         let (gpr_ranges, fpr_ranges): (HashMap<_, _>, HashMap<_, _>) = live_ranges
             .into_iter()
@@ -321,13 +638,18 @@ where
         let gpr_stack = self.simplify_graph(&gpr_graph, Self::NUM_ALLOCATABLE);
         let fpr_stack = self.simplify_graph(&fpr_graph, Self::FPR_ALLOCATABLE);
 
-        let (gpr_alloc, offset) = self.color_graph_gpr(gpr_stack, &gpr_graph, func.offset);
-        let (fpr_alloc, offset) = self.color_graph_fpr(fpr_stack, &fpr_graph, offset);
+        let (local_loc, global_loc, locals_size) = collect_local_and_global_ids(func);
+
+        // Spill slots go below the locals
+        let (gpr_alloc, offset) =
+            self.color_graph_gpr(gpr_stack, &gpr_graph, &forbidden, locals_size + 8);
+        let (fpr_alloc, offset) = self.color_graph_fpr(fpr_stack, &fpr_graph, &forbidden, offset);
 
         gpr_alloc.iter().for_each(|(k, v)| {
             if let Loc::PhysReg(rr) = v
                 && let Some(r) = rr.as_gpr()
                 && self.is_callee_saved(r)
+                && !used_callee_saved.contains(r)
             {
                 used_callee_saved.push(*r);
             }
@@ -339,14 +661,13 @@ where
             if let Loc::PhysReg(rr) = v
                 && let Some(r) = rr.as_fpr()
                 && self.fp_is_callee_saved(*r)
+                && !used_callee_saved_fp.contains(r)
             {
                 used_callee_saved_fp.push(*r);
             }
             vreg_loc.insert(*k, v.clone());
         });
 
-        let (local_loc, global_loc) = collect_local_and_global_ids(func);
-
         Allocation {
             vreg_loc,
             used_callee_saved,
@@ -392,6 +713,7 @@ where
         &self,
         stack: Vec<VReg>,
         graph: &InterferenceGraph,
+        forbidden: &HashMap<VReg, HashSet<usize>>,
         current_stack_offset: i32,
     ) -> (HashMap<VReg, Loc<Self::Reg, Self::FpReg>>, i32) {
         let mut stack_offset = current_stack_offset;
@@ -400,7 +722,7 @@ where
         for node in stack.into_iter().rev() {
             let neighbors = graph.neighbors(&node);
 
-            let neighbor_colors: HashSet<usize> = neighbors
+            let mut neighbor_colors: HashSet<usize> = neighbors
                 .iter()
                 .filter_map(|n| {
                     if let Some(Loc::PhysReg(rr)) = allocation.get(n) {
@@ -411,6 +733,7 @@ where
                     }
                 })
                 .collect();
+            neighbor_colors.extend(forbidden.get(&node).into_iter().flatten());
 
             if let Some(color) = (0..Self::NUM_ALLOCATABLE).find(|c| !neighbor_colors.contains(c)) {
                 let phys_reg = self.allocatable_regs()[color];
@@ -429,6 +752,7 @@ where
         &self,
         stack: Vec<VReg>,
         graph: &InterferenceGraph,
+        forbidden: &HashMap<VReg, HashSet<usize>>,
         current_stack_offset: i32,
     ) -> (HashMap<VReg, Loc<Self::Reg, Self::FpReg>>, i32) {
         let mut stack_offset = current_stack_offset;
@@ -437,7 +761,7 @@ where
         for node in stack.into_iter().rev() {
             let neighbors = graph.neighbors(&node);
 
-            let neighbor_colors: HashSet<usize> = neighbors
+            let mut neighbor_colors: HashSet<usize> = neighbors
                 .iter()
                 .filter_map(|n| {
                     if let Some(Loc::PhysReg(rr)) = allocation.get(n) {
@@ -448,12 +772,14 @@ where
                     }
                 })
                 .collect();
+            neighbor_colors.extend(forbidden.get(&node).into_iter().flatten());
 
             if let Some(color) = (0..Self::FPR_ALLOCATABLE).find(|c| !neighbor_colors.contains(c)) {
                 let phys_reg = self.float_regs()[color];
                 allocation.insert(node, Loc::PhysReg(RegRef::fpr(phys_reg, RegWidth::W128)));
             } else {
-                allocation.insert(node, Loc::Stack(stack_offset, node.width));
+                // W128 tells the emitters the slot holds a double
+                allocation.insert(node, Loc::Stack(stack_offset, RegWidth::W128));
                 stack_offset += 8;
             }
         }
@@ -488,34 +814,16 @@ where
         let name = func.name.clone();
         let allocation = self.regalloc(func);
 
-        let gp_args = self.arg_regs();
-        let fp_args = self.fp_arg_regs();
         let mut param_moves: Vec<LInst<Self::Reg, Self::FpReg>> = Vec::new();
-        let mut gp_idx = 0;
-        let mut fp_idx = 0;
-
-        for param in &func.params {
-            if let Some(dst_loc) = allocation.vreg_loc.get(param) {
-                let is_fp = matches!(param.ty, VRegType::Float);
-                if is_fp {
-                    let arg_reg = Loc::PhysReg(RegRef::fpr(fp_args[fp_idx], RegWidth::W128));
-                    if *dst_loc != arg_reg {
-                        param_moves.push(LInst::Mov {
-                            dst: dst_loc.clone(),
-                            src: Operand::Loc(arg_reg),
-                        });
-                    }
-                    fp_idx += 1;
-                } else {
-                    let arg_reg = Loc::PhysReg(RegRef::gpr(gp_args[gp_idx], param.width));
-                    if *dst_loc != arg_reg {
-                        param_moves.push(LInst::Mov {
-                            dst: dst_loc.clone(),
-                            src: Operand::Loc(arg_reg),
-                        });
-                    }
-                    gp_idx += 1;
-                }
+        for (param, rr) in self.param_regs(func) {
+            let arg_reg = Loc::PhysReg(rr);
+            if let Some(dst_loc) = allocation.vreg_loc.get(&param)
+                && *dst_loc != arg_reg
+            {
+                param_moves.push(LInst::Mov {
+                    dst: dst_loc.clone(),
+                    src: Operand::Loc(arg_reg),
+                });
             }
         }
 
@@ -524,10 +832,13 @@ where
             .iter()
             .filter_map(|block| {
                 let term = match &block.terminator {
-                    Terminator::Return { value } => Some(LTerm::Ret {
+                    Terminator::Return { value, value_hi } => Some(LTerm::Ret {
                         value: value
                             .as_ref()
                             .map(|v| self.value_to_operand(v, &allocation)),
+                        value_hi: value_hi
+                            .as_ref()
+                            .map(|v| self.value_to_operand(v, &allocation)),
                     }),
                     Terminator::Jump { block: target } => Some(LTerm::Jump { target: *target }),
                     Terminator::Branch {
@@ -564,10 +875,15 @@ where
             .collect();
 
         let mut has_frame = true;
+        let mut export = None;
 
         for a in &func.attributes {
-            if let AtDecl::NoFrame = a {
-                has_frame = false;
+            match a {
+                AtDecl::NoFrame => has_frame = false,
+                AtDecl::Export { symbol } => {
+                    export = Some(symbol.clone().unwrap_or_else(|| name.clone()))
+                }
+                _ => {}
             }
         }
 
@@ -576,10 +892,14 @@ where
             blocks,
             entry: func.entry,
             has_frame,
-            size: func.offset,
+            size: allocation.stack_size,
+            saved: allocation.used_callee_saved,
+            saved_fp: allocation.used_callee_saved_fp,
+            export,
         }
     }
 
+    #[allow(clippy::type_complexity)]
     fn value_to_addr(
         &self,
         val: &Value,
@@ -597,7 +917,7 @@ where
                         vec![],
                         Addr::BaseOff {
                             base: *rr.as_gpr().unwrap(),
-                            off: offset,
+                            off: -offset,
                         },
                     ),
                     Loc::Stack(stack_off, _) => {
@@ -615,7 +935,7 @@ where
                             setup,
                             Addr::BaseOff {
                                 base: scratch,
-                                off: offset,
+                                off: -offset,
                             },
                         )
                     }
@@ -628,7 +948,7 @@ where
                     .get(id)
                     .copied()
                     .unwrap_or(0)
-                    .saturating_add(offset);
+                    .saturating_sub(offset);
                 let fp = self.fp().expect("fp required for Local address");
                 (vec![], Addr::BaseOff { base: fp, off })
             }
@@ -708,9 +1028,10 @@ where
                 a: self.value_to_operand(left, allocation),
                 b: self.value_to_operand(right, allocation),
             }],
-            IRInstruction::Cast { reg, src, ty } => vec![LInst::Cast {
+            IRInstruction::Cast { reg, src, from, ty } => vec![LInst::Cast {
                 dst: allocation.vreg_loc[reg].clone(),
                 src: self.value_to_operand(src, allocation),
+                from: from.clone(),
                 ty: ty.clone(),
             }],
             IRInstruction::Load {
@@ -783,7 +1104,10 @@ where
                                 None
                             }
                         });
-                        if let (Some(base_reg), Some(index_reg)) = (base_gpr, index_gpr) {
+                        // Both targets fold a scale of 1, 2, 4 or 8 into the address
+                        if let (Some(base_reg), Some(index_reg), 1 | 2 | 4 | 8) =
+                            (base_gpr, index_gpr, scale)
+                        {
                             vec![LInst::Lea {
                                 dst: allocation.vreg_loc[dest].clone(),
                                 addr: Addr::BaseIndex {
@@ -795,19 +1119,23 @@ where
                             }]
                         } else {
                             let (mut setup, addr) = self.value_to_addr(base, 0, allocation);
-                            let base_scratch = self.scratch_regs()[0];
-                            let index_operand = self.value_to_operand(index, allocation);
+                            let base_scratch =
+                                Loc::PhysReg(RegRef::gpr(self.scratch_regs()[0], RegWidth::W64));
+                            let index_scratch =
+                                Loc::PhysReg(RegRef::gpr(self.scratch_regs()[1], RegWidth::W64));
                             setup.push(LInst::Lea {
-                                dst: Loc::PhysReg(RegRef::gpr(base_scratch, RegWidth::W64)),
+                                dst: base_scratch.clone(),
                                 addr,
                             });
+                            setup.push(LInst::Mul {
+                                dst: index_scratch.clone(),
+                                a: self.value_to_operand(index, allocation),
+                                b: Operand::ImmI64(*scale as i64),
+                            });
                             setup.push(LInst::Add {
                                 dst: allocation.vreg_loc[dest].clone(),
-                                a: Operand::Loc(Loc::PhysReg(RegRef::gpr(
-                                    base_scratch,
-                                    RegWidth::W64,
-                                ))),
-                                b: index_operand,
+                                a: Operand::Loc(base_scratch),
+                                b: Operand::Loc(index_scratch),
                             });
                             setup
                         }
@@ -822,29 +1150,51 @@ where
                     }
                 }
             }
-            IRInstruction::Call { reg, func, args } => {
+            IRInstruction::Call {
+                reg,
+                reg_hi,
+                func,
+                args,
+                sret,
+            } => {
                 let mut instrs = vec![];
-                let mir_func = mir_prgrm.functions.get(func).unwrap();
-                let mut is_variadic = false;
-                for attribute in mir_func.attributes.clone() {
-                    if let AtDecl::Variadic = attribute {
-                        is_variadic = true;
-                    }
-                }
+                let sret = sret.as_ref().map(|s| self.value_to_operand(s, allocation));
+                // C functions declared with `@extern def` are called by their plain symbol.
+                // The analyzer rejects calls to anything undeclared, so the last arm only
+                // leaves the symbol for the linker to resolve.
+                let (fixed_params, is_variadic, target) = match (
+                    mir_prgrm.functions.get(func),
+                    mir_prgrm.extern_fns.get(func),
+                ) {
+                    (Some(mir_func), _) => (
+                        mir_func.params.len(),
+                        mir_func
+                            .attributes
+                            .iter()
+                            .any(|attribute| matches!(attribute, AtDecl::Variadic)),
+                        CallTarget::Direct(func.clone()),
+                    ),
+                    (None, Some(ext)) => (
+                        ext.params.len(),
+                        ext.variadic,
+                        CallTarget::Extern(func.clone()),
+                    ),
+                    (None, None) => (args.len(), false, CallTarget::Extern(func.clone())),
+                };
 
                 // println!("{args:?}");
 
                 let mut passed_args = vec![];
                 if is_variadic && target_os() == "macos" {
                     // Non-variadic args passed in registers as normal
-                    for arg in args.iter().take(mir_func.params.len()) {
+                    for arg in args.iter().take(fixed_params) {
                         passed_args.push(self.value_to_operand(arg, allocation));
                     }
 
                     // Variadic args must be passed on the stack per macOS ARM64 ABI
                     let variadic_args: Vec<Operand<Self::Reg, Self::FpReg>> = args
                         .iter()
-                        .skip(mir_func.params.len())
+                        .skip(fixed_params)
                         .map(|a| self.value_to_operand(a, allocation))
                         .collect();
                     let num_variadic = variadic_args.len();
@@ -901,8 +1251,8 @@ where
                                         let base_reg = self.reg64(*base);
                                         instrs.push(LInst::InlineAsm {
                                             asm: format!(
-                                                "ldur x16, [{}, #-{}]\nstr x16, [sp, #{}]",
-                                                base_reg, off, offset
+                                                "ldur x16, [{}, #{}]\nstr x16, [sp, #{}]",
+                                                base_reg, -off, offset
                                             ),
                                         });
                                     }
@@ -918,8 +1268,10 @@ where
 
                     instrs.push(LInst::Call {
                         dst: reg.map(|r| allocation.vreg_loc[&r].clone()),
-                        func: CallTarget::Direct(func.clone()),
+                        dst_hi: reg_hi.map(|r| allocation.vreg_loc[&r].clone()),
+                        func: target,
                         args: passed_args, // only non-variadic args
+                        sret,
                     });
 
                     if num_variadic > 0 {
@@ -934,11 +1286,13 @@ where
 
                 instrs.push(LInst::Call {
                     dst: reg.map(|r| allocation.vreg_loc[&r].clone()),
-                    func: CallTarget::Direct(func.to_string()),
+                    dst_hi: reg_hi.map(|r| allocation.vreg_loc[&r].clone()),
+                    func: target,
                     args: args
                         .iter()
                         .map(|a| self.value_to_operand(a, allocation))
                         .collect(),
+                    sret,
                 });
                 instrs
             }
@@ -955,33 +1309,142 @@ where
                 setup
             }
             IRInstruction::Memcpy { dst, src, size, .. } => {
-                let (mut setup, addr_dst) = self.value_to_addr(dst, 0, allocation);
-                let (setup_src, addr_src) = self.value_to_addr(src, 0, allocation);
-                setup.extend(setup_src);
-
-                let [scratch0, scratch1] = [
-                    self.scratch_regs()[0],
-                    self.scratch_regs()
-                        .get(1)
-                        .copied()
-                        .unwrap_or_else(|| self.scratch_regs()[0]),
-                ];
-                setup.push(LInst::Lea {
-                    dst: Loc::PhysReg(RegRef::gpr(scratch0, RegWidth::W64)),
-                    addr: addr_dst,
+                // Widest chunks first, each loaded and stored through the copy register
+                let mut insts = Vec::new();
+                let mut done = 0;
+                for (width, ty, reg_width) in [
+                    (8, Type::Long, RegWidth::W64),
+                    (4, Type::int, RegWidth::W32),
+                    (1, Type::Char, RegWidth::W8),
+                ] {
+                    let reg = Loc::PhysReg(RegRef::gpr(self.copy_reg(), reg_width));
+                    while size - done >= width {
+                        let (setup, addr) = self.value_to_addr(src, done as i32, allocation);
+                        insts.extend(setup);
+                        insts.push(LInst::Load {
+                            dst: reg.clone(),
+                            addr,
+                            ty: ty.clone(),
+                        });
+                        let (setup, addr) = self.value_to_addr(dst, done as i32, allocation);
+                        insts.extend(setup);
+                        insts.push(LInst::Store {
+                            src: Operand::Loc(reg.clone()),
+                            addr,
+                            ty: ty.clone(),
+                        });
+                        done += width;
+                    }
+                }
+                insts
+            }
+            IRInstruction::InlineAsm {
+                template,
+                operands,
+                clobbers,
+            } => {
+                let regs = self.asm_operand_regs(operands, clobbers);
+                let mut insts = Vec::new();
+                let mut names = Vec::new();
+                let mut outputs = Vec::new();
+
+                for (op, reg) in operands.iter().zip(regs) {
+                    let (loc, name) = match reg {
+                        RegType::GprReg(r) => (
+                            Loc::PhysReg(RegRef::gpr(r, op.width)),
+                            self.reg_by_width(r, op.width).to_string(),
+                        ),
+                        RegType::FprReg(f) => (
+                            Loc::PhysReg(RegRef::fpr(f, RegWidth::W128)),
+                            self.fpr_asm_name(f),
+                        ),
+                    };
+                    if let Some(input) = &op.input {
+                        insts.push(LInst::Mov {
+                            dst: loc.clone(),
+                            src: self.value_to_operand(input, allocation),
+                        });
+                    }
+                    if let Some(output) = &op.output {
+                        outputs.push(LInst::Mov {
+                            dst: allocation.vreg_loc[output].clone(),
+                            src: Operand::Loc(loc),
+                        });
+                    }
+                    names.push(name);
+                }
+
+                insts.push(LInst::InlineAsm {
+                    asm: render_asm_template(template, &names).expect("asm template was checked"),
                 });
-                setup.push(LInst::Lea {
-                    dst: Loc::PhysReg(RegRef::gpr(scratch1, RegWidth::W64)),
-                    addr: addr_src,
+                insts.extend(outputs);
+                insts
+            }
+            IRInstruction::AtomicLoad {
+                reg,
+                addr,
+                ty,
+                order,
+            } => {
+                let (mut setup, addr) = self.value_to_addr(addr, 0, allocation);
+                setup.push(LInst::AtomicLoad {
+                    dst: allocation.vreg_loc[reg].clone(),
+                    addr,
+                    ty: ty.clone(),
+                    order: *order,
                 });
-                setup.push(LInst::Call {
-                    dst: None,
-                    func: CallTarget::Direct("memcpy".to_owned()),
-                    args: vec![
-                        Operand::Loc(Loc::PhysReg(RegRef::gpr(scratch0, RegWidth::W64))),
-                        Operand::Loc(Loc::PhysReg(RegRef::gpr(scratch1, RegWidth::W64))),
-                        Operand::ImmI64(*size as i64),
-                    ],
+                setup
+            }
+            IRInstruction::AtomicStore {
+                value,
+                addr,
+                ty,
+                order,
+            } => {
+                let (mut setup, addr) = self.value_to_addr(addr, 0, allocation);
+                setup.push(LInst::AtomicStore {
+                    src: self.value_to_operand(value, allocation),
+                    addr,
+                    ty: ty.clone(),
+                    order: *order,
+                });
+                setup
+            }
+            IRInstruction::AtomicRmw {
+                reg,
+                op,
+                addr,
+                value,
+                ty,
+                order,
+            } => {
+                let (mut setup, addr) = self.value_to_addr(addr, 0, allocation);
+                setup.push(LInst::AtomicRmw {
+                    dst: allocation.vreg_loc[reg].clone(),
+                    op: *op,
+                    addr,
+                    src: self.value_to_operand(value, allocation),
+                    ty: ty.clone(),
+                    order: *order,
+                });
+                setup
+            }
+            IRInstruction::AtomicCmpXchg {
+                reg,
+                addr,
+                expected,
+                desired,
+                ty,
+                order,
+            } => {
+                let (mut setup, addr) = self.value_to_addr(addr, 0, allocation);
+                setup.push(LInst::AtomicCmpXchg {
+                    dst: allocation.vreg_loc[reg].clone(),
+                    addr,
+                    expected: self.value_to_operand(expected, allocation),
+                    desired: self.value_to_operand(desired, allocation),
+                    ty: ty.clone(),
+                    order: *order,
                 });
                 setup
             }
@@ -1048,6 +1511,10 @@ pub struct LFunction<
     pub entry: BlockId,
     pub has_frame: bool,
     pub size: i32,
+    // Callee-saved registers the body overwrites, preserved below the frame
+    pub saved: Vec<R>,
+    pub saved_fp: Vec<F>,
+    pub export: Option<String>, // C symbol of an `@export` function
 }
 
 #[derive(Debug, Clone)]
@@ -1067,6 +1534,7 @@ pub enum LTerm<
 > {
     Ret {
         value: Option<Operand<R, F>>,
+        value_hi: Option<Operand<R, F>>,
     },
     Jump {
         target: BlockId,
@@ -1101,7 +1569,12 @@ pub struct LGlobalDef {
     pub value: GlobalValue,
 }
 
-fn collect_local_and_global_ids(func: &IRFunction) -> (HashMap<usize, i32>, HashMap<usize, SymId>) {
+/// Lays out the function's stack locals below the frame pointer and returns
+/// `(local offsets, global symbols, bytes used by locals)`. A local at offset
+/// `off` occupies `[fp - off, fp - off + size)`.
+fn collect_local_and_global_ids(
+    func: &IRFunction,
+) -> (HashMap<usize, i32>, HashMap<usize, SymId>, i32) {
     let mut local_ids: HashSet<usize> = HashSet::new();
     let mut global_ids: HashSet<usize> = HashSet::new();
 
@@ -1115,15 +1588,20 @@ fn collect_local_and_global_ids(func: &IRFunction) -> (HashMap<usize, i32>, Hash
     let mut local_loc: HashMap<usize, i32> = HashMap::new();
     let mut sorted_locals: Vec<_> = local_ids.into_iter().collect();
     sorted_locals.sort_unstable();
-    for (i, &id) in sorted_locals.iter().enumerate() {
-        // local_loc.insert(id, func.offset + (i as i32) * 8);
-        local_loc.insert(id, (i as i32) * 8 + 8);
+    let mut locals_size = 0;
+    for &id in sorted_locals.iter() {
+        let (size, align) = func.local_layouts.get(&id).copied().unwrap_or((8, 8));
+        // The frame pointer is 16-byte aligned, so an offset that is a multiple
+        // of the alignment gives an aligned address; the analyzer rejects more
+        let end = locals_size as usize + size.max(1);
+        locals_size = end.next_multiple_of(align.clamp(8, 16)) as i32;
+        local_loc.insert(id, locals_size);
     }
 
     let global_loc: HashMap<usize, SymId> =
         global_ids.into_iter().map(|id| (id, SymId(id))).collect();
 
-    (local_loc, global_loc)
+    (local_loc, global_loc, locals_size)
 }
 
 fn collect_value_ids(
@@ -1155,8 +1633,8 @@ fn collect_value_ids(
             collect_val(base, locals, globals);
             collect_val(index, locals, globals);
         }
-        IRInstruction::Call { args, .. } => {
-            for arg in args {
+        IRInstruction::Call { args, sret, .. } => {
+            for arg in args.iter().chain(sret) {
                 collect_val(arg, locals, globals);
             }
         }
@@ -1167,6 +1645,15 @@ fn collect_value_ids(
             collect_val(dst, locals, globals);
             collect_val(src, locals, globals);
         }
+        IRInstruction::InlineAsm { .. }
+        | IRInstruction::AtomicLoad { .. }
+        | IRInstruction::AtomicStore { .. }
+        | IRInstruction::AtomicRmw { .. }
+        | IRInstruction::AtomicCmpXchg { .. } => {
+            for value in inst.uses() {
+                collect_val(value, locals, globals);
+            }
+        }
         IRInstruction::Add { left, right, .. }
         | IRInstruction::Sub { left, right, .. }
         | IRInstruction::Mul { left, right, .. }
@@ -1203,9 +1690,12 @@ fn collect_terminator_value_ids(
     }
 
     match term {
-        Terminator::Return { value: Some(v) } | Terminator::Branch { condition: v, .. } => {
-            collect_val(v, locals, globals);
+        Terminator::Return { value, value_hi } => {
+            for v in value.iter().chain(value_hi) {
+                collect_val(v, locals, globals);
+            }
         }
+        Terminator::Branch { condition: v, .. } => collect_val(v, locals, globals),
         _ => {}
     }
 }
@@ -1319,13 +1809,24 @@ fn assign_live_ranges(insts: Vec<LifetimeInstr>) -> HashMap<VReg, LiveRange> {
                     update_live_range(vreg_of_value(addr), &mut map, idx);
                     update_live_range(vreg_of_value(value), &mut map, idx);
                 }
-                IRInstruction::Gep { dest, base, .. } => {
+                IRInstruction::Gep {
+                    dest, base, index, ..
+                } => {
                     update_live_range(Some(dest), &mut map, idx);
                     update_live_range(vreg_of_value(base), &mut map, idx);
+                    update_live_range(vreg_of_value(index), &mut map, idx);
                 }
-                IRInstruction::Call { reg, args, .. } => {
+                IRInstruction::Call {
+                    reg,
+                    reg_hi,
+                    args,
+                    sret,
+                    ..
+                } => {
                     update_live_range(reg.as_ref(), &mut map, idx);
+                    update_live_range(reg_hi.as_ref(), &mut map, idx);
                     args.iter()
+                        .chain(sret)
                         .for_each(|arg| update_live_range(vreg_of_value(arg), &mut map, idx));
                 }
                 IRInstruction::Move { dest, from } => {
@@ -1340,11 +1841,25 @@ fn assign_live_ranges(insts: Vec<LifetimeInstr>) -> HashMap<VReg, LiveRange> {
                     update_live_range(vreg_of_value(src), &mut map, idx);
                     update_live_range(vreg_of_value(dst), &mut map, idx);
                 }
+                IRInstruction::InlineAsm { .. }
+                | IRInstruction::AtomicLoad { .. }
+                | IRInstruction::AtomicStore { .. }
+                | IRInstruction::AtomicRmw { .. }
+                | IRInstruction::AtomicCmpXchg { .. } => {
+                    for value in irinstruction.uses() {
+                        update_live_range(vreg_of_value(value), &mut map, idx);
+                    }
+                    for reg in irinstruction.defs() {
+                        update_live_range(Some(&reg), &mut map, idx);
+                    }
+                }
                 _ => {}
             },
             LifetimeInstr::Terminator(terminator) => match terminator {
-                Terminator::Return { value: Some(value) } => {
-                    update_live_range(vreg_of_value(value), &mut map, idx)
+                Terminator::Return { value, value_hi } => {
+                    for value in value.iter().chain(value_hi) {
+                        update_live_range(vreg_of_value(value), &mut map, idx)
+                    }
                 }
                 Terminator::Branch { condition, .. } => {
                     update_live_range(vreg_of_value(condition), &mut map, idx)
@@ -1357,6 +1872,64 @@ fn assign_live_ranges(insts: Vec<LifetimeInstr>) -> HashMap<VReg, LiveRange> {
     map
 }
 
+/// Stretches each live range over every block boundary its value is live
+/// across, so a value carried around a loop or past blocks laid out in between
+/// keeps its register there.
+fn extend_over_blocks(blocks: &[IRBlock], live_ranges: &mut HashMap<VReg, LiveRange>) {
+    let regs = |values: Vec<&Value>| -> Vec<VReg> {
+        values.into_iter().filter_map(vreg_of_value).copied().collect()
+    };
+    let step = |block: &IRBlock, mut live: HashSet<VReg>| {
+        live.extend(regs(block.terminator.uses()));
+        for inst in block.instructions.iter().rev() {
+            for def in inst.defs() {
+                live.remove(&def);
+            }
+            live.extend(regs(inst.uses()));
+        }
+        live
+    };
+    let live_out = |block: &IRBlock, live_in: &HashMap<BlockId, HashSet<VReg>>| {
+        block
+            .terminator
+            .successors()
+            .iter()
+            .filter_map(|next| live_in.get(next))
+            .flatten()
+            .copied()
+            .collect::<HashSet<VReg>>()
+    };
+
+    let mut live_in: HashMap<BlockId, HashSet<VReg>> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in blocks.iter().rev() {
+            let live = step(block, live_out(block, &live_in));
+            if live_in.get(&block.id) != Some(&live) {
+                live_in.insert(block.id, live);
+                changed = true;
+            }
+        }
+    }
+
+    // Blocks are flattened in order, each followed by its terminator
+    let mut start = 0;
+    for block in blocks {
+        let end = start + block.instructions.len();
+        let out = live_out(block, &live_in);
+        let boundaries = (live_in[&block.id].iter().map(|vreg| (vreg, start)))
+            .chain(out.iter().map(|vreg| (vreg, end)));
+        for (vreg, idx) in boundaries {
+            if let Some(range) = live_ranges.get_mut(vreg) {
+                range.start = range.start.min(idx);
+                range.end = range.end.max(idx);
+            }
+        }
+        start = end + 1;
+    }
+}
+
 fn update_live_range(vreg: Option<&VReg>, map: &mut HashMap<VReg, LiveRange>, idx: usize) {
     if let Some(vreg) = vreg {
         map.entry(*vreg)
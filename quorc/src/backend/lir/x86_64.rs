@@ -1,4 +1,4 @@
-use crate::backend::lir::regalloc::TargetRegs;
+use crate::{backend::lir::regalloc::TargetRegs, midend::mir::block::IRInstruction};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum X86RegGpr {
@@ -71,27 +71,10 @@ impl X86RegFpr {
         X86RegFpr::XMM15,
     ];
 
-    pub const FP_CALLER_SAVED: &'static [X86RegFpr] = &[
-        X86RegFpr::XMM0,
-        X86RegFpr::XMM1,
-        X86RegFpr::XMM2,
-        X86RegFpr::XMM3,
-        X86RegFpr::XMM4,
-        X86RegFpr::XMM5,
-        X86RegFpr::XMM6,
-        X86RegFpr::XMM7,
-    ];
+    // SysV preserves no xmm register across calls
+    pub const FP_CALLER_SAVED: &'static [X86RegFpr] = X86RegFpr::ALL;
 
-    pub const FP_CALLEE_SAVED: &'static [X86RegFpr] = &[
-        X86RegFpr::XMM8,
-        X86RegFpr::XMM9,
-        X86RegFpr::XMM10,
-        X86RegFpr::XMM11,
-        X86RegFpr::XMM12,
-        X86RegFpr::XMM13,
-        X86RegFpr::XMM14,
-        X86RegFpr::XMM15,
-    ];
+    pub const FP_CALLEE_SAVED: &'static [X86RegFpr] = &[];
 }
 
 impl X86RegGpr {
@@ -152,8 +135,6 @@ impl X86RegGpr {
         X86RegGpr::RDI,
         X86RegGpr::R8,
         X86RegGpr::R9,
-        X86RegGpr::R10,
-        X86RegGpr::R11,
         X86RegGpr::RBX,
         X86RegGpr::R12,
         X86RegGpr::R13,
@@ -166,7 +147,7 @@ impl X86RegGpr {
 pub struct X86Regs;
 
 impl TargetRegs for X86Regs {
-    const NUM_ALLOCATABLE: usize = 14;
+    const NUM_ALLOCATABLE: usize = 12;
     const FPR_ALLOCATABLE: usize = 15;
 
     type Reg = X86RegGpr;
@@ -243,32 +224,12 @@ impl TargetRegs for X86Regs {
         )
     }
 
-    fn fp_is_caller_saved(&self, r: Self::FpReg) -> bool {
-        matches!(
-            r,
-            X86RegFpr::XMM0
-                | X86RegFpr::XMM1
-                | X86RegFpr::XMM2
-                | X86RegFpr::XMM3
-                | X86RegFpr::XMM4
-                | X86RegFpr::XMM5
-                | X86RegFpr::XMM6
-                | X86RegFpr::XMM7
-        )
+    fn fp_is_caller_saved(&self, _r: Self::FpReg) -> bool {
+        true
     }
 
-    fn fp_is_callee_saved(&self, r: Self::FpReg) -> bool {
-        matches!(
-            r,
-            X86RegFpr::XMM8
-                | X86RegFpr::XMM9
-                | X86RegFpr::XMM10
-                | X86RegFpr::XMM11
-                | X86RegFpr::XMM12
-                | X86RegFpr::XMM13
-                | X86RegFpr::XMM14
-                | X86RegFpr::XMM15
-        )
+    fn fp_is_callee_saved(&self, _r: Self::FpReg) -> bool {
+        false
     }
 
     fn reg8(&self, reg: Self::Reg) -> &'static str {
@@ -387,4 +348,22 @@ impl TargetRegs for X86Regs {
     fn fp_arg_regs(&self) -> &'static [Self::FpReg] {
         X86RegFpr::ARG_REGS
     }
+
+    fn copy_reg(&self) -> Self::Reg {
+        X86RegGpr::RAX
+    }
+
+    // `idiv` divides rdx:rax, leaving the quotient in rax and the remainder in rdx
+    fn div_clobbers(&self) -> &'static [Self::Reg] {
+        &[X86RegGpr::RAX, X86RegGpr::RDX]
+    }
+
+    // `cmpxchg` compares against and returns through rax
+    fn atomic_clobbers(&self, inst: &IRInstruction) -> Vec<Self::Reg> {
+        let mut regs = self.scratch_regs().to_vec();
+        if let IRInstruction::AtomicCmpXchg { .. } = inst {
+            regs.push(X86RegGpr::RAX);
+        }
+        regs
+    }
 }
@@ -103,6 +103,16 @@ impl Codegen {
             }
         }
 
+        for (section, content) in &ir_program.section_asm {
+            let section = match section {
+                Section::Text => AsmSection::TEXT,
+                Section::Data => AsmSection::DATA,
+                Section::Rodata => AsmSection::RODATA,
+                Section::Bss => AsmSection::BSS,
+            };
+            codegen.add_line(section, content);
+        }
+
         for constant in &ir_program.global_consts {
             let constant_ = match target_arch() {
                 "x86_64" => {
@@ -4,6 +4,7 @@ use crate::{
         lir::regalloc::{Addr, LFunction, LInst, LTerm, Loc, Operand},
     },
     midend::mir::block::GlobalDef,
+    target::c_symbol,
 };
 
 pub trait TargetEmitter: std::fmt::Debug {
@@ -47,6 +48,11 @@ pub trait TargetEmitter: std::fmt::Debug {
         let mut ctx = Self::generate_ctx(func);
 
         if func.name != "main" {
+            if let Some(symbol) = &func.export {
+                // `$` lets the symbol share a name with an instruction
+                let symbol = c_symbol(symbol);
+                func_asm.push_str(&format!("global ${symbol}\n${symbol}:\n"));
+            }
             func_asm.push_str(&format!("__q_f_{}:\n", func.name));
         } else {
             func_asm.push_str(&format!("global main\n{}:\n", func.name));
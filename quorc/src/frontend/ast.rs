@@ -1,7 +1,9 @@
+use std::collections::HashMap;
+
 use crate::frontend::size::SizeOf;
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Type {
     int,
     float,
@@ -20,10 +22,41 @@ pub enum Type {
     Void,
     Unknown,
     Pointer(Box<Type>),
-    Generic(String), // resolved before midend
+    Generic(String),                 // resolved before midend
+    ArrayLen(Box<Type>, Box<Expr>), // length is a constant expression, resolved before midend
+    Tuple(Vec<Type>),                // lowered to an anonymous struct before midend
     Inferred,
 }
 
+// Struct types are compared by name: the fields attached to a `Type::Struct`
+// are a cached copy of the declaration, which may still be incomplete while a
+// self-referential struct is being defined
+impl PartialEq for Type {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Type::Struct { name, generics, .. },
+                Type::Struct {
+                    name: other_name,
+                    generics: other_generics,
+                    ..
+                },
+            ) => name == other_name && generics == other_generics,
+            (Type::Array(elem, len), Type::Array(other_elem, other_len)) => {
+                elem == other_elem && len == other_len
+            }
+            (Type::Pointer(inner), Type::Pointer(other_inner)) => inner == other_inner,
+            (Type::StructLiteral(name), Type::StructLiteral(other_name))
+            | (Type::Generic(name), Type::Generic(other_name)) => name == other_name,
+            (Type::ArrayLen(elem, len), Type::ArrayLen(other_elem, other_len)) => {
+                elem == other_elem && len == other_len
+            }
+            (Type::Tuple(elements), Type::Tuple(other_elements)) => elements == other_elements,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
 impl Type {
     pub fn pointer_to(base: Type) -> Type {
         Type::Pointer(Box::new(base))
@@ -46,11 +79,12 @@ impl Type {
             Type::float => 4,
             Type::Char => 1,
             Type::Bool => 1,
-            Type::Pointer(_) => 8,
+            Type::Pointer(_) | Type::null => 8,
             Type::Long => 8,
             Type::Array(elem, _) => elem.align(),
             Type::Struct { instances, .. } => {
-                instances.iter().map(|(_, t)| t.align()).max().unwrap_or(1)
+                let fields = instances.iter().map(|(_, t)| (t.size(), t.align()));
+                aggregate_layout(fields, false).2
             }
             _ => 1,
         }
@@ -70,11 +104,29 @@ impl Type {
     //     }
     // }
 
+    /// Type of `self[i]`. A pointer to an array indexes the array it points to.
+    pub fn element_type(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(inner) => match &**inner {
+                Type::Array(elem, _) => Some(elem),
+                _ => Some(inner),
+            },
+            Type::Array(elem, _) => Some(elem),
+            _ => None,
+        }
+    }
+
     /// Returns true if the type fits in a single register (primitive or pointer).
     pub fn fits_in_register(&self) -> bool {
         matches!(
             self,
-            Type::int | Type::float | Type::Long | Type::Char | Type::Bool | Type::Pointer(_)
+            Type::int
+                | Type::float
+                | Type::Long
+                | Type::Char
+                | Type::Bool
+                | Type::Pointer(_)
+                | Type::null
         )
     }
 
@@ -84,24 +136,15 @@ impl Type {
             Type::float => 4,
             Type::Char => 1,
             Type::Bool => 1,
-            Type::Pointer(_) => 8,
+            Type::Pointer(_) | Type::null => 8,
             Type::Long => 8,
 
             Type::Array(elem, Some(n)) => elem.size() * (*n),
             Type::Array(_, None) => 0, // unsized; avoid allocating this as a local
 
             Type::Struct { instances, .. } => {
-                let mut off = 0usize;
-                let mut max_align = 1usize;
-
-                for (_, ty) in instances {
-                    let a = ty.align();
-                    max_align = max_align.max(a);
-                    off = round_up(off, a);
-                    off += ty.size();
-                }
-
-                round_up(off, max_align)
+                let fields = instances.iter().map(|(_, t)| (t.size(), t.align()));
+                aggregate_layout(fields, false).1
             }
 
             _ => 0,
@@ -109,18 +152,71 @@ impl Type {
     }
 }
 
+/// Lays out fields given as `(size, align)` pairs in declaration order and
+/// returns `(field offsets, total size, alignment)`. Unions put every field at 0.
+pub fn aggregate_layout(
+    fields: impl IntoIterator<Item = (usize, usize)>,
+    is_union: bool,
+) -> (Vec<usize>, usize, usize) {
+    let mut offsets = Vec::new();
+    let mut off = 0usize;
+    let mut max_align = 1usize;
+
+    for (size, align) in fields {
+        max_align = max_align.max(align);
+        if is_union {
+            offsets.push(0);
+            off = off.max(size);
+        } else {
+            off = round_up(off, align);
+            offsets.push(off);
+            off += size;
+        }
+    }
+
+    (offsets, round_up(off, max_align), max_align)
+}
+
+/// Padding controls of a struct: `@packed`, `@align(N)` on the struct and
+/// `@align(N)` on its fields. The default is the C layout.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructLayout {
+    pub packed: bool,
+    pub align: Option<usize>,
+    pub field_align: HashMap<String, usize>,
+}
+
+impl StructLayout {
+    /// `aggregate_layout` for fields given as `(name, (size, align))`. Packing
+    /// drops every field to alignment 1, `@align` only ever raises it.
+    pub fn arrange<'a>(
+        &self,
+        fields: impl IntoIterator<Item = (&'a str, (usize, usize))>,
+        is_union: bool,
+    ) -> (Vec<usize>, usize, usize) {
+        let fields = fields.into_iter().map(|(name, (size, align))| {
+            let align = if self.packed { 1 } else { align };
+            (size, self.field_align.get(name).map_or(align, |&n| n.max(align)))
+        });
+        let (offsets, size, align) = aggregate_layout(fields, is_union);
+        let align = self.align.map_or(align, |n| n.max(align));
+        (offsets, round_up(size, align), align)
+    }
+}
+
 fn round_up(x: usize, align: usize) -> usize {
     debug_assert!(align.is_power_of_two());
     (x + align - 1) & !(align - 1)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     SizeOf(SizeOf),
     IntLiteral(i32),
     LongLiteral(i64),
     FloatLiteral(f64),
     BoolLiteral(bool),
+    NullLiteral, // converts to any pointer type
     StringLiteral(String),
     // name of class
     CharLiteral(char),
@@ -132,17 +228,24 @@ pub enum Expr {
     StructInit {
         name: String,
         params: Vec<(String, Expr)>,
+        default: bool, // `..default` or `{}`: zero the fields not listed
     },
 
     AddressOf(Box<Expr>), // &expr
 
+    New(Box<Expr>), // new Struct { .. }, holding the StructInit
+    NewArray {
+        elem: Type,
+        count: Box<Expr>,
+    }, // new T[count]
+
     DerefAssign {
         target: Box<Expr>,
         value: Box<Expr>,
     },
 
-    //          class, iname
-    InstanceVar(String, String),
+    // base.field, dereferencing pointers to structs on the way
+    InstanceVar(Box<Expr>, String),
 
     Variable(String, Type),
 
@@ -151,12 +254,6 @@ pub enum Expr {
         value: Box<Expr>,
     },
 
-    CompoundAssign {
-        name: String,
-        op: BinaryOp,
-        value: Box<Expr>,
-    },
-
     PreIncrement {
         name: String,
     },
@@ -196,6 +293,13 @@ pub enum Expr {
 
     Array(Vec<Expr>, Type),
 
+    ArrayRepeat {
+        value: Box<Expr>,
+        count: Box<Expr>, // folded to an IntLiteral by the analyzer
+    }, // [value; count]
+
+    Tuple(Vec<Expr>),
+
     ArrayAccess {
         array: Box<Expr>,
         index: Box<Expr>,
@@ -209,7 +313,7 @@ pub enum Expr {
     },
 
     FieldAssign {
-        class_name: String,
+        base: Box<Expr>,
         field: String,
         value: Box<Expr>,
     },
@@ -218,7 +322,7 @@ pub enum Expr {
 impl Expr {
     pub fn get_type(&self) -> Type {
         match self {
-            Expr::StructInit { name, params } => Type::Struct {
+            Expr::StructInit { name, params, .. } => Type::Struct {
                 name: name.to_string(),
                 instances: params
                     .iter()
@@ -229,22 +333,27 @@ impl Expr {
             Expr::IntLiteral(_) => Type::int,
             Expr::FloatLiteral(_) => Type::float,
             Expr::BoolLiteral(_) => Type::Bool,
+            Expr::NullLiteral => Type::null,
             Expr::CharLiteral(_) => Type::Char,
             Expr::Variable(_, ty) => ty.clone(),
             Expr::Binary { result_type, .. } => result_type.clone(),
             Expr::Unary { expr, .. } => expr.get_type(),
             Expr::Call { return_type, .. } => return_type.clone(),
             Expr::Cast { target_type, .. } => target_type.clone(),
-            Expr::AddressOf(expr) => Type::Pointer(Box::new(expr.get_type())),
+            Expr::AddressOf(expr) | Expr::New(expr) => Type::Pointer(Box::new(expr.get_type())),
+            Expr::NewArray { elem, .. } => Type::Pointer(Box::new(elem.clone())),
             Expr::DerefAssign { target, .. } => target.get_type(),
             Expr::Array(elements, element_type) => {
                 Type::Array(Box::new(element_type.clone()), Some(elements.len()))
             }
+            Expr::ArrayRepeat { value, count } => match **count {
+                Expr::IntLiteral(n) => Type::Array(Box::new(value.get_type()), Some(n as usize)),
+                _ => Type::Array(Box::new(value.get_type()), None),
+            },
             Expr::StringLiteral(_) => Type::Pointer(Box::new(Type::Char)),
             Expr::IndexAssign { value, .. } => value.get_type(),
             // Expr::InstanceVar(_, _) => todo!(),
             Expr::Assign { value, .. } => value.get_type(),
-            Expr::CompoundAssign { value, .. } => value.get_type(),
             Expr::PreIncrement { .. } | Expr::PostIncrement { .. } => Type::int,
             Expr::PreDecrement { .. } | Expr::PostDecrement { .. } => Type::int,
             // Expr::ArrayAccess { array, index } => todo!(),
@@ -307,6 +416,11 @@ pub enum Stmt {
     VarDecl {
         name: String,
         var_type: Type,
+        value: Option<Expr>, // None for `let x: T;`
+    },
+    // let (a, b) = expr;
+    Destructure {
+        names: Vec<String>,
         value: Expr,
     },
     FunDecl {
@@ -321,6 +435,8 @@ pub enum Stmt {
         instances: Vec<(String, Type)>,
         generics: Vec<String>,
         union: bool,
+        opaque: bool, // `struct Name;`, completed by a later full declaration if any
+        layout: StructLayout,
     },
     If {
         condition: Expr,
@@ -330,23 +446,60 @@ pub enum Stmt {
     While {
         condition: Expr,
         body: Box<Stmt>,
+        label: Option<String>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        condition: Expr,
+        label: Option<String>,
     },
     For {
         init: Option<Box<Stmt>>,
         condition: Option<Expr>,
         update: Option<Expr>,
         body: Box<Stmt>,
+        label: Option<String>,
+    },
+
+    // asm("add {0}, {1}", inout(reg) x, in(reg) y, clobber("rcx"));
+    InlineAsm {
+        template: String,
+        operands: Vec<AsmOperand>,
+        clobbers: Vec<String>,
     },
 
     Block(Vec<Stmt>),
     Expression(Expr),
+    Delete(Expr),
     Return(Option<Expr>),
-    Break,
-    Continue,
+    Break(Option<String>),
+    Continue(Option<String>),
 
     CfgStmt(CfgExpr, Box<Stmt>),
 }
 
+#[derive(Debug, Clone)]
+pub struct AsmOperand {
+    pub dir: AsmDir,
+    pub reg: AsmReg,
+    pub expr: Expr, // a variable for `out` and `inout`
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsmDir {
+    In,
+    Out,
+    InOut,
+}
+
+/// Register constraint of an inline assembly operand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmReg {
+    Any,           // reg: any general purpose register
+    Float,         // freg: any floating point register
+    Named(String), // "rax", "x0", "xmm1", ...
+}
+
 #[derive(Debug, Clone)]
 pub enum CfgExpr {
     Cmp {
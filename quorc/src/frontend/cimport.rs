@@ -0,0 +1,891 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static INCLUDE_DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Sets the `-I` directories, searched before the bundled `lib/include`.
+pub fn init_include_dirs(dirs: Vec<PathBuf>) {
+    INCLUDE_DIRS.set(dirs).ok();
+}
+
+/// Locates the header of a `@cimport`. Local (`"x.h"`) headers are looked up
+/// next to the importing file first; nothing outside the search path is used.
+pub fn find_header(name: &str, local: bool, current_dir: &Path) -> Option<PathBuf> {
+    let bundled = PathBuf::from(format!("{}/lib/include", env!("CARGO_MANIFEST_DIR")));
+
+    local
+        .then(|| current_dir.to_path_buf())
+        .into_iter()
+        .chain(INCLUDE_DIRS.get().into_iter().flatten().cloned())
+        .chain(std::iter::once(bundled))
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Translates the declarations of a C header into Quor source: prototypes
+/// become `@extern def`s, structs and unions become `struct`s, and enumerators
+/// and integer `#define`s become `@const`s. Anything outside that subset, or
+/// using a type Quor cannot represent, is skipped.
+pub fn translate_header(source: &str) -> String {
+    let mut translator = Translator::default();
+    translator.run(tokenize(source));
+    translator.out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Num(i64),
+    Punct(&'static str),
+    Other,
+    Define(String, Vec<Tok>),
+}
+
+const PUNCTS: [&str; 32] = [
+    "...", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "->", "{", "}", "(", ")", "[", "]", ";",
+    ",", "*", "=", "+", "-", "/", "%", "~", "!", "&", "|", "^", "<", ">", ":",
+];
+
+/// Qualifiers and annotations that carry no layout information.
+const NOISE: [&str; 20] = [
+    "const",
+    "volatile",
+    "restrict",
+    "__restrict",
+    "__restrict__",
+    "extern",
+    "register",
+    "_Noreturn",
+    "__extension__",
+    "__inline",
+    "__inline__",
+    "__THROW",
+    "__THROWNL",
+    "__wur",
+    "__BEGIN_DECLS",
+    "__END_DECLS",
+    "__cdecl",
+    "__stdcall",
+    "_Nullable",
+    "_Nonnull",
+];
+
+/// Annotations followed by a parenthesized argument list.
+const NOISE_CALLS: [&str; 6] = [
+    "__attribute__",
+    "__attribute",
+    "__asm__",
+    "__asm",
+    "__declspec",
+    "__nonnull",
+];
+
+/// Quor keywords, which cannot name C items.
+const KEYWORDS: [&str; 21] = [
+    "break", "continue", "loop", "do", "def", "let", "if", "struct", "else", "while", "for",
+    "return", "true", "false", "as", "int", "float", "bool", "void", "char", "long",
+];
+
+fn tokenize(source: &str) -> Vec<Tok> {
+    let source = strip_comments(source).replace("\\\n", " ");
+    let mut tokens = Vec::new();
+
+    for line in source.lines() {
+        let line = line.trim();
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut words = tokenize_line(directive.trim_start());
+            if words.first() == Some(&Tok::Ident("define".to_string()))
+                && let Some(Tok::Ident(name)) = words.get(1).cloned()
+            {
+                let function_like = directive
+                    .split_once(name.as_str())
+                    .is_some_and(|(_, rest)| rest.starts_with('('));
+                if !function_like {
+                    tokens.push(Tok::Define(name, words.split_off(2)));
+                }
+            }
+            continue;
+        }
+        tokens.extend(tokenize_line(line));
+    }
+
+    strip_noise(tokens)
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    // Keep line structure for directives
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    last = c;
+                }
+                out.push(' ');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '"' | '\'' => {
+                out.push(c);
+                while let Some(inner) = chars.next() {
+                    out.push(inner);
+                    if inner == '\\' {
+                        out.extend(chars.next());
+                    } else if inner == c {
+                        break;
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn tokenize_line(line: &str) -> Vec<Tok> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Tok::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(parse_int(&text).map_or(Tok::Other, Tok::Num));
+        } else if c == '\'' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '\'' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            let text: String = chars[start..i.min(chars.len())].iter().collect();
+            tokens.push(parse_char(&text).map_or(Tok::Other, Tok::Num));
+            i += 1;
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            tokens.push(Tok::Other);
+            i += 1;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            match PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+                Some(p) => {
+                    tokens.push(Tok::Punct(p));
+                    i += p.len();
+                }
+                None => {
+                    tokens.push(Tok::Other);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    tokens
+}
+
+fn parse_int(text: &str) -> Option<i64> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()
+    } else if digits.len() > 1
+        && let Some(oct) = digits.strip_prefix('0')
+    {
+        i64::from_str_radix(oct, 8).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+fn parse_char(text: &str) -> Option<i64> {
+    let value = match text {
+        "\\n" => '\n',
+        "\\t" => '\t',
+        "\\r" => '\r',
+        "\\0" => '\0',
+        "\\\\" => '\\',
+        "\\'" => '\'',
+        _ if text.chars().count() == 1 => text.chars().next()?,
+        _ => return None,
+    };
+    Some(value as i64)
+}
+
+fn strip_noise(tokens: Vec<Tok>) -> Vec<Tok> {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut iter = tokens.into_iter().peekable();
+
+    while let Some(tok) = iter.next() {
+        let Tok::Ident(name) = &tok else {
+            out.push(tok);
+            continue;
+        };
+        if NOISE.contains(&name.as_str()) {
+            continue;
+        }
+        if NOISE_CALLS.contains(&name.as_str()) || name.starts_with("__attribute") {
+            if iter.peek() == Some(&Tok::Punct("(")) {
+                let mut depth = 0;
+                for tok in iter.by_ref() {
+                    match tok {
+                        Tok::Punct("(") => depth += 1,
+                        Tok::Punct(")") => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        out.push(tok);
+    }
+
+    out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum CType {
+    Void,
+    Bool,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Record(String),
+    Anon(usize), // index into `Translator::anon_records`, named by a typedef
+    FnPtr,
+    Pointer(Box<CType>),
+    Array(Box<CType>, Option<usize>),
+    Unsupported,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Place {
+    Param,
+    Return,
+    Field,
+    Pointee,
+}
+
+struct Record {
+    union: bool,
+    fields: Option<Vec<(String, CType)>>, // None if a field cannot be read
+}
+
+struct Signature {
+    params: Vec<(Option<String>, CType)>,
+    variadic: bool,
+}
+
+#[derive(Default)]
+struct Translator {
+    out: String,
+    typedefs: HashMap<String, CType>,
+    consts: HashMap<String, i64>,
+    structs: HashSet<String>,
+    functions: HashSet<String>,
+    anon_records: Vec<Record>,
+}
+
+struct Cursor<'a> {
+    toks: &'a [Tok],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn eat(&mut self, punct: &'static str) -> bool {
+        if self.peek() == Some(&Tok::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Tok::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    /// The tokens up to the `close` matching an already consumed `open`.
+    fn group(&mut self, open: &str, close: &str) -> Option<&[Tok]> {
+        let start = self.pos;
+        let mut depth = 1;
+        while let Some(tok) = self.toks.get(self.pos) {
+            self.pos += 1;
+            match tok {
+                Tok::Punct(p) if *p == open => depth += 1,
+                Tok::Punct(p) if *p == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&self.toks[start..self.pos - 1]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Splits `toks` at top-level occurrences of `sep`.
+fn split_top<'a>(toks: &'a [Tok], sep: &str) -> Vec<&'a [Tok]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, tok) in toks.iter().enumerate() {
+        match tok {
+            Tok::Punct("(" | "[" | "{") => depth += 1,
+            Tok::Punct(")" | "]" | "}") => depth -= 1,
+            Tok::Punct(p) if *p == sep && depth == 0 => {
+                parts.push(&toks[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&toks[start..]);
+    parts
+}
+
+fn builtin_typedef(name: &str) -> Option<CType> {
+    Some(match name {
+        "size_t" | "ssize_t" | "ptrdiff_t" | "intptr_t" | "uintptr_t" | "off_t" | "time_t"
+        | "int64_t" | "uint64_t" | "intmax_t" | "uintmax_t" => CType::Long,
+        "int32_t" | "uint32_t" | "wchar_t" | "pid_t" => CType::Int,
+        "int16_t" | "uint16_t" => CType::Short,
+        "int8_t" | "uint8_t" => CType::Char,
+        "bool" | "_Bool" => CType::Bool,
+        _ => return None,
+    })
+}
+
+fn quor_ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+impl Translator {
+    fn run(&mut self, toks: Vec<Tok>) {
+        let mut cursor = Cursor {
+            toks: &toks,
+            pos: 0,
+        };
+
+        while let Some(tok) = cursor.peek().cloned() {
+            match tok {
+                Tok::Define(name, body) => {
+                    cursor.pos += 1;
+                    self.define(&name, &body);
+                }
+                Tok::Punct(";" | "}") => cursor.pos += 1,
+                // extern "C" {
+                Tok::Other if cursor.toks.get(cursor.pos + 1) == Some(&Tok::Punct("{")) => {
+                    cursor.pos += 2
+                }
+                _ => {
+                    let start = cursor.pos;
+                    let mut depth = 0;
+                    let mut body = false;
+                    while let Some(tok) = cursor.peek() {
+                        match tok {
+                            Tok::Punct("(" | "[") => depth += 1,
+                            Tok::Punct(")" | "]") => depth -= 1,
+                            // A function definition, e.g. a static inline helper
+                            Tok::Punct("{")
+                                if depth == 0
+                                    && cursor.pos > start
+                                    && cursor.toks[cursor.pos - 1] == Tok::Punct(")") =>
+                            {
+                                body = true;
+                                cursor.pos += 1;
+                                cursor.group("{", "}");
+                                break;
+                            }
+                            Tok::Punct("{") => {
+                                cursor.pos += 1;
+                                cursor.group("{", "}");
+                                continue;
+                            }
+                            Tok::Punct(";") if depth == 0 => break,
+                            Tok::Define(..) => break,
+                            _ => {}
+                        }
+                        cursor.pos += 1;
+                    }
+                    if !body {
+                        self.declaration(&toks[start..cursor.pos]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str, body: &[Tok]) {
+        if body.is_empty() || KEYWORDS.contains(&name) {
+            return;
+        }
+        if let Some(value) = self.eval(body)
+            && (-i64::from(i32::MAX)..=i64::from(i32::MAX)).contains(&value)
+        {
+            self.constant(name, value);
+        }
+    }
+
+    fn constant(&mut self, name: &str, value: i64) {
+        if KEYWORDS.contains(&name) || self.consts.contains_key(name) {
+            return;
+        }
+        self.consts.insert(name.to_string(), value);
+        self.out.push_str(&format!("@const {name} = {value}\n"));
+    }
+
+    fn declaration(&mut self, toks: &[Tok]) {
+        let mut cursor = Cursor { toks, pos: 0 };
+        let is_typedef = cursor.peek() == Some(&Tok::Ident("typedef".to_string()));
+        if is_typedef {
+            cursor.pos += 1;
+        }
+        // Static and inline functions have no symbol to link against
+        if matches!(cursor.peek(), Some(Tok::Ident(s)) if s == "static" || s == "inline") {
+            return;
+        }
+        let Some(base) = self.type_spec(&mut cursor) else {
+            return;
+        };
+
+        let rest = &toks[cursor.pos..];
+        if rest.is_empty() {
+            return;
+        }
+        for part in split_top(rest, ",") {
+            let mut cursor = Cursor { toks: part, pos: 0 };
+            let Some((name, ty, signature)) = self.declarator(&mut cursor, base.clone()) else {
+                continue;
+            };
+            let Some(name) = name else {
+                continue;
+            };
+
+            if is_typedef {
+                self.typedef(name, ty, signature.is_some());
+            } else if let Some(signature) = signature {
+                self.prototype(&name, &ty, signature);
+            }
+        }
+    }
+
+    fn typedef(&mut self, name: String, ty: CType, is_function: bool) {
+        if is_function {
+            self.typedefs.insert(name, CType::Unsupported);
+            return;
+        }
+        let ty = match ty {
+            CType::Anon(index) => {
+                let record = &self.anon_records[index];
+                let (union, fields) = (record.union, record.fields.clone());
+                self.record(&name, union, fields);
+                CType::Record(name.clone())
+            }
+            ty => ty,
+        };
+        self.typedefs.insert(name, ty);
+    }
+
+    fn prototype(&mut self, name: &str, ret: &CType, signature: Signature) {
+        if KEYWORDS.contains(&name) || name == "main" || !self.functions.insert(name.to_string()) {
+            return;
+        }
+
+        let mut params = Vec::new();
+        for (i, (param, ty)) in signature.params.iter().enumerate() {
+            let ty = match ty {
+                CType::Array(elem, _) => CType::Pointer(elem.clone()),
+                ty => ty.clone(),
+            };
+            let Some(ty) = self.quor_type(&ty, Place::Param) else {
+                return;
+            };
+            let param = param
+                .as_deref()
+                .map_or_else(|| format!("arg{i}"), quor_ident);
+            params.push(format!("{param}: {ty}"));
+        }
+        if signature.variadic {
+            params.push("...".to_string());
+        }
+
+        let ret = match ret {
+            CType::Void => String::new(),
+            ty => match self.quor_type(ty, Place::Return) {
+                Some(ty) => format!(" :: {ty}"),
+                None => return,
+            },
+        };
+
+        self.out.push_str(&format!(
+            "@extern def {name}({}){ret};\n",
+            params.join(", ")
+        ));
+    }
+
+    fn record(&mut self, name: &str, union: bool, fields: Option<Vec<(String, CType)>>) {
+        if KEYWORDS.contains(&name) || self.structs.contains(name) {
+            return;
+        }
+        let Some(fields) = fields.filter(|fields| !fields.is_empty()) else {
+            return;
+        };
+
+        let mut body = String::new();
+        for (field, ty) in &fields {
+            let Some(ty) = self.quor_type(ty, Place::Field) else {
+                return;
+            };
+            body.push_str(&format!("    {}: {ty};\n", quor_ident(field)));
+        }
+
+        if union {
+            self.out.push_str("@union\n");
+        }
+        self.out.push_str(&format!("struct {name} {{\n{body}}}\n"));
+        self.structs.insert(name.to_string());
+    }
+
+    /// The Quor spelling of `ty`, if it has one. C `double` is passed in
+    /// registers the way Quor passes `float`; in memory only `float` matches.
+    fn quor_type(&self, ty: &CType, place: Place) -> Option<String> {
+        Some(match ty {
+            CType::Void if place == Place::Pointee => "void".to_string(),
+            CType::Bool => "bool".to_string(),
+            CType::Char => "char".to_string(),
+            CType::Int => "int".to_string(),
+            CType::Long => "long".to_string(),
+            CType::Float if matches!(place, Place::Field | Place::Pointee) => "float".to_string(),
+            CType::Double if matches!(place, Place::Param | Place::Return) => "float".to_string(),
+            CType::Record(name) if self.structs.contains(name) => name.clone(),
+            CType::FnPtr => "void*".to_string(),
+            CType::Pointer(inner) => match &**inner {
+                // Opaque structs and function pointers are only passed around
+                CType::Record(name) if !self.structs.contains(name) => "void*".to_string(),
+                CType::FnPtr => "void*".to_string(),
+                inner => format!("{}*", self.quor_type(inner, Place::Pointee)?),
+            },
+            CType::Array(elem, Some(n)) if place == Place::Field => {
+                format!("[{}, {n}]", self.quor_type(elem, Place::Field)?)
+            }
+            _ => return None,
+        })
+    }
+
+    fn type_spec(&mut self, cursor: &mut Cursor) -> Option<CType> {
+        let mut base: Option<CType> = None;
+        let (mut longs, mut short, mut sign) = (0, false, false);
+
+        while let Some(Tok::Ident(word)) = cursor.peek().cloned() {
+            match word.as_str() {
+                "signed" | "unsigned" => sign = true,
+                "short" => short = true,
+                "long" => longs += 1,
+                "int" => base = base.or(Some(CType::Int)),
+                "char" => base = Some(CType::Char),
+                "void" => base = Some(CType::Void),
+                "float" => base = Some(CType::Float),
+                "double" => base = Some(CType::Double),
+                "_Bool" | "bool" => base = Some(CType::Bool),
+                "struct" | "union" => {
+                    cursor.pos += 1;
+                    base = Some(self.record_spec(cursor, word == "union")?);
+                    continue;
+                }
+                "enum" => {
+                    cursor.pos += 1;
+                    self.enum_spec(cursor)?;
+                    base = Some(CType::Int);
+                    continue;
+                }
+                _ if base.is_some() || sign || short || longs > 0 => break,
+                name => {
+                    base = Some(
+                        self.typedefs
+                            .get(name)
+                            .cloned()
+                            .or_else(|| builtin_typedef(name))?,
+                    );
+                }
+            }
+            cursor.pos += 1;
+        }
+
+        Some(match base {
+            None | Some(CType::Int) if short => CType::Short,
+            None | Some(CType::Int) if longs > 0 => CType::Long,
+            Some(CType::Double) if longs > 0 => CType::Unsupported,
+            _ if short || longs > 0 => return None,
+            None if sign => CType::Int,
+            base => base?,
+        })
+    }
+
+    fn record_spec(&mut self, cursor: &mut Cursor, union: bool) -> Option<CType> {
+        let tag = cursor.ident();
+        if !cursor.eat("{") {
+            return Some(CType::Record(tag?));
+        }
+        let body = cursor.group("{", "}")?.to_vec();
+        let fields = self.fields(&body);
+
+        match tag {
+            Some(tag) => {
+                self.record(&tag, union, fields);
+                Some(CType::Record(tag))
+            }
+            None => {
+                self.anon_records.push(Record { union, fields });
+                Some(CType::Anon(self.anon_records.len() - 1))
+            }
+        }
+    }
+
+    fn fields(&mut self, body: &[Tok]) -> Option<Vec<(String, CType)>> {
+        let mut fields = Vec::new();
+        for decl in split_top(body, ";") {
+            if decl.is_empty() {
+                continue;
+            }
+            // Bitfields have no Quor layout
+            if decl.contains(&Tok::Punct(":")) {
+                return None;
+            }
+            let mut cursor = Cursor { toks: decl, pos: 0 };
+            let base = self.type_spec(&mut cursor)?;
+            for part in split_top(&decl[cursor.pos..], ",") {
+                let mut cursor = Cursor { toks: part, pos: 0 };
+                let (name, ty, signature) = self.declarator(&mut cursor, base.clone())?;
+                if signature.is_some() {
+                    return None;
+                }
+                fields.push((name?, ty));
+            }
+        }
+        Some(fields)
+    }
+
+    fn enum_spec(&mut self, cursor: &mut Cursor) -> Option<()> {
+        cursor.ident();
+        if !cursor.eat("{") {
+            return Some(());
+        }
+        let body = cursor.group("{", "}")?.to_vec();
+
+        let mut next = 0;
+        for item in split_top(&body, ",") {
+            let Some(Tok::Ident(name)) = item.first() else {
+                continue;
+            };
+            let value = match item.get(1) {
+                Some(Tok::Punct("=")) => self.eval(&item[2..])?,
+                _ => next,
+            };
+            self.constant(name, value);
+            next = value + 1;
+        }
+        Some(())
+    }
+
+    /// Parses `*name[N]`, `*name(params)` or `(*name)(params)`, including
+    /// abstract declarators without a name.
+    fn declarator(
+        &mut self,
+        cursor: &mut Cursor,
+        mut ty: CType,
+    ) -> Option<(Option<String>, CType, Option<Signature>)> {
+        while cursor.eat("*") {
+            ty = CType::Pointer(Box::new(ty));
+        }
+
+        if cursor.eat("(") {
+            let inner = cursor.group("(", ")")?;
+            if inner.first() != Some(&Tok::Punct("*")) {
+                return None;
+            }
+            let name = inner.iter().find_map(|tok| match tok {
+                Tok::Ident(name) => Some(name.clone()),
+                _ => None,
+            });
+            return Some((name, CType::FnPtr, None));
+        }
+
+        let name = cursor.ident();
+
+        if cursor.eat("(") {
+            let params = cursor.group("(", ")")?.to_vec();
+            let signature = self.params(&params)?;
+            return Some((name, ty, Some(signature)));
+        }
+
+        let mut dims = Vec::new();
+        while cursor.eat("[") {
+            let size = cursor.group("[", "]")?.to_vec();
+            dims.push(if size.is_empty() {
+                None
+            } else {
+                Some(usize::try_from(self.eval(&size)?).ok()?)
+            });
+        }
+        for dim in dims.into_iter().rev() {
+            ty = CType::Array(Box::new(ty), dim);
+        }
+
+        // Initializers of variables are irrelevant
+        if cursor.peek().is_some() && !cursor.eat("=") {
+            return None;
+        }
+        Some((name, ty, None))
+    }
+
+    fn params(&mut self, toks: &[Tok]) -> Option<Signature> {
+        let mut signature = Signature {
+            params: Vec::new(),
+            variadic: false,
+        };
+        if toks.is_empty() || toks == [Tok::Ident("void".to_string())] {
+            return Some(signature);
+        }
+
+        for param in split_top(toks, ",") {
+            if param == [Tok::Punct("...")] {
+                signature.variadic = true;
+                continue;
+            }
+            let mut cursor = Cursor {
+                toks: param,
+                pos: 0,
+            };
+            let base = self.type_spec(&mut cursor)?;
+            let (name, ty, inner) = self.declarator(&mut cursor, base)?;
+            let ty = if inner.is_some() { CType::FnPtr } else { ty };
+            signature.params.push((name, ty));
+        }
+        Some(signature)
+    }
+
+    /// Evaluates an integer constant expression, e.g. `(1 << 4) | FLAG`.
+    fn eval(&self, toks: &[Tok]) -> Option<i64> {
+        let mut cursor = Cursor { toks, pos: 0 };
+        let value = self.binary(&mut cursor, 0)?;
+        cursor.peek().is_none().then_some(value)
+    }
+
+    fn binary(&self, cursor: &mut Cursor, min_prec: u8) -> Option<i64> {
+        let mut left = self.unary(cursor)?;
+        while let Some(Tok::Punct(op)) = cursor.peek().cloned() {
+            let prec = match op {
+                "*" | "/" | "%" => 10,
+                "+" | "-" => 9,
+                "<<" | ">>" => 8,
+                "<" | ">" | "<=" | ">=" => 7,
+                "==" | "!=" => 6,
+                "&" => 5,
+                "^" => 4,
+                "|" => 3,
+                "&&" => 2,
+                "||" => 1,
+                _ => break,
+            };
+            if prec <= min_prec {
+                break;
+            }
+            cursor.pos += 1;
+            let right = self.binary(cursor, prec)?;
+            left = match op {
+                "*" => left.checked_mul(right)?,
+                "/" => left.checked_div(right)?,
+                "%" => left.checked_rem(right)?,
+                "+" => left.checked_add(right)?,
+                "-" => left.checked_sub(right)?,
+                "<<" => left.checked_shl(u32::try_from(right).ok()?)?,
+                ">>" => left.checked_shr(u32::try_from(right).ok()?)?,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "&" => left & right,
+                "^" => left ^ right,
+                "|" => left | right,
+                "&&" => (left != 0 && right != 0) as i64,
+                _ => (left != 0 || right != 0) as i64,
+            };
+        }
+        Some(left)
+    }
+
+    fn unary(&self, cursor: &mut Cursor) -> Option<i64> {
+        let tok = cursor.peek()?.clone();
+        cursor.pos += 1;
+        match tok {
+            Tok::Num(value) => Some(value),
+            Tok::Ident(name) => self.consts.get(&name).copied(),
+            Tok::Punct("-") => self.unary(cursor)?.checked_neg(),
+            Tok::Punct("+") => self.unary(cursor),
+            Tok::Punct("~") => Some(!self.unary(cursor)?),
+            Tok::Punct("!") => Some((self.unary(cursor)? == 0) as i64),
+            Tok::Punct("(") => {
+                let inner = cursor.group("(", ")")?;
+                self.eval(inner)
+            }
+            _ => None,
+        }
+    }
+}
@@ -196,7 +196,10 @@ impl Lexer {
                 tokens.push(self.make_token(token));
             }
             c if c.is_ascii_digit() => {
-                tokens.push(self.scan_number()?);
+                // `t.0.1` is two tuple indices, not `t` followed by `0.1`
+                let field_index =
+                    matches!(tokens.last(), Some(t) if t.token_type == TokenType::Period);
+                tokens.push(self.scan_number(field_index)?);
             }
             c if c.is_ascii_alphabetic() || c == '_' => {
                 tokens.push(self.scan_identifier());
@@ -223,6 +226,8 @@ impl Lexer {
         let token_type = match text.as_str() {
             "break" => TokenType::Break,
             "continue" => TokenType::Continue,
+            "loop" => TokenType::Loop,
+            "do" => TokenType::Do,
 
             "def" => TokenType::Def,
 
@@ -237,6 +242,7 @@ impl Lexer {
             "return" => TokenType::Return,
             "true" => TokenType::True,
             "false" => TokenType::False,
+            "null" => TokenType::Null,
             "as" => TokenType::As,
 
             "int" => TokenType::Int,
@@ -251,7 +257,7 @@ impl Lexer {
         Token::new(token_type, start_line, start_col, start_pos)
     }
 
-    fn scan_number(&mut self) -> Result<Token, LexError> {
+    fn scan_number(&mut self, field_index: bool) -> Result<Token, LexError> {
         let start_line = self.line;
         let start_col = self.column - 1; // already advanced
         let start_pos = self.current - 1;
@@ -264,7 +270,7 @@ impl Lexer {
         }
 
         // Check for decimal point
-        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+        if !field_index && self.peek() == '.' && self.peek_next().is_ascii_digit() {
             is_float = true;
             self.advance(); // consume '.'
 
@@ -309,6 +315,20 @@ impl Lexer {
             return Err(LexError::InvalidCharacter('\'', start_line, start_col));
         }
 
+        // 'name without a closing quote is a loop label
+        if (self.peek().is_ascii_alphabetic() || self.peek() == '_') && self.peek_next() != '\'' {
+            while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+                self.advance();
+            }
+            let name: String = self.chars[start_pos + 1..self.current].iter().collect();
+            return Ok(Token::new(
+                TokenType::Label(name),
+                start_line,
+                start_col,
+                start_pos,
+            ));
+        }
+
         // Read the character (support common escapes)
         let ch = match self.advance() {
             '\\' => {
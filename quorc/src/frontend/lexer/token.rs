@@ -33,6 +33,9 @@ impl Token {
 pub enum TokenType {
     Break,
     Continue,
+    Loop,
+    Do,
+    Label(String),
 
     IntLiteral(i32),
     FloatLiteral(f64),
@@ -52,6 +55,7 @@ pub enum TokenType {
     Return,
     True,
     False,
+    Null,
     For,
 
     And,
@@ -80,7 +80,8 @@ impl Parser {
         false
     }
 
-    fn parse_import(&mut self) -> Result<Stmt, ParseError> {
+    /// The path of an `@import` or `@cimport`; `<std>` paths end in `!`.
+    fn parse_import(&mut self, decl: &str) -> Result<Stmt, ParseError> {
         let mut epilogue = "";
 
         let path = match &self.peek().token_type {
@@ -103,6 +104,10 @@ impl Parser {
                             path.push('.');
                             self.advance();
                         }
+                        TokenType::Slash => {
+                            path.push('/');
+                            self.advance();
+                        }
                         TokenType::Greater => {
                             self.advance();
                             break;
@@ -120,7 +125,7 @@ impl Parser {
         };
 
         let path = format!("{path}{epilogue}");
-        Ok(Stmt::AtDecl("import".to_string(), Some(path), None, None))
+        Ok(Stmt::AtDecl(decl.to_string(), Some(path), None, None))
     }
 
     fn at_declaration(&mut self) -> Result<Stmt, ParseError> {
@@ -194,8 +199,12 @@ impl Parser {
             //     ));
             // }
 
-            if decl == "import" {
-                return self.parse_import();
+            if decl == "import" || decl == "cimport" {
+                return self.parse_import(decl);
+            }
+
+            if decl == "extern" && self.check(&TokenType::Def) {
+                return self.extern_prototype();
             }
 
             if decl == "extern"
@@ -232,12 +241,22 @@ impl Parser {
                 return Err(ParseError::UnexpectedToken(self.peek().clone()));
             }
 
+            if decl == "allow" {
+                self.consume(TokenType::LeftParen, "Expected '(' after @allow")?;
+                let TokenType::Identifier(lint) = self.peek().token_type.clone() else {
+                    return Err(ParseError::UnexpectedToken(self.peek().clone()));
+                };
+                self.advance();
+                self.consume(TokenType::RightParen, "Expected ')' after lint name")?;
+                return Ok(Stmt::AtDecl(decl.to_string(), Some(lint), None, None));
+            }
+
             if let TokenType::Identifier(name) = &self.peek().clone().token_type {
                 self.advance();
 
                 if let TokenType::Equal = &self.peek().token_type {
                     self.advance();
-                    let expr = self.expression().unwrap_or_else(|_| panic!());
+                    let expr = self.expression()?;
                     return Ok(Stmt::AtDecl(
                         decl.to_string(),
                         Some(name.to_string()),
@@ -294,6 +313,22 @@ impl Parser {
             if let TokenType::LeftBrace = &self.peek().token_type {
                 self.advance();
                 let mut assembly_code = String::new();
+                // Data sections keep string quotes, e.g. `msg: db "hi", 0`
+                let quote_strings = matches!(
+                    decl.as_str(),
+                    "__asm_bss__"
+                        | "_asm_bss_"
+                        | "asm_bss"
+                        | "__asm_ro__"
+                        | "_asm_ro_"
+                        | "asm_ro"
+                        | "__asm_data__"
+                        | "_asm_data_"
+                        | "asm_data"
+                );
+                // Whether the last token opened a `:lo12:`-style relocation operator
+                let mut in_reloc = false;
+                let mut previous = TokenType::Newline;
 
                 while self.peek().token_type != TokenType::RightBrace {
                     // println!("Processing token: {:?}", self.peek().token_type);
@@ -314,6 +349,11 @@ impl Parser {
                         TokenType::FloatLiteral(float) => {
                             assembly_code.push_str(&float.to_string());
                         }
+                        TokenType::StringLiteral(string) if quote_strings => {
+                            assembly_code.push('"');
+                            assembly_code.push_str(string);
+                            assembly_code.push('"');
+                        }
                         TokenType::StringLiteral(string) => {
                             assembly_code.push_str(string);
                         }
@@ -388,23 +428,39 @@ impl Parser {
 
                     self.advance();
 
+                    // A colon after anything but a name opens a relocation
+                    // operator like `:lo12:`, which is closed by the next colon;
+                    // the operator and its symbol are written without spaces
+                    let glued = current_token == TokenType::Colon
+                        && (in_reloc || !matches!(previous, TokenType::Identifier(_)));
+                    if current_token == TokenType::Colon {
+                        in_reloc = glued && !in_reloc;
+                    }
+                    previous = current_token.clone();
+
                     // Add a space after most tokens, but be smart about it
-                    if !self.is_at_end() && self.peek().token_type != TokenType::RightBrace {
+                    if !glued
+                        && !self.is_at_end()
+                        && self.peek().token_type != TokenType::RightBrace
+                    {
                         let next_token = &self.peek().token_type;
 
-                        // Don't add space after comma, newline, or closing punctuation
+                        // Don't add space after comma, newline, directive dots, or closing punctuation
                         if !matches!(
                             current_token,
                             TokenType::Comma
+                                | TokenType::Period
                                 | TokenType::Newline
                                 | TokenType::RightParen
                                 | TokenType::RightBracket
                                 | TokenType::Semicolon
                         ) {
-                            // Don't add space before newline or closing punctuation
+                            // Don't add space before newline, commas, label colons, or closing punctuation
                             if !matches!(
                                 next_token,
                                 TokenType::Newline
+                                    | TokenType::Comma
+                                    | TokenType::Colon
                                     | TokenType::RightParen
                                     | TokenType::RightBracket
                                     | TokenType::Semicolon
@@ -537,6 +593,7 @@ impl Parser {
         if self.match_token(&[TokenType::At]) {
             let mut lookahead = self.current - 1;
             let mut found_def = false;
+            let mut found_struct = false;
             while lookahead < self.tokens.len() {
                 match &self.tokens[lookahead].token_type {
                     TokenType::At => {
@@ -545,7 +602,22 @@ impl Parser {
                     }
                     TokenType::Identifier(identifier) => {
                         match identifier.as_str() {
-                            "variadic" | "trust_ret" | "inline" | "no_frame" => {
+                            "variadic" | "trust_ret" | "inline" | "no_frame" | "packed"
+                            | "union" => {
+                                lookahead += 1;
+                            }
+                            "export"
+                                if self.tokens.get(lookahead + 1).map(|t| &t.token_type)
+                                    != Some(&TokenType::LeftParen) =>
+                            {
+                                lookahead += 1;
+                            }
+                            "format" | "export" | "align" => {
+                                while lookahead < self.tokens.len()
+                                    && self.tokens[lookahead].token_type != TokenType::RightParen
+                                {
+                                    lookahead += 1;
+                                }
                                 lookahead += 1;
                             }
                             _ => break,
@@ -560,6 +632,10 @@ impl Parser {
                         found_def = true;
                         break;
                     }
+                    TokenType::Struct => {
+                        found_struct = true;
+                        break;
+                    }
                     _ => {
                         break;
                     }
@@ -569,18 +645,27 @@ impl Parser {
             if found_def {
                 self.current -= 1;
                 return self.fn_dec();
+            } else if found_struct {
+                self.current -= 1;
+                return self.struct_dec();
             } else {
                 return self.at_declaration();
             }
         }
-        if self.match_token(&[TokenType::While]) {
-            return self.while_statement(false);
+        if let TokenType::Label(label) = &self.peek().token_type {
+            let label = label.clone();
+            self.advance();
+            self.consume(TokenType::Colon, "Expected ':' after loop label")?;
+            return self.loop_statement(Some(label));
         }
-        if self.match_token(&[TokenType::For]) {
-            return self.while_statement(true);
+        if matches!(
+            self.peek().token_type,
+            TokenType::While | TokenType::For | TokenType::Loop | TokenType::Do
+        ) {
+            return self.loop_statement(None);
         }
         if self.match_token(&[TokenType::Struct]) {
-            return self.class_dec();
+            return self.class_dec(false, StructLayout::default());
         }
         if self.match_token(&[TokenType::Return]) {
             return self.return_statement();
@@ -595,12 +680,32 @@ impl Parser {
             return Ok(Stmt::Block(self.block(final_iter_in_loop)?));
         }
         if self.match_token(&[TokenType::Break]) {
-            self.advance();
-            return Ok(Stmt::Break);
+            let label = self.jump_label();
+            self.consume(TokenType::Semicolon, "Expected ';' after 'break'")?;
+            return Ok(Stmt::Break(label));
         }
         if self.match_token(&[TokenType::Continue]) {
+            let label = self.jump_label();
+            self.consume(TokenType::Semicolon, "Expected ';' after 'continue'")?;
+            return Ok(Stmt::Continue(label));
+        }
+        if matches!(&self.peek().token_type, TokenType::Identifier(id) if id == "delete")
+            && matches!(
+                self.tokens.get(self.current + 1).map(|t| &t.token_type),
+                Some(TokenType::Identifier(_) | TokenType::Star)
+            )
+        {
             self.advance();
-            return Ok(Stmt::Continue);
+            let expr = self.expression()?;
+            self.consume(TokenType::Semicolon, "Expected ';' after 'delete'")?;
+            return Ok(Stmt::Delete(expr));
+        }
+        if matches!(&self.peek().token_type, TokenType::Identifier(id) if id == "asm")
+            && self.tokens.get(self.current + 1).map(|t| &t.token_type)
+                == Some(&TokenType::LeftParen)
+        {
+            self.current += 2;
+            return self.asm_statement();
         }
 
         let expr = self.expression()?;
@@ -610,7 +715,73 @@ impl Parser {
         Ok(Stmt::Expression(expr))
     }
 
+    /// `asm("template", in(reg) a, out("rax") b, inout(freg) c, clobber("rcx"));`
+    /// after `asm(`.
+    fn asm_statement(&mut self) -> Result<Stmt, ParseError> {
+        let TokenType::StringLiteral(template) = self.peek().token_type.clone() else {
+            return Err(ParseError::UnexpectedToken(self.peek().clone()));
+        };
+        self.advance();
+
+        let mut operands = Vec::new();
+        let mut clobbers = Vec::new();
+        while self.match_token(&[TokenType::Comma]) {
+            let TokenType::Identifier(kind) = self.peek().token_type.clone() else {
+                return Err(ParseError::UnexpectedToken(self.peek().clone()));
+            };
+            let dir = match kind.as_str() {
+                "in" => AsmDir::In,
+                "out" => AsmDir::Out,
+                "inout" => AsmDir::InOut,
+                "clobber" => {
+                    self.advance();
+                    self.consume(TokenType::LeftParen, "Expected '(' after clobber")?;
+                    loop {
+                        let TokenType::StringLiteral(reg) = self.peek().token_type.clone() else {
+                            return Err(ParseError::UnexpectedToken(self.peek().clone()));
+                        };
+                        self.advance();
+                        clobbers.push(reg);
+                        if !self.match_token(&[TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                    self.consume(TokenType::RightParen, "Expected ')' after clobbers")?;
+                    continue;
+                }
+                _ => return Err(ParseError::UnexpectedToken(self.peek().clone())),
+            };
+            self.advance();
+
+            self.consume(TokenType::LeftParen, "Expected '(' before register")?;
+            let reg = match self.peek().token_type.clone() {
+                TokenType::Identifier(class) if class == "reg" => AsmReg::Any,
+                TokenType::Identifier(class) if class == "freg" => AsmReg::Float,
+                TokenType::StringLiteral(name) => AsmReg::Named(name),
+                _ => return Err(ParseError::UnexpectedToken(self.peek().clone())),
+            };
+            self.advance();
+            self.consume(TokenType::RightParen, "Expected ')' after register")?;
+
+            let expr = self.expression()?;
+            operands.push(AsmOperand { dir, reg, expr });
+        }
+
+        self.consume(TokenType::RightParen, "Expected ')' after asm operands")?;
+        self.consume(TokenType::Semicolon, "Expected ';' after asm")?;
+
+        Ok(Stmt::InlineAsm {
+            template: template.replace("\\n", "\n"),
+            operands,
+            clobbers,
+        })
+    }
+
     fn var_dec(&mut self) -> Result<Stmt, ParseError> {
+        if self.match_token(&[TokenType::LeftParen]) {
+            return self.destructure();
+        }
+
         let name = self.consume(TokenType::Identifier("".into()), "Expected variable name")?;
         let var_name = match &name.token_type {
             TokenType::Identifier(n) => n.clone(),
@@ -626,9 +797,12 @@ impl Parser {
 
         // self.consume(TokenType::Colon, "Expected ':' after variable name")?;
 
-        self.consume(TokenType::Equal, "Expected '=' after variable type")?;
-
-        let initializer = self.expression()?;
+        let initializer = if var_type != Type::Inferred && self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            self.consume(TokenType::Equal, "Expected '=' after variable type")?;
+            Some(self.expression()?)
+        };
 
         self.consume(
             TokenType::Semicolon,
@@ -642,7 +816,70 @@ impl Parser {
         })
     }
 
-    fn class_dec(&mut self) -> Result<Stmt, ParseError> {
+    fn destructure(&mut self) -> Result<Stmt, ParseError> {
+        let mut names = Vec::new();
+        loop {
+            let name = self.consume(TokenType::Identifier("".into()), "Expected variable name")?;
+            match &name.token_type {
+                TokenType::Identifier(n) => names.push(n.clone()),
+                _ => return Err(ParseError::UnexpectedToken(name.clone())),
+            }
+
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(TokenType::RightParen, "Expected ')' after destructured names")?;
+        self.consume(TokenType::Equal, "Expected '=' after destructured names")?;
+        let value = self.expression()?;
+        self.consume(
+            TokenType::Semicolon,
+            "Expected ';' after variable declaration",
+        )?;
+
+        Ok(Stmt::Destructure { names, value })
+    }
+
+    /// A struct with `@union`, `@packed` or `@align(N)` in front of it.
+    fn struct_dec(&mut self) -> Result<Stmt, ParseError> {
+        let mut union = false;
+        let mut layout = StructLayout::default();
+        while self.match_token(&[TokenType::At]) {
+            let TokenType::Identifier(attr) = self.peek().token_type.clone() else {
+                return Err(ParseError::UnexpectedToken(self.peek().clone()));
+            };
+            self.advance();
+            match attr.as_str() {
+                "union" => union = true,
+                "packed" => layout.packed = true,
+                "align" => layout.align = Some(self.align_attribute()?),
+                _ => {
+                    return Err(ParseError::Expected {
+                        expected: TokenType::Identifier("struct attribute".to_string()),
+                        found: self.previous().clone(),
+                        message: format!("'@{attr}' cannot be applied to a struct"),
+                    });
+                }
+            }
+            while self.match_token(&[TokenType::Newline]) {}
+        }
+        self.consume(TokenType::Struct, "Expected 'struct' after struct attributes")?;
+        self.class_dec(union, layout)
+    }
+
+    /// `@align(16)`, after the name.
+    fn align_attribute(&mut self) -> Result<usize, ParseError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after @align")?;
+        let TokenType::IntLiteral(align) = self.peek().token_type else {
+            return Err(ParseError::UnexpectedToken(self.peek().clone()));
+        };
+        self.advance();
+        self.consume(TokenType::RightParen, "Expected ')' after alignment")?;
+        Ok(align.max(0) as usize)
+    }
+
+    fn class_dec(&mut self, union: bool, mut layout: StructLayout) -> Result<Stmt, ParseError> {
         let name_tok = self.consume(TokenType::Identifier("".into()), "Expected class name")?;
         let class_name = if let TokenType::Identifier(n) = &name_tok.token_type {
             n.clone()
@@ -694,6 +931,20 @@ impl Parser {
             )?;
         }
 
+        // Forward declaration; without a full declaration the struct stays opaque
+        if self.check(&TokenType::Semicolon) {
+            self.advance();
+            self.current_generics = HashSet::new();
+            return Ok(Stmt::StructDecl {
+                name: class_name,
+                instances: Vec::new(),
+                generics,
+                union,
+                opaque: true,
+                layout,
+            });
+        }
+
         self.consume(TokenType::LeftBrace, "Expected '{' after class name")?;
 
         let mut fields = Vec::new();
@@ -703,6 +954,21 @@ impl Parser {
                 self.consume(TokenType::Newline, "unexpected error parsing struct fields")?;
                 continue;
             }
+            let align = if self.match_token(&[TokenType::At]) {
+                if self.peek().token_type != TokenType::Identifier("align".into()) {
+                    return Err(ParseError::Expected {
+                        expected: TokenType::Identifier("align".to_string()),
+                        found: self.peek().clone(),
+                        message: "Only @align can be applied to a field".to_string(),
+                    });
+                }
+                self.advance();
+                let align = self.align_attribute()?;
+                while self.match_token(&[TokenType::Newline]) {}
+                Some(align)
+            } else {
+                None
+            };
             let field_name_tok =
                 self.consume(TokenType::Identifier("".into()), "Expected field name")?;
 
@@ -716,6 +982,9 @@ impl Parser {
             let ty = self.parse_type()?;
 
             self.consume(TokenType::Semicolon, "Expected ';' after field declaration")?;
+            if let Some(align) = align {
+                layout.field_align.insert(field_name.clone(), align);
+            }
             fields.push((field_name, ty));
         }
 
@@ -727,10 +996,116 @@ impl Parser {
             name: class_name,
             instances: fields,
             generics,
-            union: false,
+            union,
+            opaque: false,
+            layout,
         })
     }
 
+    /// `@format(printf, 1)` -- kept as the attribute string `format(printf,1)`.
+    fn format_attribute(&mut self) -> Result<String, ParseError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after @format")?;
+        let TokenType::Identifier(style) = self.peek().token_type.clone() else {
+            return Err(ParseError::UnexpectedToken(self.peek().clone()));
+        };
+        self.advance();
+        self.consume(TokenType::Comma, "Expected ',' after format style")?;
+        let TokenType::IntLiteral(index) = self.peek().token_type else {
+            return Err(ParseError::UnexpectedToken(self.peek().clone()));
+        };
+        self.advance();
+        self.consume(TokenType::RightParen, "Expected ')' after format index")?;
+        Ok(format!("format({style},{index})"))
+    }
+
+    /// `@export` or `@export("c_name")`.
+    fn export_attribute(&mut self) -> Result<String, ParseError> {
+        if !self.match_token(&[TokenType::LeftParen]) {
+            return Ok("export".to_string());
+        }
+        let TokenType::StringLiteral(symbol) = self.peek().token_type.clone() else {
+            return Err(ParseError::UnexpectedToken(self.peek().clone()));
+        };
+        self.advance();
+        self.consume(TokenType::RightParen, "Expected ')' after export name")?;
+        Ok(format!("export({symbol})"))
+    }
+
+    /// `name(a: T, b: U)` of a function declaration. A trailing `...` marks
+    /// the function `variadic`.
+    fn fn_signature(
+        &mut self,
+        attributes: &mut Vec<String>,
+    ) -> Result<(String, Vec<(String, Type)>), ParseError> {
+        let name = self.consume(TokenType::Identifier("".into()), "Expected function name")?;
+        let fun_name = match &name.token_type {
+            TokenType::Identifier(n) => n.clone(),
+            _ => return Err(ParseError::UnexpectedToken(name.clone())),
+        };
+
+        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
+
+        let mut parameters = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if self.match_token(&[TokenType::Period]) {
+                    self.consume(TokenType::Period, "Expected '...'")?;
+                    self.consume(TokenType::Period, "Expected '...'")?;
+                    if !attributes.iter().any(|a| a == "variadic") {
+                        attributes.push("variadic".to_string());
+                    }
+                    break;
+                }
+
+                let param_name =
+                    self.consume(TokenType::Identifier("".into()), "Expected parameter name")?;
+                let param_name_str = match &param_name.token_type {
+                    TokenType::Identifier(n) => n.clone(),
+                    _ => return Err(ParseError::UnexpectedToken(param_name.clone())),
+                };
+
+                self.consume(TokenType::Colon, "Expected ':' after parameter name")?;
+                let param_type = self.parse_type()?;
+                parameters.push((param_name_str, param_type));
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        Ok((fun_name, parameters))
+    }
+
+    /// `@extern def name(params) :: ret;` -- a C function's signature, kept as
+    /// a body-less `FunDecl` inside the `extern` declaration.
+    fn extern_prototype(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::Def, "Expected 'def' keyword")?;
+        let mut attributes = vec!["extern".to_string()];
+        let (name, params) = self.fn_signature(&mut attributes)?;
+
+        let return_type = if self.match_token(&[TokenType::DoubleColon]) {
+            self.parse_type()?
+        } else {
+            Type::Void
+        };
+        self.consume(TokenType::Semicolon, "Expected ';' after extern prototype")?;
+
+        Ok(Stmt::AtDecl(
+            "extern".to_string(),
+            Some(name.clone()),
+            None,
+            Some(Box::new(Stmt::FunDecl {
+                name,
+                params,
+                return_type,
+                body: Vec::new(),
+                attributes,
+            })),
+        ))
+    }
+
     fn fn_dec(&mut self) -> Result<Stmt, ParseError> {
         // Collect attributes before the function declaration
         let mut attributes = Vec::new();
@@ -740,6 +1115,18 @@ impl Parser {
             if let TokenType::Identifier(attr) = &self.peek().token_type.clone() {
                 match attr.as_str() {
                     "variadic" | "trust_ret" | "inline" | "no_frame" => {}
+                    "format" => {
+                        self.advance();
+                        attributes.push(self.format_attribute()?);
+                        while self.match_token(&[TokenType::Newline]) {}
+                        continue;
+                    }
+                    "export" => {
+                        self.advance();
+                        attributes.push(self.export_attribute()?);
+                        while self.match_token(&[TokenType::Newline]) {}
+                        continue;
+                    }
                     _ => continue,
                 };
                 self.advance();
@@ -767,35 +1154,7 @@ impl Parser {
             self.consume(TokenType::Def, "Expected 'def' keyword")?;
         }
 
-        let name = self.consume(TokenType::Identifier("".into()), "Expected function name")?;
-        let fun_name = match &name.token_type {
-            TokenType::Identifier(n) => n.clone(),
-            _ => return Err(ParseError::UnexpectedToken(name.clone())),
-        };
-
-        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
-
-        let mut parameters = Vec::new();
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                let param_name =
-                    self.consume(TokenType::Identifier("".into()), "Expected parameter name")?;
-                let param_name_str = match &param_name.token_type {
-                    TokenType::Identifier(n) => n.clone(),
-                    _ => return Err(ParseError::UnexpectedToken(param_name.clone())),
-                };
-
-                self.consume(TokenType::Colon, "Expected ':' after parameter name")?;
-                let param_type = self.parse_type()?;
-                parameters.push((param_name_str, param_type));
-
-                if !self.match_token(&[TokenType::Comma]) {
-                    break;
-                }
-            }
-        }
-
-        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        let (fun_name, parameters) = self.fn_signature(&mut attributes)?;
 
         if let TokenType::LeftBrace = self.peek().token_type {
             self.advance();
@@ -843,7 +1202,47 @@ impl Parser {
         })
     }
 
-    fn while_statement(&mut self, is_for: bool) -> Result<Stmt, ParseError> {
+    fn jump_label(&mut self) -> Option<String> {
+        if let TokenType::Label(label) = &self.peek().token_type {
+            let label = label.clone();
+            self.advance();
+            return Some(label);
+        }
+        None
+    }
+
+    fn loop_statement(&mut self, label: Option<String>) -> Result<Stmt, ParseError> {
+        if self.match_token(&[TokenType::While]) {
+            return self.while_statement(false, label);
+        }
+        if self.match_token(&[TokenType::For]) {
+            return self.while_statement(true, label);
+        }
+        if self.match_token(&[TokenType::Loop]) {
+            let body = Box::new(self.statement(true, None)?);
+            return Ok(Stmt::While {
+                condition: Expr::BoolLiteral(true),
+                body,
+                label,
+            });
+        }
+        if self.match_token(&[TokenType::Do]) {
+            let body = Box::new(self.statement(true, None)?);
+            self.consume(TokenType::While, "Expected 'while' after do body")?;
+            self.consume(TokenType::LeftParen, "Expected '(' after 'while'")?;
+            let condition = self.expression()?;
+            self.consume(TokenType::RightParen, "Expected ')' after while condition")?;
+            self.consume(TokenType::Semicolon, "Expected ';' after do-while")?;
+            return Ok(Stmt::DoWhile {
+                body,
+                condition,
+                label,
+            });
+        }
+        Err(ParseError::UnexpectedToken(self.peek().clone()))
+    }
+
+    fn while_statement(&mut self, is_for: bool, label: Option<String>) -> Result<Stmt, ParseError> {
         self.consume(TokenType::LeftParen, "Expected '(' after 'while'")?;
         let condition = self.expression()?;
         if is_for {
@@ -853,15 +1252,20 @@ impl Parser {
                 match iter {
                     Expr::Assign { .. }
                     | Expr::DerefAssign { .. }
-                    | Expr::CompoundAssign { .. }
                     | Expr::PreIncrement { .. }
                     | Expr::PostIncrement { .. }
                     | Expr::PreDecrement { .. }
                     | Expr::PostDecrement { .. } => {
                         self.consume(TokenType::RightParen, "Expected ')' after while condition")?;
-                        let body = Box::new(self.statement(true, Some(iter))?);
-
-                        return Ok(Stmt::While { condition, body });
+                        let body = Box::new(self.statement(true, None)?);
+
+                        return Ok(Stmt::For {
+                            init: None,
+                            condition: Some(condition),
+                            update: Some(iter),
+                            body,
+                            label,
+                        });
                     }
                     _ => return Err(ParseError::UnexpectedToken(self.peek().clone())),
                 }
@@ -875,7 +1279,11 @@ impl Parser {
             self.consume(TokenType::RightParen, "Expected ')' after while condition")?;
             let body = Box::new(self.statement(true, None)?);
 
-            Ok(Stmt::While { condition, body })
+            Ok(Stmt::While {
+                condition,
+                body,
+                label,
+            })
         }
     }
 
@@ -905,46 +1313,32 @@ impl Parser {
             TokenType::SlashEqual,
         ]) {
             let op_token = self.previous().token_type.clone();
-            let value = self.assignment()?;
+            let mut value = self.assignment()?;
+
+            // `x op= v` is `x = x op v`, evaluating the target twice
+            let op = match op_token {
+                TokenType::PlusEqual => Some(BinaryOp::Add),
+                TokenType::MinusEqual => Some(BinaryOp::Sub),
+                TokenType::StarEqual => Some(BinaryOp::Mul),
+                TokenType::SlashEqual => Some(BinaryOp::Div),
+                _ => None,
+            };
+            if let Some(op) = op {
+                value = Expr::Binary {
+                    left: Box::new(expr.clone()),
+                    op,
+                    right: Box::new(value),
+                    result_type: Type::Unknown,
+                };
+            }
 
             match expr {
-                Expr::Variable(name, _) => match op_token {
-                    TokenType::Equal => {
-                        return Ok(Expr::Assign {
-                            name,
-                            value: Box::new(value),
-                        });
-                    }
-                    TokenType::PlusEqual => {
-                        return Ok(Expr::CompoundAssign {
-                            name,
-                            op: BinaryOp::Add,
-                            value: Box::new(value),
-                        });
-                    }
-                    TokenType::MinusEqual => {
-                        return Ok(Expr::CompoundAssign {
-                            name,
-                            op: BinaryOp::Sub,
-                            value: Box::new(value),
-                        });
-                    }
-                    TokenType::StarEqual => {
-                        return Ok(Expr::CompoundAssign {
-                            name,
-                            op: BinaryOp::Mul,
-                            value: Box::new(value),
-                        });
-                    }
-                    TokenType::SlashEqual => {
-                        return Ok(Expr::CompoundAssign {
-                            name,
-                            op: BinaryOp::Div,
-                            value: Box::new(value),
-                        });
-                    }
-                    _ => unreachable!(),
-                },
+                Expr::Variable(name, _) => {
+                    return Ok(Expr::Assign {
+                        name,
+                        value: Box::new(value),
+                    });
+                }
 
                 Expr::Unary {
                     op: UnaryOp::Dereference,
@@ -956,12 +1350,12 @@ impl Parser {
                         value: Box::new(value),
                     });
                 }
-                Expr::InstanceVar(class_name, instance_name) => {
+                Expr::InstanceVar(base, field) => {
                     // return Ok(Expr::InstanceVar(class_name, instance_name));
 
                     return Ok(Expr::FieldAssign {
-                        class_name,
-                        field: instance_name,
+                        base,
+                        field,
                         value: Box::new(value),
                     });
 
@@ -1248,6 +1642,25 @@ impl Parser {
         loop {
             if self.match_token(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                self.consume(
+                    TokenType::RightBracket,
+                    "Right bracket expected for array indexing",
+                )?;
+                expr = Expr::ArrayAccess {
+                    array: Box::new(expr),
+                    index: Box::new(index),
+                };
+            } else if self.match_token(&[TokenType::Period]) {
+                // tuple elements are numbered: t.0
+                let field = match self.peek().token_type.clone() {
+                    TokenType::Identifier(field) => field,
+                    TokenType::IntLiteral(index) => index.to_string(),
+                    _ => return Err(ParseError::UnexpectedToken(self.peek().clone())),
+                };
+                self.advance();
+                expr = Expr::InstanceVar(Box::new(expr), field);
             } else if self.match_token(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
                 let op_token = self.previous().token_type.clone();
                 if let Expr::Variable(name, _) = expr {
@@ -1267,29 +1680,44 @@ impl Parser {
         Ok(expr)
     }
 
+    /// Parses one argument of `sizeof`/`alignof`/`offsetof`, which may be a type,
+    /// a bare name (struct or variable, decided by the analyzer) or an expression.
     fn sizeof_expression(&mut self) -> Result<Expr, ParseError> {
         match &self.peek().token_type {
-            TokenType::Char => Ok(Expr::SizeOf(SizeOf::Prim(Type::Char))),
-            TokenType::Int => Ok(Expr::SizeOf(SizeOf::Prim(Type::int))),
-            TokenType::Float => Ok(Expr::SizeOf(SizeOf::Prim(Type::float))),
-            TokenType::Boolean => Ok(Expr::SizeOf(SizeOf::Prim(Type::Bool))),
-            TokenType::StringLiteral(_) => Ok(Expr::SizeOf(SizeOf::Prim(Type::Pointer(Box::new(
-                Type::Char,
-            ))))),
-            TokenType::Identifier(ident) => Ok(Expr::SizeOf(SizeOf::Variable(ident.to_string()))),
-            _ => Err(ParseError::UnexpectedToken(self.peek().clone())),
+            TokenType::Char
+            | TokenType::Int
+            | TokenType::Float
+            | TokenType::Boolean
+            | TokenType::Long
+            | TokenType::Void
+            | TokenType::LeftBracket => Ok(Expr::SizeOf(SizeOf::Prim(self.parse_type()?))),
+            TokenType::Identifier(ident) => match self.peek_next().token_type {
+                TokenType::RightParen | TokenType::Comma => {
+                    let ident = ident.to_string();
+                    self.advance();
+                    Ok(Expr::SizeOf(SizeOf::Variable(ident)))
+                }
+                TokenType::Less => Ok(Expr::SizeOf(SizeOf::Prim(self.parse_type()?))),
+                TokenType::Star
+                    if self.current_generics.contains(ident.as_str())
+                        || self.stars_end_argument() =>
+                {
+                    Ok(Expr::SizeOf(SizeOf::Prim(self.parse_type()?)))
+                }
+                _ => Ok(Expr::SizeOf(SizeOf::Expr(Box::new(self.expression()?)))),
+            },
+            _ => Ok(Expr::SizeOf(SizeOf::Expr(Box::new(self.expression()?)))),
         }
     }
 
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
         if let Expr::Variable(name, _) = callee.clone()
-            && name == "sizeof"
+            && matches!(name.as_str(), "sizeof" | "alignof" | "offsetof" | "len")
         {
             let mut arguments = Vec::new();
             if !self.check(&TokenType::RightParen) {
                 loop {
                     arguments.push(self.sizeof_expression()?);
-                    self.advance();
                     if !self.match_token(&[TokenType::Comma]) {
                         break;
                     }
@@ -1358,6 +1786,10 @@ impl Parser {
                 self.advance();
                 Ok(Expr::BoolLiteral(false))
             }
+            TokenType::Null => {
+                self.advance();
+                Ok(Expr::NullLiteral)
+            }
             TokenType::CharLiteral(c) => {
                 let c = *c;
                 self.advance();
@@ -1391,6 +1823,16 @@ impl Parser {
                     }
                 }
 
+                // [value; count]
+                if elements.len() == 1 && self.match_token(&[TokenType::Semicolon]) {
+                    let count = self.expression()?;
+                    self.consume(TokenType::RightBracket, "Expected ']' after repeat count")?;
+                    return Ok(Expr::ArrayRepeat {
+                        value: Box::new(elements.pop().unwrap()),
+                        count: Box::new(count),
+                    });
+                }
+
                 self.consume(TokenType::RightBracket, "Expected ']' after array elements")?;
 
                 let element_type = elements.first().map_or(Type::Unknown, |e| match e {
@@ -1411,112 +1853,145 @@ impl Parser {
                 let name = name.clone();
                 self.advance();
 
+                if name == "new" && self.starts_type() {
+                    return self.new_expression();
+                }
+
                 if self.peek().token_type == TokenType::LeftBrace {
-                    let mut inits: Vec<(String, Expr)> = Vec::new();
                     self.advance();
+                    return self.struct_init(name);
+                }
 
-                    // self.consume(TokenType::LeftBrace, "Expected '(' to introduce class init")?;
-
-                    if !self.check(&TokenType::RightBrace) {
-                        loop {
-                            if let TokenType::Newline = self.peek().token_type {
-                                self.advance();
-                            }
-
-                            let fname_tok = self
-                                .consume(TokenType::Identifier("".into()), "Expected field name")?;
-                            let fname = if let TokenType::Identifier(n) = &fname_tok.token_type {
-                                n.clone()
-                            } else {
-                                return Err(ParseError::UnexpectedToken(fname_tok.clone()));
-                            };
-
-                            self.consume(TokenType::Colon, "Expected ':' after field name")?;
-                            let val = self.expression()?;
-                            inits.push((fname, val));
-
-                            if !self.match_token(&[TokenType::Comma]) {
-                                break;
-                            }
+                Ok(Expr::Variable(name.clone(), Type::Unknown))
+            }
+            TokenType::LeftParen => {
+                self.advance();
+                let expr = self.expression()?;
+                if self.match_token(&[TokenType::Comma]) {
+                    let mut elements = vec![expr];
+                    loop {
+                        elements.push(self.expression()?);
+                        if !self.match_token(&[TokenType::Comma]) {
+                            break;
                         }
                     }
+                    self.consume(TokenType::RightParen, "Expected ')' after tuple elements")?;
+                    return Ok(Expr::Tuple(elements));
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+                Ok(expr)
+            }
+            _ => Err(ParseError::UnexpectedToken(self.peek().clone())),
+        }
+    }
 
-                    if let TokenType::Newline = self.peek().token_type {
-                        self.advance();
-                    }
-
-                    self.consume(
-                        TokenType::RightBrace,
-                        "Expected ')' after class initializer",
-                    )?;
+    /// A struct literal's field initializers, after its `{`. An empty literal
+    /// or a trailing `..default` zero-fills the fields that aren't listed.
+    fn struct_init(&mut self, name: String) -> Result<Expr, ParseError> {
+        let mut inits: Vec<(String, Expr)> = Vec::new();
+        let mut default = false;
 
-                    return Ok(Expr::StructInit {
-                        name,
-                        params: inits,
-                    });
-                }
+        // self.consume(TokenType::LeftBrace, "Expected '(' to introduce class init")?;
 
-                if self.peek().token_type == TokenType::LeftBracket {
-                    let array = Box::new(Expr::Variable(name.clone(), Type::Unknown));
+        if !self.check(&TokenType::RightBrace) {
+            loop {
+                if let TokenType::Newline = self.peek().token_type {
                     self.advance();
-                    let peeked = self.peek().clone().token_type;
-                    match peeked {
-                        TokenType::IntLiteral(index) => {
-                            self.advance();
+                }
 
-                            self.consume(
-                                TokenType::RightBracket,
-                                "Right bracket expected for array indexing",
-                            )?;
+                if self.match_token(&[TokenType::Period]) {
+                    self.consume(TokenType::Period, "Expected '..default'")?;
+                    match &self.peek().token_type {
+                        TokenType::Identifier(word) if word == "default" => self.advance(),
+                        _ => return Err(ParseError::UnexpectedToken(self.peek().clone())),
+                    };
+                    default = true;
+                    break;
+                }
 
-                            return Ok(Expr::ArrayAccess {
-                                array,
-                                index: Box::new(Expr::IntLiteral(index)),
-                            });
-                        }
-                        TokenType::Identifier(name) => {
-                            self.advance();
+                let fname_tok = self
+                    .consume(TokenType::Identifier("".into()), "Expected field name")?;
+                let fname = if let TokenType::Identifier(n) = &fname_tok.token_type {
+                    n.clone()
+                } else {
+                    return Err(ParseError::UnexpectedToken(fname_tok.clone()));
+                };
 
-                            self.consume(
-                                TokenType::RightBracket,
-                                "Right bracket expected for array indexing",
-                            )?;
+                self.consume(TokenType::Colon, "Expected ':' after field name")?;
+                let val = self.expression()?;
+                inits.push((fname, val));
 
-                            return Ok(Expr::ArrayAccess {
-                                array,
-                                index: Box::new(Expr::Variable(name.to_string(), Type::Unknown)),
-                            });
-                        }
-                        _ => {
-                            return Err(ParseError::UnexpectedToken(self.peek().clone()));
-                        }
-                    }
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
                 }
+            }
+        }
 
-                if self.peek().token_type == TokenType::Period {
-                    self.advance();
-                    if let TokenType::Identifier(var_name) = &self.peek().token_type.clone() {
-                        self.consume(
-                            TokenType::Identifier(var_name.to_string()),
-                            "Could not find instance",
-                        )?;
-                        return Ok(Expr::InstanceVar(name, var_name.to_string()));
-                    }
-                }
+        if let TokenType::Newline = self.peek().token_type {
+            self.advance();
+        }
 
-                Ok(Expr::Variable(name.clone(), Type::Unknown))
-            }
-            TokenType::LeftParen => {
-                self.advance();
-                let expr = self.expression()?;
-                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-                Ok(expr)
+        self.consume(
+            TokenType::RightBrace,
+            "Expected ')' after class initializer",
+        )?;
+
+        Ok(Expr::StructInit {
+            default: default || inits.is_empty(),
+            name,
+            params: inits,
+        })
+    }
+
+    /// `new Struct { field: value, .. }` or `new T[count]`, after `new`.
+    fn new_expression(&mut self) -> Result<Expr, ParseError> {
+        let ty = self.parse_base_type()?;
+        if self.match_token(&[TokenType::LeftBracket]) {
+            let count = self.expression()?;
+            self.consume(TokenType::RightBracket, "Expected ']' after element count")?;
+            return Ok(Expr::NewArray {
+                elem: ty,
+                count: Box::new(count),
+            });
+        }
+
+        match ty {
+            Type::Struct { name, generics, .. }
+                if generics.is_empty() && self.match_token(&[TokenType::LeftBrace]) =>
+            {
+                Ok(Expr::New(Box::new(self.struct_init(name)?)))
             }
             _ => Err(ParseError::UnexpectedToken(self.peek().clone())),
         }
     }
 
-    fn _peek_next(&mut self) -> &Token {
+    /// Whether the next token can begin a type.
+    fn starts_type(&self) -> bool {
+        matches!(
+            self.peek().token_type,
+            TokenType::Identifier(_)
+                | TokenType::Int
+                | TokenType::Float
+                | TokenType::Char
+                | TokenType::Boolean
+                | TokenType::Long
+        )
+    }
+
+    /// Whether the `*`s after the identifier at the cursor run up to the end of
+    /// a sizeof argument, as in `Node*`, rather than multiplying as in `n * 4`.
+    fn stars_end_argument(&self) -> bool {
+        let mut i = self.current + 1;
+        while matches!(self.tokens.get(i), Some(t) if t.token_type == TokenType::Star) {
+            i += 1;
+        }
+        matches!(
+            self.tokens.get(i).map(|t| &t.token_type),
+            Some(TokenType::RightParen | TokenType::Comma)
+        )
+    }
+
+    fn peek_next(&self) -> &Token {
         if self.current + 1 >= self.tokens.len() {
             &self.tokens[self.current]
         } else {
@@ -1545,7 +2020,44 @@ impl Parser {
         Ok(statements)
     }
 
+    /// A type, including C-style array dimensions: `int[4][4]` is
+    /// `[[int, 4], 4]` and `int[10]*` points to a `[int, 10]`.
     fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let elem = self.parse_base_type()?;
+        if !self.check(&TokenType::LeftBracket) {
+            return Ok(elem);
+        }
+
+        let mut dims = Vec::new();
+        while self.match_token(&[TokenType::LeftBracket]) {
+            dims.push(self.array_size()?);
+        }
+        // The first dimension is the outermost
+        let mut ty = elem;
+        for size in dims.into_iter().rev() {
+            ty = array_of(ty, size);
+        }
+
+        while self.match_token(&[TokenType::Star]) {
+            ty = Type::Pointer(Box::new(ty));
+        }
+        Ok(ty)
+    }
+
+    /// The `N]` that closes an array type.
+    fn array_size(&mut self) -> Result<Expr, ParseError> {
+        let size_tok = self.peek().clone();
+        let size = self.expression()?;
+        self.consume(TokenType::RightBracket, "Expected ']' after array size")?;
+
+        match size {
+            Expr::IntLiteral(n) if n < 0 => Err(ParseError::UnexpectedToken(size_tok)),
+            size => Ok(size),
+        }
+    }
+
+    /// A type without trailing array dimensions.
+    fn parse_base_type(&mut self) -> Result<Type, ParseError> {
         if self.match_token(&[TokenType::LeftBracket]) {
             let elem = self.parse_type()?;
 
@@ -1558,17 +2070,29 @@ impl Parser {
                 "Expected ',' after element type or ']' for slice",
             )?;
 
-            let size_tok = self.consume(TokenType::IntLiteral(0), "Expected array size")?;
-            let size = match size_tok.token_type {
-                TokenType::IntLiteral(n) => n as usize,
-                _ => return Err(ParseError::UnexpectedToken(size_tok.clone())),
-            };
-            self.consume(TokenType::RightBracket, "Expected ']' after array size")?;
-            return Ok(Type::Array(Box::new(elem), Some(size)));
+            let size = self.array_size()?;
+            let mut ty = array_of(elem, size);
+            while self.match_token(&[TokenType::Star]) {
+                ty = Type::Pointer(Box::new(ty));
+            }
+            return Ok(ty);
         }
 
         let token_type = &self.peek().token_type;
         let mut base_type = match token_type {
+            TokenType::LeftParen => {
+                self.advance();
+                let mut elements = vec![self.parse_type()?];
+                while self.match_token(&[TokenType::Comma]) {
+                    elements.push(self.parse_type()?);
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after tuple type")?;
+                if elements.len() == 1 {
+                    elements.pop().unwrap()
+                } else {
+                    Type::Tuple(elements)
+                }
+            }
             TokenType::Int => {
                 self.advance();
                 Type::int
@@ -1635,6 +2159,15 @@ impl Parser {
     }
 }
 
+/// An array of `size` elements; anything other than a plain literal is
+/// folded by the analyzer.
+fn array_of(elem: Type, size: Expr) -> Type {
+    match size {
+        Expr::IntLiteral(n) => Type::Array(Box::new(elem), Some(n as usize)),
+        size => Type::ArrayLen(Box::new(elem), Box::new(size)),
+    }
+}
+
 #[derive(Debug)]
 pub enum ParseError {
     Expected {
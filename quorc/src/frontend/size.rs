@@ -1,7 +1,8 @@
-use crate::frontend::ast::Type;
+use crate::frontend::ast::{Expr, Type};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum SizeOf {
     Variable(String),
     Prim(Type),
+    Expr(Box<Expr>),
 }
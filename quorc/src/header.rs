@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::frontend::ast::{Stmt, StructLayout, Type};
+use crate::midend::analyzer::mangle_name;
+
+/// Fields, `union`-ness and padding controls of each struct, by name. Opaque
+/// structs have no fields.
+type Structs<'a> = HashMap<&'a str, (Option<&'a [(String, Type)]>, bool, &'a StructLayout)>;
+
+/// Builds a C header declaring the `@export` functions of a typed program,
+/// along with every struct their signatures reach.
+pub fn c_header(program: &[Stmt], guard: &str) -> Result<String, String> {
+    let mut structs: Structs = HashMap::new();
+    for stmt in program {
+        if let Stmt::StructDecl {
+            name,
+            instances,
+            union,
+            opaque,
+            layout,
+            ..
+        } = stmt
+        {
+            if *opaque {
+                structs
+                    .entry(name.as_str())
+                    .or_insert((None, *union, layout));
+            } else {
+                structs.insert(name.as_str(), (Some(instances.as_slice()), *union, layout));
+            }
+        }
+    }
+
+    let mut prototypes = Vec::new();
+    let mut roots = Vec::new();
+    for stmt in program {
+        let Stmt::FunDecl {
+            name,
+            params,
+            return_type,
+            attributes,
+            ..
+        } = stmt
+        else {
+            continue;
+        };
+        let Some(symbol) = attributes.iter().find_map(|attr| {
+            if attr == "export" {
+                Some(name.as_str())
+            } else {
+                attr.strip_prefix("export(")?.strip_suffix(')')
+            }
+        }) else {
+            continue;
+        };
+
+        let mut decls = Vec::new();
+        for (param, ty) in params {
+            // Arrays decay to pointers at the call boundary
+            let ty = match ty {
+                Type::Array(elem, _) => Type::Pointer(elem.clone()),
+                ty => ty.clone(),
+            };
+            decls.push(c_decl(&ty, param, false)?);
+            roots.push(ty);
+        }
+        if decls.is_empty() {
+            decls.push("void".to_string());
+        }
+        prototypes.push(format!(
+            "{};",
+            c_decl(
+                return_type,
+                &format!("{symbol}({})", decls.join(", ")),
+                false
+            )?
+        ));
+        roots.push(return_type.clone());
+    }
+
+    // Every struct gets a typedef up front, so pointers to it need no
+    // ordering; by-value fields are defined before the structs holding them
+    let mut emitted = HashSet::new();
+    let mut definitions = Vec::new();
+    let mut reached = Vec::new();
+    for ty in &roots {
+        define_structs(ty, &structs, &mut emitted, &mut reached, &mut definitions)?;
+    }
+
+    let mut out = format!("#ifndef {guard}\n#define {guard}\n\n");
+    out.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+    for name in &reached {
+        let keyword = if structs[name.as_str()].1 {
+            "union"
+        } else {
+            "struct"
+        };
+        let name = c_name(name);
+        out.push_str(&format!("typedef {keyword} {name} {name};\n"));
+    }
+    if !reached.is_empty() {
+        out.push('\n');
+    }
+    for definition in definitions {
+        out.push_str(&definition);
+        out.push('\n');
+    }
+    for prototype in prototypes {
+        out.push_str(&prototype);
+        out.push('\n');
+    }
+    out.push_str(&format!("\n#endif // {guard}\n"));
+
+    Ok(out)
+}
+
+fn define_structs(
+    ty: &Type,
+    structs: &Structs,
+    emitted: &mut HashSet<String>,
+    reached: &mut Vec<String>,
+    definitions: &mut Vec<String>,
+) -> Result<(), String> {
+    match ty {
+        Type::Pointer(inner) | Type::Array(inner, _) => {
+            define_structs(inner, structs, emitted, reached, definitions)
+        }
+        Type::Struct { .. } | Type::StructLiteral(_) => {
+            let name = struct_name(ty);
+            if !emitted.insert(name.clone()) {
+                return Ok(());
+            }
+            let &(fields, union, layout) = structs
+                .get(name.as_str())
+                .ok_or_else(|| format!("Unknown struct '{name}' in exported signature"))?;
+            reached.push(name.clone());
+            // Only the typedef is emitted, so C can hold pointers to it
+            let Some(fields) = fields else {
+                return Ok(());
+            };
+
+            let mut body = Vec::new();
+            for (field, ty) in fields {
+                define_structs(ty, structs, emitted, reached, definitions)?;
+                let decl = c_decl(ty, field, true)?;
+                match layout.field_align.get(field) {
+                    Some(n) => body.push(format!("    {decl} __attribute__((aligned({n})));\n")),
+                    None => body.push(format!("    {decl};\n")),
+                }
+            }
+            let keyword = if union { "union" } else { "struct" };
+            let mut attributes = Vec::new();
+            if layout.packed {
+                attributes.push("packed".to_string());
+            }
+            if let Some(n) = layout.align {
+                attributes.push(format!("aligned({n})"));
+            }
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" __attribute__(({}))", attributes.join(", "))
+            };
+            definitions.push(format!(
+                "{keyword} {} {{\n{}}}{attributes};\n",
+                c_name(&name),
+                body.concat()
+            ));
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn struct_name(ty: &Type) -> String {
+    match ty {
+        Type::Struct { name, generics, .. } if !generics.is_empty() => mangle_name(name, generics),
+        Type::Struct { name, .. } | Type::StructLiteral(name) => name.clone(),
+        _ => unreachable!(),
+    }
+}
+
+/// Mangled names such as `Pair.int` are not valid C identifiers.
+fn c_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// The C declaration of `declarator` with type `ty`. Floats are passed as
+/// doubles but stored in memory as 32-bit floats.
+fn c_decl(ty: &Type, declarator: &str, in_memory: bool) -> Result<String, String> {
+    let base = match ty {
+        Type::int => "int".to_string(),
+        Type::Long => "int64_t".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::float if in_memory => "float".to_string(),
+        Type::float => "double".to_string(),
+        Type::Void => "void".to_string(),
+        Type::Struct { .. } | Type::StructLiteral(_) => c_name(&struct_name(ty)),
+        Type::Pointer(inner) => {
+            // Parenthesize so pointers to arrays keep their meaning
+            let declarator = match **inner {
+                Type::Array(..) => format!("(*{declarator})"),
+                _ => format!("*{declarator}"),
+            };
+            return c_decl(inner, &declarator, true);
+        }
+        Type::Array(elem, Some(n)) => return c_decl(elem, &format!("{declarator}[{n}]"), true),
+        _ => {
+            return Err(format!(
+                "Type {ty:?} cannot be used in an exported declaration"
+            ));
+        }
+    };
+
+    Ok(if declarator.is_empty() {
+        base
+    } else {
+        format!("{base} {declarator}")
+    })
+}
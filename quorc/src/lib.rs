@@ -1,5 +1,7 @@
 pub mod backend;
 pub mod debug;
 pub mod frontend;
+pub mod header;
+pub mod lint;
 pub mod midend;
 pub mod target;
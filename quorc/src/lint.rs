@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedParameters,
+    UnusedFunctions,
+    UnusedImports,
+    UnusedConstants,
+    UnreachableCode,
+    DeadStores,
+    UseAfterFree,
+    DoubleFree,
+    MemoryLeak,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+static LEVELS: OnceLock<HashMap<Lint, Level>> = OnceLock::new();
+static DENIED: AtomicUsize = AtomicUsize::new(0);
+
+impl Lint {
+    pub const ALL: [Lint; 10] = [
+        Lint::UnusedVariables,
+        Lint::UnusedParameters,
+        Lint::UnusedFunctions,
+        Lint::UnusedImports,
+        Lint::UnusedConstants,
+        Lint::UnreachableCode,
+        Lint::DeadStores,
+        Lint::UseAfterFree,
+        Lint::DoubleFree,
+        Lint::MemoryLeak,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedParameters => "unused_parameters",
+            Lint::UnusedFunctions => "unused_functions",
+            Lint::UnusedImports => "unused_imports",
+            Lint::UnusedConstants => "unused_constants",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::DeadStores => "dead_stores",
+            Lint::UseAfterFree => "use_after_free",
+            Lint::DoubleFree => "double_free",
+            Lint::MemoryLeak => "memory_leak",
+        }
+    }
+
+    /// Whether `name` selects this lint, either directly or through the
+    /// `unused` / `warnings` groups. Dashes and underscores are interchangeable.
+    pub fn matches(self, name: &str) -> bool {
+        match name.replace('-', "_").as_str() {
+            "warnings" | "all" => true,
+            "unused" => self.name().starts_with("unused_") || self == Lint::DeadStores,
+            name => name == self.name(),
+        }
+    }
+}
+
+fn is_known(name: &str) -> bool {
+    Lint::ALL.iter().any(|lint| lint.matches(name))
+}
+
+/// Applies `-A`/`-W`/`-D` flags in order; later flags win.
+pub fn init_lints(flags: &[(Level, String)]) -> Result<(), String> {
+    let mut levels: HashMap<Lint, Level> = Lint::ALL.iter().map(|l| (*l, Level::Warn)).collect();
+
+    for (level, name) in flags {
+        if !is_known(name) {
+            return Err(format!("Unknown lint: '{name}'"));
+        }
+        for lint in Lint::ALL.iter().filter(|l| l.matches(name)) {
+            levels.insert(*lint, *level);
+        }
+    }
+
+    LEVELS.set(levels).ok();
+    Ok(())
+}
+
+/// Whether an `@allow(...)` attribute in `attributes` silences `lint`.
+pub fn is_allowed(lint: Lint, attributes: &[String]) -> bool {
+    attributes.iter().any(|attr| {
+        attr.strip_prefix("allow(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .is_some_and(|name| lint.matches(name))
+    })
+}
+
+pub fn emit(lint: Lint, attributes: &[String], message: &str) {
+    if is_allowed(lint, attributes) {
+        return;
+    }
+
+    let level = LEVELS
+        .get()
+        .and_then(|levels| levels.get(&lint).copied())
+        .unwrap_or(Level::Warn);
+    match level {
+        Level::Allow => {}
+        Level::Warn => eprintln!("warning[{}] :: {message}", lint.name()),
+        Level::Deny => {
+            eprintln!("error[{}] :: {message}", lint.name());
+            DENIED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn denied_count() -> usize {
+    DENIED.load(Ordering::Relaxed)
+}
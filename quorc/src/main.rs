@@ -1,9 +1,12 @@
 use quorc::backend::Codegen;
 use quorc::debug_log;
+use quorc::frontend::cimport::{find_header, init_include_dirs, translate_header};
 use quorc::frontend::{lexer::Lexer, parser::Parser};
+use quorc::header::c_header;
+use quorc::lint::{Level, denied_count, init_lints};
 use quorc::midend::analyzer::TypeChecker;
 use quorc::midend::mir::cfg::IRGenerator;
-use quorc::target::{in_debug_mode, init_target, target_arch, target_os};
+use quorc::target::{in_debug_mode, init_allocator, init_target, target_arch, target_os};
 
 use std::env;
 use std::fs;
@@ -173,7 +176,7 @@ pub fn build_link_run(
     )?;
 
     run(
-        Command::new("gcc").args(["-no-pie", obj.to_str().unwrap(), "-o".into(), out]),
+        Command::new("gcc").args(["-no-pie", obj.to_str().unwrap(), "-o", out]),
         &workdir,
     )?;
 
@@ -222,10 +225,44 @@ const FLAG_HELP: &[(&str, &str)] = &[
     ("--emit-ast", "Print the AST after parsing"),
     ("--emit-typed", "Print the typed AST after type checking"),
     ("--emit-mir", "Print the MIR after IR generation"),
+    (
+        "--emit-layout",
+        "Print the size, alignment and field offsets of every struct",
+    ),
     (
         "--emit-asm",
         "Print the generated assembly before assembling",
     ),
+    (
+        "--emit-header",
+        "Write a C header for the @export functions next to the source",
+    ),
+    (
+        "-I<dir>",
+        "Search <dir> for @cimport headers before the bundled lib/include",
+    ),
+    (
+        "bindgen <header.h>",
+        "Print the Quor declarations @cimport would generate for a C header",
+    ),
+    (
+        "--alloc-fn=<symbol>",
+        "Function `new` allocates with (default malloc)",
+    ),
+    (
+        "--free-fn=<symbol>",
+        "Function `delete` frees with (default free)",
+    ),
+    (
+        "-O<level>",
+        "Optimization level 0-2 (default 1); -O0 inlines nothing, -O1 inlines @inline functions, -O2 also small leaf functions",
+    ),
+    (
+        "-W<lint>",
+        "Warn on a lint (e.g. -Wunused-variables, -Wunused, -Wwarnings)",
+    ),
+    ("-A<lint>", "Allow a lint, silencing its warnings"),
+    ("-D<lint>", "Deny a lint, turning its warnings into errors"),
     (
         "--target-os=<os>",
         "Override the target OS (e.g. linux, macos)",
@@ -256,6 +293,11 @@ fn main() {
     let mut os_override = None;
     let mut arch_override = None;
     let mut debug_mode = false;
+    let mut opt_level = 1;
+    let mut lint_flags = Vec::new();
+    let mut include_dirs = Vec::new();
+    let mut alloc_override = None;
+    let mut free_override = None;
 
     for arg in compiler_args {
         if let Some(value) = arg.strip_prefix("--target-os=") {
@@ -264,12 +306,62 @@ fn main() {
         if let Some(value) = arg.strip_prefix("--target-arch=") {
             arch_override = Some(value.to_string());
         }
+        if let Some(value) = arg.strip_prefix("--alloc-fn=") {
+            alloc_override = Some(value.to_string());
+        }
+        if let Some(value) = arg.strip_prefix("--free-fn=") {
+            free_override = Some(value.to_string());
+        }
         if "--debug" == arg.as_str() {
             debug_mode = true;
         }
+        if let Some(level) = arg.strip_prefix("-O") {
+            opt_level = match level.parse::<u8>() {
+                Ok(level @ 0..=2) => level,
+                _ => {
+                    eprintln!("Invalid optimization level: {arg}");
+                    std::process::exit(1);
+                }
+            };
+        }
+        if let Some(dir) = arg.strip_prefix("-I") {
+            include_dirs.push(PathBuf::from(dir));
+        }
+        for (prefix, level) in [("-A", Level::Allow), ("-W", Level::Warn), ("-D", Level::Deny)] {
+            if let Some(lint) = arg.strip_prefix(prefix) {
+                lint_flags.push((level, lint.to_string()));
+            }
+        }
     }
 
-    init_target(arch_override, os_override, debug_mode);
+    if let Err(e) = init_lints(&lint_flags) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+
+    init_target(arch_override, os_override, debug_mode, opt_level);
+    init_allocator(alloc_override, free_override);
+    init_include_dirs(include_dirs);
+
+    if args[1] == "bindgen" {
+        let Some(name) = compiler_args.first() else {
+            eprintln!("Usage: quor bindgen <header.h>");
+            std::process::exit(1);
+        };
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let Some(path) = find_header(name, true, &cwd) else {
+            eprintln!("Header '{name}' not found in the include path");
+            std::process::exit(1);
+        };
+        match fs::read_to_string(&path) {
+            Ok(source) => print!("{}", translate_header(&source)),
+            Err(e) => {
+                eprintln!("Failed to read {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
     debug_log!(
         "target_arch" => target_arch(),
@@ -364,6 +456,23 @@ fn main() {
         println!("{:?}", typed);
     }
 
+    if compiler_args.contains(&"--emit-header".to_string()) {
+        let guard = format!(
+            "{}_H",
+            out_name
+                .to_uppercase()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+        let header = workdir.join(format!("{out_name}.h"));
+        if let Err(e) = c_header(&typed, &guard).and_then(|text| {
+            fs::write(&header, text)
+                .map_err(|e| format!("Failed to write {}: {e}", header.display()))
+        }) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+
     let mir_time = Instant::now();
 
     let mir = match IRGenerator::generate(typed) {
@@ -376,10 +485,19 @@ fn main() {
 
     debug_log!("mir" => format!("{:.2?}", mir_time.elapsed()));
 
+    if denied_count() > 0 {
+        eprintln!("aborting due to {} denied lint(s)", denied_count());
+        std::process::exit(1);
+    }
+
     if compiler_args.contains(&"--emit-mir".to_string()) {
         println!("{:?}", mir);
     }
 
+    if compiler_args.contains(&"--emit-layout".to_string()) {
+        print!("{}", mir.layout_report());
+    }
+
     let codegen_time = Instant::now();
 
     let asm = Codegen::generate(mir);
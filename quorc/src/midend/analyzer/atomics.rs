@@ -0,0 +1,112 @@
+use crate::{
+    frontend::ast::{Expr, Type},
+    midend::{
+        analyzer::{TypeChecker, base_type},
+        mir::block::MemOrder,
+    },
+};
+
+/// Number of value arguments following the pointer, and whether a memory
+/// order comes last, for each volatile/atomic builtin.
+pub(crate) fn memory_builtin(name: &str) -> Option<(usize, bool)> {
+    Some(match name {
+        "volatile_load" => (0, false),
+        "volatile_store" => (1, false),
+        "atomic_load" => (0, true),
+        "atomic_store" | "atomic_swap" | "atomic_fetch_add" => (1, true),
+        "atomic_compare_exchange" => (2, true),
+        _ => return None,
+    })
+}
+
+fn is_store(name: &str) -> bool {
+    name.ends_with("_store")
+}
+
+impl TypeChecker {
+    pub(crate) fn check_memory_builtin(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+    ) -> Result<Type, String> {
+        let (values, ordered) = memory_builtin(name).unwrap();
+        let expected = 1 + values + ordered as usize;
+        if args.len() != expected {
+            return Err(format!(
+                "Function '{name}' expected {expected} arguments, got {}",
+                args.len()
+            ));
+        }
+
+        let elem = match base_type(&self.type_check_expr(&args[0])?) {
+            Type::Pointer(elem) => *elem,
+            other => return Err(format!("'{name}' expects a pointer, found {other:?}")),
+        };
+        let integral = matches!(elem, Type::int | Type::Long | Type::Char);
+        if !(integral || matches!(elem, Type::Bool | Type::Pointer(_))) {
+            return Err(format!("'{name}' cannot access a value of type {elem:?}"));
+        }
+        if name == "atomic_fetch_add" && !integral {
+            return Err(format!(
+                "'{name}' expects a pointer to an integer, found {elem:?}"
+            ));
+        }
+
+        for arg in &args[1..=values] {
+            let ty = base_type(&self.type_check_expr(arg)?);
+            let widens = elem == Type::Long && matches!(ty, Type::int | Type::Char);
+            if ty != base_type(&elem) && !widens {
+                return Err(format!(
+                    "Argument type mismatch in call to '{name}': expected {elem:?}, got {ty:?}"
+                ));
+            }
+        }
+
+        if ordered {
+            let order = self.memory_order(&args[expected - 1])?;
+            let invalid = match name {
+                "atomic_load" => order.releases() && order != MemOrder::SeqCst,
+                "atomic_store" => order.acquires() && order != MemOrder::SeqCst,
+                _ => false,
+            };
+            if invalid {
+                return Err(format!("'{name}' cannot use memory order {order:?}"));
+            }
+        }
+
+        Ok(if is_store(name) { Type::Void } else { elem })
+    }
+
+    /// Folds the memory order down to a literal for the midend.
+    pub(crate) fn fill_memory_builtin(
+        &mut self,
+        name: &str,
+        args: &mut [Expr],
+        return_type: &mut Type,
+    ) {
+        for arg in args.iter_mut() {
+            self.fill_expr_types(arg);
+        }
+        if let (Some((_, true)), Some(order)) = (memory_builtin(name), args.last_mut()) {
+            // Already validated by check_memory_builtin
+            let index = self.eval_const(order).unwrap().as_i64().unwrap();
+            *order = Expr::IntLiteral(index as i32);
+        }
+        *return_type = match args[0].get_type() {
+            _ if is_store(name) => Type::Void,
+            Type::Pointer(elem) => *elem,
+            other => other,
+        };
+    }
+
+    fn memory_order(&mut self, expr: &Expr) -> Result<MemOrder, String> {
+        self.eval_const(expr)
+            .ok()
+            .and_then(|value| value.as_i64())
+            .and_then(MemOrder::from_index)
+            .ok_or_else(|| {
+                "Memory order must be one of the constants RELAXED, ACQUIRE, RELEASE, ACQ_REL or SEQ_CST"
+                    .to_string()
+            })
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{frontend::ast::Type, midend::analyzer::converts_implicitly};
+
+/// int, long and char; char (like bool) is unsigned.
+pub(crate) fn is_integer(ty: &Type) -> bool {
+    matches!(ty, Type::int | Type::Long | Type::Char)
+}
+
+/// The conversions `expr as T` allows:
+/// * integer to integer: widening sign-extends int and zero-extends char, narrowing truncates
+/// * bool to integer gives 0 or 1, integer to bool tests for nonzero
+/// * integer to float, and float to integer truncating toward zero
+/// * int or long to pointer and back
+/// * pointer to any other pointer, and `null` or an array to a pointer
+pub(crate) fn check_cast(from: &Type, to: &Type) -> Result<(), String> {
+    let allowed = match (from, to) {
+        _ if from == to || converts_implicitly(from, to) => true,
+        _ if is_integer(from) && is_integer(to) => true,
+        // untyped values take the type they are cast to
+        (Type::Void, Type::int | Type::Bool | Type::Char | Type::float | Type::Struct { .. }) => {
+            true
+        }
+        (Type::Bool, other) | (other, Type::Bool) => is_integer(other),
+        (Type::float, other) | (other, Type::float) => is_integer(other),
+        (Type::int | Type::Long, Type::Pointer(_)) | (Type::Pointer(_), Type::int | Type::Long) => {
+            true
+        }
+        (Type::Pointer(_), Type::Pointer(_)) => true,
+        _ => false,
+    };
+    if allowed {
+        return Ok(());
+    }
+    let hint = match (from, to) {
+        (Type::Char | Type::Bool, Type::Pointer(_))
+        | (Type::Pointer(_), Type::Char | Type::Bool) => {
+            "; only int and long convert to and from pointers"
+        }
+        (Type::float, Type::Pointer(_)) | (Type::Pointer(_), Type::float) => {
+            "; convert through long first"
+        }
+        _ => "",
+    };
+    Err(format!("Invalid cast from {from:?} to {to:?}{hint}"))
+}
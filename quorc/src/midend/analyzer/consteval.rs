@@ -0,0 +1,526 @@
+use crate::{
+    frontend::ast::{BinaryOp, Expr, Stmt, Type, UnaryOp},
+    midend::analyzer::{TypeChecker, casts::check_cast},
+};
+
+/// A value known at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i32),
+    Long(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+impl ConstValue {
+    pub fn get_type(&self) -> Type {
+        match self {
+            ConstValue::Int(_) => Type::int,
+            ConstValue::Long(_) => Type::Long,
+            ConstValue::Float(_) => Type::float,
+            ConstValue::Bool(_) => Type::Bool,
+            ConstValue::Char(_) => Type::Char,
+            ConstValue::Str(_) => Type::Pointer(Box::new(Type::Char)),
+        }
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        match self {
+            ConstValue::Int(i) => Expr::IntLiteral(*i),
+            ConstValue::Long(l) => Expr::LongLiteral(*l),
+            ConstValue::Float(f) => Expr::FloatLiteral(*f),
+            ConstValue::Bool(b) => Expr::BoolLiteral(*b),
+            ConstValue::Char(c) => Expr::CharLiteral(*c),
+            ConstValue::Str(s) => Expr::StringLiteral(s.clone()),
+        }
+    }
+
+    /// Integer view of the value, used for array lengths and casts.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ConstValue::Int(i) => Some(*i as i64),
+            ConstValue::Long(l) => Some(*l),
+            ConstValue::Char(c) => Some(*c as i64),
+            ConstValue::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+}
+
+impl TypeChecker {
+    /// Evaluates every `@const` in the program, replacing each definition with
+    /// its folded literal so the midend only ever sees plain values.
+    pub(crate) fn fold_global_consts(&mut self, program: &mut [Stmt]) -> Result<(), String> {
+        for stmt in program.iter() {
+            if let Stmt::AtDecl(decl, Some(name), Some(val), _) = stmt
+                && decl.as_str() == "const"
+                && self.const_defs.insert(name.clone(), val.clone()).is_some()
+            {
+                return Err(format!("Constant '{name}' already defined"));
+            }
+        }
+
+        for stmt in program.iter_mut() {
+            if let Stmt::AtDecl(decl, Some(name), val, _) = stmt
+                && decl.as_str() == "const"
+            {
+                let expr = val
+                    .take()
+                    .ok_or_else(|| format!("Missing value for '{name}'"))?;
+
+                let folded = match expr {
+                    Expr::StructInit {
+                        name: sname,
+                        params,
+                        default,
+                    } => {
+                        let params = params
+                            .into_iter()
+                            .map(|(field, e)| Ok((field, self.fold_literal(e)?)))
+                            .collect::<Result<Vec<_>, String>>()?;
+                        Expr::StructInit {
+                            name: sname,
+                            params,
+                            default,
+                        }
+                    }
+                    Expr::Array(elems, _) => {
+                        let elems = elems
+                            .into_iter()
+                            .map(|e| self.fold_literal(e))
+                            .collect::<Result<Vec<_>, String>>()?;
+                        let elem_ty = elems.first().map_or(Type::Unknown, |e| e.get_type());
+                        Expr::Array(elems, elem_ty)
+                    }
+                    _ => self.eval_const_named(name)?.to_expr(),
+                };
+
+                let ty = self.type_check_expr(&folded)?;
+                self.globals.insert(name.clone(), ty);
+                *val = Some(folded);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fold_literal(&mut self, expr: Expr) -> Result<Expr, String> {
+        match expr {
+            Expr::StructInit { .. } | Expr::Array(..) => Ok(expr),
+            _ => Ok(self.eval_const(&expr)?.to_expr()),
+        }
+    }
+
+    fn eval_const_named(&mut self, name: &str) -> Result<ConstValue, String> {
+        if let Some(value) = self.const_values.get(name) {
+            return Ok(value.clone());
+        }
+
+        let expr = self
+            .const_defs
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("'{name}' is not a compile-time constant"))?;
+
+        if !self.const_in_progress.insert(name.to_string()) {
+            return Err(format!("Constant '{name}' is defined in terms of itself"));
+        }
+        let value = self.eval_const(&expr);
+        self.const_in_progress.remove(name);
+
+        let value = value.map_err(|e| format!("In constant '{name}': {e}"))?;
+        self.const_values.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    pub(crate) fn eval_const(&mut self, expr: &Expr) -> Result<ConstValue, String> {
+        match expr {
+            Expr::IntLiteral(i) => Ok(ConstValue::Int(*i)),
+            Expr::LongLiteral(l) => Ok(ConstValue::Long(*l)),
+            Expr::FloatLiteral(f) => Ok(ConstValue::Float(*f)),
+            Expr::BoolLiteral(b) => Ok(ConstValue::Bool(*b)),
+            Expr::CharLiteral(c) => Ok(ConstValue::Char(*c)),
+            Expr::StringLiteral(s) => Ok(ConstValue::Str(s.clone())),
+            Expr::Variable(name, _) => {
+                self.note_use(name);
+                self.eval_const_named(name)
+            }
+            Expr::Call { name, args, .. }
+                if matches!(name.as_str(), "sizeof" | "alignof" | "offsetof" | "len") =>
+            {
+                let value = self.eval_layout_query(name, args)?;
+                i32::try_from(value)
+                    .map(ConstValue::Int)
+                    .map_err(|_| format!("{name} result overflows int"))
+            }
+            Expr::Unary { op, expr, .. } => {
+                let value = self.eval_const(expr)?;
+                match (op, value) {
+                    (UnaryOp::Negate, ConstValue::Int(i)) => i
+                        .checked_neg()
+                        .map(ConstValue::Int)
+                        .ok_or_else(|| format!("Overflow negating {i}")),
+                    (UnaryOp::Negate, ConstValue::Long(l)) => l
+                        .checked_neg()
+                        .map(ConstValue::Long)
+                        .ok_or_else(|| format!("Overflow negating {l}")),
+                    (UnaryOp::Negate, ConstValue::Float(f)) => Ok(ConstValue::Float(-f)),
+                    (UnaryOp::Not, ConstValue::Bool(b)) => Ok(ConstValue::Bool(!b)),
+                    (op, value) => Err(format!(
+                        "Operator {op:?} cannot be applied to constant {value:?}"
+                    )),
+                }
+            }
+            Expr::Binary {
+                left, op, right, ..
+            } => {
+                let left = self.eval_const(left)?;
+                let right = self.eval_const(right)?;
+                eval_binary(op, left, right)
+            }
+            Expr::Cast { expr, target_type } => {
+                let value = self.eval_const(expr)?;
+                eval_cast(value, target_type)
+            }
+            other => Err(format!(
+                "Expression is not a compile-time constant: {other:?}"
+            )),
+        }
+    }
+
+    /// Replaces every `ArrayLen` in `ty` with a sized `Array`.
+    pub(crate) fn resolve_type(&mut self, ty: &Type) -> Result<Type, String> {
+        match ty {
+            Type::ArrayLen(elem, len) => {
+                let elem = self.resolve_type(elem)?;
+                let value = self.eval_const(len)?;
+                let n = value
+                    .as_i64()
+                    .ok_or_else(|| format!("Array length must be an integer, found {value:?}"))?;
+                let n = usize::try_from(n)
+                    .map_err(|_| format!("Array length must not be negative, found {n}"))?;
+                Ok(Type::Array(Box::new(elem), Some(n)))
+            }
+            Type::Array(elem, len) => Ok(Type::Array(Box::new(self.resolve_type(elem)?), *len)),
+            Type::Pointer(inner) => Ok(Type::Pointer(Box::new(self.resolve_type(inner)?))),
+            Type::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|t| self.resolve_type(t))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(self.tuple_struct(&elements))
+            }
+            Type::Struct {
+                name,
+                instances,
+                generics,
+            } => {
+                let generics = generics
+                    .iter()
+                    .map(|t| self.resolve_type(t))
+                    .collect::<Result<Vec<_>, String>>()?;
+                // `Pair<int>` names the struct monomorphized for it, which
+                // is what literals of it produce
+                if !generics.is_empty() && generics.iter().all(is_concrete) {
+                    return Ok(Type::Struct {
+                        name: self.monomorphize_struct(name, &generics)?,
+                        instances: Vec::new(),
+                        generics: Vec::new(),
+                    });
+                }
+                Ok(Type::Struct {
+                    name: name.clone(),
+                    instances: instances
+                        .iter()
+                        .map(|(n, t)| Ok((n.clone(), self.resolve_type(t)?)))
+                        .collect::<Result<_, String>>()?,
+                    generics,
+                })
+            }
+            other => Ok(other.clone()),
+        }
+    }
+
+    pub(crate) fn resolve_stmt_types(&mut self, stmt: &mut Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::AtDecl(decl, _, _, Some(proto)) if decl.as_str() == "extern" => {
+                self.resolve_stmt_types(proto)
+            }
+            Stmt::VarDecl {
+                var_type, value, ..
+            } => {
+                *var_type = self.resolve_type(var_type)?;
+                match value {
+                    Some(value) => self.resolve_expr_types(value),
+                    None => Ok(()),
+                }
+            }
+            Stmt::Destructure { value, .. } => self.resolve_expr_types(value),
+            Stmt::FunDecl {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                for (_, ty) in params.iter_mut() {
+                    *ty = self.resolve_type(ty)?;
+                }
+                *return_type = self.resolve_type(return_type)?;
+                for s in body {
+                    self.resolve_stmt_types(s)?;
+                }
+                Ok(())
+            }
+            Stmt::StructDecl { instances, .. } => {
+                for (_, ty) in instances.iter_mut() {
+                    *ty = self.resolve_type(ty)?;
+                }
+                Ok(())
+            }
+            Stmt::If {
+                condition,
+                then_stmt,
+                else_stmt,
+            } => {
+                self.resolve_expr_types(condition)?;
+                self.resolve_stmt_types(then_stmt)?;
+                if let Some(else_stmt) = else_stmt {
+                    self.resolve_stmt_types(else_stmt)?;
+                }
+                Ok(())
+            }
+            Stmt::While {
+                condition, body, ..
+            }
+            | Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                self.resolve_expr_types(condition)?;
+                self.resolve_stmt_types(body)
+            }
+            Stmt::For {
+                init,
+                condition,
+                update,
+                body,
+                ..
+            } => {
+                if let Some(init) = init {
+                    self.resolve_stmt_types(init)?;
+                }
+                if let Some(cond) = condition {
+                    self.resolve_expr_types(cond)?;
+                }
+                if let Some(update) = update {
+                    self.resolve_expr_types(update)?;
+                }
+                self.resolve_stmt_types(body)
+            }
+            Stmt::Block(stmts) => {
+                for s in stmts {
+                    self.resolve_stmt_types(s)?;
+                }
+                Ok(())
+            }
+            Stmt::Expression(expr) | Stmt::Return(Some(expr)) | Stmt::Delete(expr) => {
+                self.resolve_expr_types(expr)
+            }
+            Stmt::InlineAsm { operands, .. } => {
+                for operand in operands {
+                    self.resolve_expr_types(&mut operand.expr)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_expr_types(&mut self, expr: &mut Expr) -> Result<(), String> {
+        match expr {
+            Expr::Cast { expr, target_type } => {
+                *target_type = self.resolve_type(target_type)?;
+                self.resolve_expr_types(expr)
+            }
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr_types(left)?;
+                self.resolve_expr_types(right)
+            }
+            Expr::Unary { expr, .. } | Expr::AddressOf(expr) | Expr::New(expr) => {
+                self.resolve_expr_types(expr)
+            }
+            Expr::NewArray { elem, count } => {
+                *elem = self.resolve_type(elem)?;
+                self.resolve_expr_types(count)
+            }
+            Expr::Assign { value, .. } => self.resolve_expr_types(value),
+            Expr::InstanceVar(base, _) => self.resolve_expr_types(base),
+            Expr::FieldAssign { base, value, .. } => {
+                self.resolve_expr_types(base)?;
+                self.resolve_expr_types(value)
+            }
+            Expr::DerefAssign { target, value } => {
+                self.resolve_expr_types(target)?;
+                self.resolve_expr_types(value)
+            }
+            Expr::Call { args, .. } | Expr::Array(args, _) | Expr::Tuple(args) => {
+                for arg in args {
+                    self.resolve_expr_types(arg)?;
+                }
+                Ok(())
+            }
+            Expr::StructInit { params, .. } => {
+                for (_, e) in params {
+                    self.resolve_expr_types(e)?;
+                }
+                Ok(())
+            }
+            Expr::ArrayRepeat { value, count } => {
+                self.resolve_expr_types(value)?;
+                self.resolve_expr_types(count)
+            }
+            Expr::ArrayAccess { array, index } => {
+                self.resolve_expr_types(array)?;
+                self.resolve_expr_types(index)
+            }
+            Expr::IndexAssign {
+                array,
+                index,
+                value,
+            } => {
+                self.resolve_expr_types(array)?;
+                self.resolve_expr_types(index)?;
+                self.resolve_expr_types(value)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether `ty` mentions no generic parameter.
+fn is_concrete(ty: &Type) -> bool {
+    match ty {
+        Type::Generic(_) => false,
+        Type::Pointer(inner) | Type::Array(inner, _) | Type::ArrayLen(inner, _) => {
+            is_concrete(inner)
+        }
+        Type::Struct { generics, .. } => generics.iter().all(is_concrete),
+        Type::Tuple(elements) => elements.iter().all(is_concrete),
+        _ => true,
+    }
+}
+
+fn eval_binary(op: &BinaryOp, left: ConstValue, right: ConstValue) -> Result<ConstValue, String> {
+    use ConstValue::*;
+
+    macro_rules! int_op {
+        ($a:expr, $b:expr, $wrap:path) => {{
+            let (a, b) = ($a, $b);
+            let res = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Sub => a.checked_sub(b),
+                BinaryOp::Mul => a.checked_mul(b),
+                BinaryOp::Div | BinaryOp::Mod if b == 0 => {
+                    return Err(format!(
+                        "Division by zero in constant expression ({a} {op:?} 0)"
+                    ));
+                }
+                BinaryOp::Div => a.checked_div(b),
+                BinaryOp::Mod => a.checked_rem(b),
+                BinaryOp::AND => Some(a & b),
+                BinaryOp::OR => Some(a | b),
+                BinaryOp::XOR => Some(a ^ b),
+                BinaryOp::LSHIFT => u32::try_from(b).ok().and_then(|b| a.checked_shl(b)),
+                BinaryOp::RSHIFT => u32::try_from(b).ok().and_then(|b| a.checked_shr(b)),
+                BinaryOp::Equal => return Ok(Bool(a == b)),
+                BinaryOp::NotEqual => return Ok(Bool(a != b)),
+                BinaryOp::Less => return Ok(Bool(a < b)),
+                BinaryOp::LessEqual => return Ok(Bool(a <= b)),
+                BinaryOp::Greater => return Ok(Bool(a > b)),
+                BinaryOp::GreaterEqual => return Ok(Bool(a >= b)),
+                _ => return Err(format!("Operator {op:?} is not valid on integer constants")),
+            };
+            res.map($wrap)
+                .ok_or_else(|| format!("Overflow in constant expression ({a} {op:?} {b})"))
+        }};
+    }
+
+    match (left, right) {
+        (Int(a), Int(b)) => int_op!(a, b, Int),
+        (Long(a), Long(b)) => int_op!(a, b, Long),
+        (Char(a), Char(b)) => match op {
+            BinaryOp::Equal => Ok(Bool(a == b)),
+            BinaryOp::NotEqual => Ok(Bool(a != b)),
+            BinaryOp::Less => Ok(Bool(a < b)),
+            BinaryOp::LessEqual => Ok(Bool(a <= b)),
+            BinaryOp::Greater => Ok(Bool(a > b)),
+            BinaryOp::GreaterEqual => Ok(Bool(a >= b)),
+            _ => Err(format!("Operator {op:?} is not valid on char constants")),
+        },
+        (Float(a), Float(b)) => match op {
+            BinaryOp::Add => Ok(Float(a + b)),
+            BinaryOp::Sub => Ok(Float(a - b)),
+            BinaryOp::Mul => Ok(Float(a * b)),
+            BinaryOp::Div if b == 0.0 => {
+                Err(format!("Division by zero in constant expression ({a} / 0)"))
+            }
+            BinaryOp::Div => Ok(Float(a / b)),
+            BinaryOp::Equal => Ok(Bool(a == b)),
+            BinaryOp::NotEqual => Ok(Bool(a != b)),
+            BinaryOp::Less => Ok(Bool(a < b)),
+            BinaryOp::LessEqual => Ok(Bool(a <= b)),
+            BinaryOp::Greater => Ok(Bool(a > b)),
+            BinaryOp::GreaterEqual => Ok(Bool(a >= b)),
+            _ => Err(format!("Operator {op:?} is not valid on float constants")),
+        },
+        (Bool(a), Bool(b)) => match op {
+            BinaryOp::And => Ok(Bool(a && b)),
+            BinaryOp::Or => Ok(Bool(a || b)),
+            BinaryOp::Equal => Ok(Bool(a == b)),
+            BinaryOp::NotEqual => Ok(Bool(a != b)),
+            _ => Err(format!("Operator {op:?} is not valid on bool constants")),
+        },
+        (a, b) => Err(format!(
+            "Type mismatch in constant expression: {:?} {op:?} {:?}",
+            a.get_type(),
+            b.get_type()
+        )),
+    }
+}
+
+fn eval_cast(value: ConstValue, target: &Type) -> Result<ConstValue, String> {
+    use ConstValue::*;
+
+    let out_of_range =
+        |v: &dyn std::fmt::Debug| format!("Constant {v:?} does not fit in {target:?}");
+
+    check_cast(&value.get_type(), target)?;
+    match (value, target) {
+        (Float(f), Type::float) => Ok(Float(f)),
+        // Truncated toward zero, then range-checked like any integer
+        (Float(f), Type::int | Type::Long | Type::Char) => {
+            let t = f.trunc();
+            if !(-9223372036854775808.0..9223372036854775808.0).contains(&t) {
+                return Err(out_of_range(&f));
+            }
+            eval_cast(Long(t as i64), target)
+        }
+        (Str(s), Type::Pointer(inner)) if **inner == Type::Char => Ok(Str(s)),
+        (value, ty) => {
+            let n = value
+                .as_i64()
+                .ok_or_else(|| format!("Invalid constant cast from {value:?} to {ty:?}"))?;
+            match ty {
+                Type::int => i32::try_from(n).map(Int).map_err(|_| out_of_range(&n)),
+                Type::Long => Ok(Long(n)),
+                Type::float => Ok(Float(n as f64)),
+                Type::Bool => Ok(Bool(n != 0)),
+                Type::Char => u8::try_from(n)
+                    .map(|c| Char(c as char))
+                    .map_err(|_| out_of_range(&n)),
+                _ => Err(format!("Invalid constant cast from {value:?} to {ty:?}")),
+            }
+        }
+    }
+}
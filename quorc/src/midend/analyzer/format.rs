@@ -0,0 +1,166 @@
+use crate::{
+    frontend::ast::{Expr, Type},
+    midend::analyzer::{TypeChecker, base_type},
+};
+
+/// The 1-based index of the format string in a `format(printf,N)` attribute.
+pub(crate) fn printf_format_index(attributes: &[String]) -> Option<usize> {
+    attributes.iter().find_map(|attr| {
+        attr.strip_prefix("format(printf,")?
+            .strip_suffix(')')?
+            .parse()
+            .ok()
+    })
+}
+
+/// One `%` conversion of a format string, e.g. `%-8ld`.
+struct Conversion {
+    spec: String,
+    length: String,
+    kind: char,
+    star_args: usize,
+}
+
+fn parse_conversions(format: &str) -> Result<Vec<Conversion>, String> {
+    let mut conversions = Vec::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            continue;
+        }
+
+        let mut spec = String::from('%');
+        let mut take_while = |spec: &mut String, f: &dyn Fn(char) -> bool| {
+            while let Some(&c) = chars.peek()
+                && f(c)
+            {
+                spec.push(c);
+                chars.next();
+            }
+        };
+
+        take_while(&mut spec, &|c| "-+ #0".contains(c));
+        take_while(&mut spec, &|c| c.is_ascii_digit() || c == '*');
+        take_while(&mut spec, &|c| c == '.');
+        take_while(&mut spec, &|c| c.is_ascii_digit() || c == '*');
+        let star_args = spec.matches('*').count();
+
+        let mut length = String::new();
+        take_while(&mut length, &|c| "hlz".contains(c));
+        spec.push_str(&length);
+
+        let Some(kind) = chars.next() else {
+            return Err(format!("format string ends inside conversion '{spec}'"));
+        };
+        spec.push(kind);
+        if !"diuxXocsfFeEgGp".contains(kind) {
+            return Err(format!("unknown conversion '{spec}'"));
+        }
+
+        conversions.push(Conversion {
+            spec,
+            length,
+            kind,
+            star_args,
+        });
+    }
+
+    Ok(conversions)
+}
+
+impl Conversion {
+    fn is_long(&self) -> bool {
+        matches!(self.length.as_str(), "l" | "ll" | "z")
+    }
+
+    fn accepts(&self, ty: &Type) -> bool {
+        match self.kind {
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' if self.is_long() => *ty == Type::Long,
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => matches!(ty, Type::int | Type::Char | Type::Bool),
+            'c' => matches!(ty, Type::Char | Type::int),
+            's' => match ty {
+                Type::Pointer(inner) | Type::Array(inner, _) => **inner == Type::Char,
+                _ => false,
+            },
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => *ty == Type::float,
+            _ => ty.is_pointer(),
+        }
+    }
+
+    fn expected(&self) -> &'static str {
+        match self.kind {
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' if self.is_long() => "long",
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => "int",
+            'c' => "char",
+            's' => "char*",
+            'p' => "a pointer",
+            _ => "float",
+        }
+    }
+}
+
+impl TypeChecker {
+    /// Checks the arguments after a literal printf-style format string against
+    /// its conversions. `index` is the 1-based position of the format string.
+    pub(crate) fn check_printf_call(
+        &mut self,
+        name: &str,
+        index: usize,
+        args: &[Expr],
+    ) -> Result<(), String> {
+        if index == 0 {
+            return Err(format!("@format index of '{name}' starts at 1"));
+        }
+        // Only literal format strings can be checked
+        let Some(Expr::StringLiteral(format)) = args.get(index - 1) else {
+            for arg in args.iter().skip(index) {
+                self.type_check_expr(arg)?;
+            }
+            return Ok(());
+        };
+        let conversions =
+            parse_conversions(format).map_err(|e| format!("In call to '{name}': {e}"))?;
+
+        let rest = &args[index..];
+        let expected: usize = conversions.iter().map(|c| 1 + c.star_args).sum();
+        if expected != rest.len() {
+            return Err(format!(
+                "Format string of '{name}' expects {expected} argument(s), got {}",
+                rest.len()
+            ));
+        }
+
+        let mut rest = rest.iter().enumerate();
+        for conversion in &conversions {
+            for _ in 0..conversion.star_args {
+                let (i, arg) = rest.next().unwrap();
+                let ty = base_type(&self.type_check_expr(arg)?);
+                if ty != Type::int {
+                    return Err(format!(
+                        "Format '{}' in call to '{name}' expects int for '*' (argument {}), got {ty:?}",
+                        conversion.spec,
+                        index + i + 1
+                    ));
+                }
+            }
+
+            let (i, arg) = rest.next().unwrap();
+            let ty = base_type(&self.type_check_expr(arg)?);
+            if !conversion.accepts(&ty) {
+                return Err(format!(
+                    "Format '{}' in call to '{name}' expects {} (argument {}), got {ty:?}",
+                    conversion.spec,
+                    conversion.expected(),
+                    index + i + 1
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
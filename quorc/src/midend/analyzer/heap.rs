@@ -0,0 +1,63 @@
+use crate::{
+    frontend::ast::{Expr, Type},
+    midend::analyzer::TypeChecker,
+    target::{alloc_fn, free_fn},
+};
+
+impl TypeChecker {
+    /// Types `new Struct { .. }` and `new T[count]` as pointers to the new value.
+    pub(crate) fn check_new(&mut self, expr: &Expr) -> Result<Type, String> {
+        let (params, ret, _) = self.heap_fn(alloc_fn(), "new")?;
+        if params != [Type::Long] || !ret.is_pointer() {
+            return Err(format!(
+                "Allocator '{}' must take a long size and return a pointer",
+                alloc_fn()
+            ));
+        }
+
+        match expr {
+            Expr::New(init) => Ok(Type::Pointer(Box::new(self.type_check_expr(init)?))),
+            Expr::NewArray { elem, count } => {
+                let count_type = self.type_check_expr(count)?;
+                if !matches!(count_type, Type::int | Type::Long | Type::Char) {
+                    return Err(format!(
+                        "Element count of 'new' must be an integer, found {count_type:?}"
+                    ));
+                }
+                if *elem == Type::Void {
+                    return Err("Cannot allocate an array of void".to_string());
+                }
+                self.type_layout(elem)?;
+                Ok(Type::Pointer(Box::new(elem.clone())))
+            }
+            _ => unreachable!("check_new on a non-allocation"),
+        }
+    }
+
+    pub(crate) fn check_delete(&mut self, expr: &Expr) -> Result<(), String> {
+        let (params, _, _) = self.heap_fn(free_fn(), "delete")?;
+        if params.len() != 1 || !params[0].is_pointer() {
+            return Err(format!(
+                "Deallocator '{}' must take a single pointer",
+                free_fn()
+            ));
+        }
+
+        match self.type_check_expr(expr)? {
+            Type::Pointer(_) => Ok(()),
+            other => Err(format!("Cannot delete a value of type {other:?}")),
+        }
+    }
+
+    /// Marks the allocator or deallocator as called so it is kept and linked.
+    pub(crate) fn note_heap_call(&mut self, name: &str) {
+        self.called.push(name.to_string());
+        self.note_use(name);
+    }
+
+    fn heap_fn(&self, name: &str, keyword: &str) -> Result<(Vec<Type>, Type, Vec<String>), String> {
+        self.lookup_fn(name).cloned().ok_or_else(|| {
+            format!("'{keyword}' calls '{name}', which is not declared (e.g. @import <mem.qu>)")
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    frontend::{
+        ast::{Expr, Type},
+        size::SizeOf,
+    },
+    midend::analyzer::TypeChecker,
+};
+
+/// Stack slots are placed relative to the frame pointer, which is only
+/// 16-byte aligned.
+const MAX_STACK_ALIGN: usize = 16;
+
+impl TypeChecker {
+    /// Rejects struct and array values that need more alignment than a stack
+    /// slot can give them.
+    pub(crate) fn require_stack_align(&mut self, ty: &Type) -> Result<(), String> {
+        // Unsized array parameters are passed as pointers
+        if !matches!(ty, Type::Struct { .. } | Type::Array(_, Some(_))) {
+            return Ok(());
+        }
+        let (_, align) = self.type_layout(ty)?;
+        if align <= MAX_STACK_ALIGN {
+            return Ok(());
+        }
+        let mut elem = ty;
+        while let Type::Array(inner, _) = elem {
+            elem = inner;
+        }
+        let name = match elem {
+            Type::Struct { name, .. } => name.as_str(),
+            _ => "value",
+        };
+        Err(format!(
+            "'{name}' needs {align}-byte alignment, but values on the stack are aligned \
+             to at most {MAX_STACK_ALIGN} bytes"
+        ))
+    }
+
+    /// Returns `(size, align)` of `ty`, looking struct fields up by name so
+    /// generic instantiations and unions are laid out like the midend does.
+    pub(crate) fn type_layout(&mut self, ty: &Type) -> Result<(usize, usize), String> {
+        match ty {
+            Type::Struct { name, generics, .. } => {
+                let name = if generics.is_empty() {
+                    name.clone()
+                } else {
+                    self.monomorphize_struct(name, generics)?
+                };
+                self.struct_layout(&name)
+                    .map(|(_, size, align)| (size, align))
+            }
+            Type::StructLiteral(name) => self
+                .struct_layout(name)
+                .map(|(_, size, align)| (size, align)),
+            Type::Array(elem, Some(n)) => {
+                let (size, align) = self.type_layout(elem)?;
+                Ok((size * n, align))
+            }
+            Type::Array(_, None) => Err("Cannot take the size of an unsized array".to_string()),
+            Type::ArrayLen(..) | Type::Tuple(_) => {
+                let ty = self.resolve_type(ty)?;
+                self.type_layout(&ty)
+            }
+            Type::Generic(name) => Err(format!("Size of generic parameter '{name}' is unknown")),
+            Type::Void | Type::Unknown | Type::Inferred | Type::Function | Type::null => {
+                Err(format!("Type {ty:?} has no size"))
+            }
+            _ => Ok((ty.size(), ty.align())),
+        }
+    }
+
+    /// Field offsets, size and alignment of the struct or union `name`.
+    fn struct_layout(&mut self, name: &str) -> Result<(Vec<usize>, usize, usize), String> {
+        self.require_complete(&Type::StructLiteral(name.to_string()))?;
+        let is_union = *self
+            .classes
+            .get(name)
+            .ok_or_else(|| format!("Unknown struct '{name}'"))?;
+        let fields = self.class_fields.get(name).cloned().unwrap_or_default();
+
+        let layouts = fields
+            .iter()
+            .map(|(field, ty)| Ok((field.as_str(), self.type_layout(ty)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let layout = self.class_layouts.get(name).cloned().unwrap_or_default();
+        Ok(layout.arrange(layouts, is_union))
+    }
+
+    fn size_of_target(&mut self, size_of: &SizeOf) -> Result<Type, String> {
+        match size_of {
+            SizeOf::Variable(name) if self.classes.contains_key(name) => {
+                self.used_items.insert(name.clone());
+                Ok(Type::Struct {
+                    name: name.clone(),
+                    instances: Vec::new(),
+                    generics: Vec::new(),
+                })
+            }
+            SizeOf::Variable(name) => {
+                self.mark_read(name);
+                self.lookup_var(name)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown type or variable '{name}'"))
+            }
+            SizeOf::Prim(ty) => Ok(ty.clone()),
+            SizeOf::Expr(expr) => self.type_check_expr(expr),
+        }
+    }
+
+    /// Evaluates `sizeof(..)`, `alignof(..)`, `offsetof(Struct, field)` or `len(array)`.
+    pub(crate) fn eval_layout_query(&mut self, name: &str, args: &[Expr]) -> Result<usize, String> {
+        match (name, args) {
+            ("sizeof", [Expr::SizeOf(so)]) => {
+                let ty = self.size_of_target(so)?;
+                self.type_layout(&ty).map(|(size, _)| size)
+            }
+            ("alignof", [Expr::SizeOf(so)]) => {
+                let ty = self.size_of_target(so)?;
+                self.type_layout(&ty).map(|(_, align)| align)
+            }
+            ("offsetof", [Expr::SizeOf(so), Expr::SizeOf(SizeOf::Variable(field))]) => {
+                let struct_name = match self.size_of_target(so)? {
+                    Type::Struct { name, generics, .. } if !generics.is_empty() => {
+                        self.monomorphize_struct(&name, &generics)?
+                    }
+                    Type::Struct { name, .. } | Type::StructLiteral(name) => name,
+                    other => return Err(format!("offsetof expects a struct, found {other:?}")),
+                };
+
+                let index = self
+                    .class_fields
+                    .get(&struct_name)
+                    .and_then(|fields| fields.iter().position(|(f, _)| f == field))
+                    .ok_or_else(|| format!("Struct '{struct_name}' has no field '{field}'"))?;
+
+                let (offsets, _, _) = self.struct_layout(&struct_name)?;
+                Ok(offsets[index])
+            }
+            ("offsetof", _) => Err("offsetof expects a struct and a field name".to_string()),
+            ("len", [Expr::SizeOf(so)]) => {
+                let ty = self.size_of_target(so)?;
+                let array = match &ty {
+                    Type::Pointer(inner) => &**inner,
+                    ty => ty,
+                };
+                match array {
+                    Type::Array(_, Some(len)) => Ok(*len),
+                    _ => Err(format!("len expects an array of known length, found {ty:?}")),
+                }
+            }
+            _ => Err(format!("{name} expects exactly one type or expression")),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+
+use crate::{
+    frontend::ast::{Stmt, Type},
+    lint::{self, Lint},
+    midend::analyzer::{TypeChecker, is_exported},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct VarUsage {
+    pub param: bool,
+    pub read: bool,
+}
+
+/// An `@import` in the root file and the items it brought in.
+#[derive(Debug, Default)]
+pub(crate) struct ImportedFile {
+    pub path: String,
+    pub items: HashSet<String>,
+}
+
+pub(crate) fn item_names(stmts: &[Stmt]) -> HashSet<String> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::FunDecl { name, .. } | Stmt::StructDecl { name, .. } => Some(name.clone()),
+            Stmt::AtDecl(decl, Some(name), _, _) if matches!(decl.as_str(), "const" | "extern") => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Moves each `@allow(lint)` onto the item after it: functions get it as an
+/// attribute, constants and imports are remembered by name.
+pub(crate) fn attach_allows(program: Vec<Stmt>, allows: &mut Vec<(String, String)>) -> Vec<Stmt> {
+    let mut result = Vec::with_capacity(program.len());
+    let mut pending: Vec<String> = Vec::new();
+
+    for mut stmt in program {
+        if let Stmt::AtDecl(decl, Some(lint), _, _) = &stmt
+            && decl.as_str() == "allow"
+        {
+            pending.push(format!("allow({lint})"));
+            continue;
+        }
+
+        match &mut stmt {
+            Stmt::FunDecl { attributes, .. } => attributes.append(&mut pending),
+            Stmt::AtDecl(decl, Some(name), _, _) if matches!(decl.as_str(), "const" | "import") => {
+                allows.extend(pending.drain(..).map(|attr| (name.clone(), attr)));
+            }
+            _ => pending.clear(),
+        }
+        result.push(stmt);
+    }
+
+    result
+}
+
+impl TypeChecker {
+    pub(crate) fn mark_read(&mut self, name: &str) {
+        for scope in self.usage.iter_mut().rev() {
+            if let Some(usage) = scope.get_mut(name) {
+                usage.read = true;
+                return;
+            }
+        }
+        self.note_use(name);
+    }
+
+    /// Records a use of a global item, counted only from root-file code.
+    pub(crate) fn note_use(&mut self, name: &str) {
+        if self.in_root {
+            self.used_items.insert(name.to_string());
+        }
+    }
+
+    pub(crate) fn mark_type_used(&mut self, ty: &Type) {
+        match ty {
+            Type::Struct { name, generics, .. } => {
+                self.note_use(name);
+                for generic in generics {
+                    self.mark_type_used(generic);
+                }
+            }
+            Type::Pointer(inner) | Type::Array(inner, _) | Type::ArrayLen(inner, _) => {
+                self.mark_type_used(inner)
+            }
+            Type::Tuple(elements) => {
+                for element in elements {
+                    self.mark_type_used(element);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Warns about the variables and parameters of the innermost scope that
+    /// were never read. Names starting with `_` are exempt.
+    pub(crate) fn warn_unused_in_scope(&self) {
+        let Some(scope) = self.usage.last() else {
+            return;
+        };
+        let mut unused: Vec<_> = scope
+            .iter()
+            .filter(|(name, usage)| !usage.read && !name.starts_with('_'))
+            .collect();
+        unused.sort_by_key(|(name, _)| name.as_str());
+
+        for (name, usage) in unused {
+            let (lint, kind) = if usage.param {
+                (Lint::UnusedParameters, "parameter")
+            } else {
+                (Lint::UnusedVariables, "variable")
+            };
+            lint::emit(
+                lint,
+                &self.current_attributes,
+                &format!(
+                    "unused {kind} '{name}' in function '{}'",
+                    self.current_function
+                ),
+            );
+        }
+    }
+
+    pub(crate) fn warn_unreachable(&self, stmts: &[Stmt]) {
+        let jump = stmts
+            .iter()
+            .position(|stmt| matches!(stmt, Stmt::Return(_) | Stmt::Break(_) | Stmt::Continue(_)));
+        if let Some(i) = jump
+            && i + 1 < stmts.len()
+        {
+            let kind = match stmts[i] {
+                Stmt::Return(_) => "return",
+                Stmt::Break(_) => "break",
+                _ => "continue",
+            };
+            lint::emit(
+                Lint::UnreachableCode,
+                &self.current_attributes,
+                &format!(
+                    "unreachable code after '{kind}' in function '{}'",
+                    self.current_function
+                ),
+            );
+        }
+    }
+
+    /// Warns about functions, constants and imports of the root file that
+    /// nothing refers to. Runs once the whole program has been checked.
+    pub(crate) fn warn_unused_items(
+        &self,
+        root: &[Stmt],
+        imports: &[ImportedFile],
+        allows: &[(String, String)],
+    ) {
+        let allows_for = |item: &str| -> Vec<String> {
+            allows
+                .iter()
+                .filter(|(name, _)| name == item)
+                .map(|(_, attr)| attr.clone())
+                .collect()
+        };
+        let is_used = |name: &String| self.used_items.contains(name);
+        // Generic functions are called by their mangled names
+        let is_called = |name: &String| {
+            is_used(name)
+                || self.used_items.iter().any(|used| {
+                    used.strip_prefix(name.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+                })
+        };
+
+        for stmt in root {
+            match stmt {
+                Stmt::FunDecl {
+                    name, attributes, ..
+                } if name != "main" && !is_called(name) && !is_exported(attributes) => lint::emit(
+                    Lint::UnusedFunctions,
+                    attributes,
+                    &format!("function '{name}' is never called"),
+                ),
+                Stmt::AtDecl(decl, Some(name), _, _)
+                    if decl.as_str() == "const" && !is_used(name) =>
+                {
+                    lint::emit(
+                        Lint::UnusedConstants,
+                        &allows_for(name),
+                        &format!("constant '{name}' is never used"),
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        for import in imports {
+            if !import.items.iter().any(is_used) {
+                lint::emit(
+                    Lint::UnusedImports,
+                    &allows_for(&import.path),
+                    &format!("unused import '{}'", import.path),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    frontend::ast::{BinaryOp, Expr, Stmt, Type, UnaryOp},
+    midend::analyzer::{TypeChecker, base_type, mangle_name},
+};
+
+const OPERATOR_FNS: &[&str] = &[
+    "op_add", "op_sub", "op_mul", "op_div", "op_mod", "op_eq", "op_ne", "op_lt", "op_le", "op_gt",
+    "op_ge", "op_index",
+];
+
+fn operator_fn(op: &BinaryOp) -> Option<&'static str> {
+    Some(match op {
+        BinaryOp::Add => "op_add",
+        BinaryOp::Sub => "op_sub",
+        BinaryOp::Mul => "op_mul",
+        BinaryOp::Div => "op_div",
+        BinaryOp::Mod => "op_mod",
+        BinaryOp::Equal => "op_eq",
+        BinaryOp::NotEqual => "op_ne",
+        BinaryOp::Less => "op_lt",
+        BinaryOp::LessEqual => "op_le",
+        BinaryOp::Greater => "op_gt",
+        BinaryOp::GreaterEqual => "op_ge",
+        _ => return None,
+    })
+}
+
+/// Operator functions are keyed by their left operand so that several structs
+/// can each define `op_add`: `def op_add(a: Vec3, b: Vec3)` becomes `op_add.Vec3`.
+pub(crate) fn mangle_operator_decl(stmt: &mut Stmt) {
+    if let Stmt::FunDecl { name, params, .. } = stmt
+        && OPERATOR_FNS.contains(&name.as_str())
+        && let Some((_, ty @ Type::Struct { .. })) = params.first()
+    {
+        *name = mangle_name(name, std::slice::from_ref(ty));
+    }
+}
+
+impl TypeChecker {
+    /// Resolves `left op right` for a struct `left` to the operator function
+    /// implementing it, returning its name and result type.
+    fn resolve_operator(
+        &self,
+        op: &str,
+        left: &Type,
+        right: &Type,
+    ) -> Result<(String, Type), String> {
+        let Type::Struct {
+            name: struct_name, ..
+        } = left
+        else {
+            unreachable!("operator lookup on non-struct type");
+        };
+        let name = mangle_name(op, std::slice::from_ref(left));
+        let (params, ret, _) = self
+            .lookup_fn(&name)
+            .ok_or_else(|| format!("No operator '{op}' defined for struct '{struct_name}'"))?;
+
+        if params.len() != 2 {
+            return Err(format!(
+                "Operator '{op}' for '{struct_name}' must take 2 parameters, found {}",
+                params.len()
+            ));
+        }
+        if params[1] != base_type(right) {
+            return Err(format!(
+                "Operator '{op}' for '{struct_name}' expects right operand {:?}, found {right:?}",
+                params[1]
+            ));
+        }
+        Ok((name, ret.clone()))
+    }
+
+    /// Type of a binary operation on a struct operand. `!=` falls back to
+    /// negating `op_eq` when no `op_ne` is defined.
+    pub(crate) fn check_binary_operator(
+        &self,
+        op: &BinaryOp,
+        left: &Type,
+        right: &Type,
+    ) -> Result<Type, String> {
+        if self.is_builtin_comparison(op, left, right) {
+            return Ok(Type::Bool);
+        }
+        let op_fn =
+            operator_fn(op).ok_or_else(|| format!("Operator {op:?} cannot be overloaded"))?;
+        match self.resolve_operator(op_fn, left, right) {
+            Err(_) if matches!(op, BinaryOp::NotEqual) => {
+                let (_, ret) = self.resolve_operator("op_eq", left, right)?;
+                if ret != Type::Bool {
+                    let Type::Struct { name, .. } = left else {
+                        unreachable!("operator lookup on non-struct type");
+                    };
+                    return Err(format!(
+                        "Operator 'op_eq' for '{name}' must return bool for '!=' to negate it, found {ret:?}"
+                    ));
+                }
+                Ok(ret)
+            }
+            result => result.map(|(_, ret)| ret),
+        }
+    }
+
+    /// `==` and `!=` between two values of the same struct compare them field
+    /// by field when the struct defines neither `op_eq` nor (for `!=`) `op_ne`.
+    fn is_builtin_comparison(&self, op: &BinaryOp, left: &Type, right: &Type) -> bool {
+        let defined = |op_fn: &str| {
+            self.lookup_fn(&mangle_name(op_fn, std::slice::from_ref(left)))
+                .is_some()
+        };
+        let overloaded = match op {
+            BinaryOp::Equal => defined("op_eq"),
+            BinaryOp::NotEqual => defined("op_ne") || defined("op_eq"),
+            _ => return false,
+        };
+        !overloaded && base_type(left) == base_type(right)
+    }
+
+    pub(crate) fn check_index_operator(&self, base: &Type, index: &Type) -> Result<Type, String> {
+        self.resolve_operator("op_index", base, index)
+            .map(|(_, ret)| ret)
+    }
+
+    /// Rewrites an overloaded `Binary` or `ArrayAccess` into a call to its
+    /// operator function, returning false when there is none to call (the
+    /// built-in struct comparison). Operands must already have their types
+    /// filled.
+    pub(crate) fn lower_operator(&self, expr: &mut Expr) -> bool {
+        let (op_fn, left, right, negate) = match expr {
+            Expr::Binary {
+                left, op, right, ..
+            } if matches!(self.expr_type(left), Type::Struct { .. }) => {
+                let (left_type, right_type) = (self.expr_type(left), self.expr_type(right));
+                if self.is_builtin_comparison(op, &left_type, &right_type) {
+                    return false;
+                }
+                let Some(op_fn) = operator_fn(op) else {
+                    return false;
+                };
+                let negate = matches!(op, BinaryOp::NotEqual)
+                    && self
+                        .resolve_operator(op_fn, &left_type, &right_type)
+                        .is_err();
+                let op_fn = if negate { "op_eq" } else { op_fn };
+                (op_fn, left, right, negate)
+            }
+            Expr::ArrayAccess { array, index }
+                if matches!(self.expr_type(array), Type::Struct { .. }) =>
+            {
+                ("op_index", array, index, false)
+            }
+            _ => return false,
+        };
+
+        // Already validated by type_check_expr
+        let (name, return_type) = self
+            .resolve_operator(op_fn, &self.expr_type(left), &self.expr_type(right))
+            .unwrap();
+        let call = Expr::Call {
+            name,
+            args: vec![
+                std::mem::replace(&mut **left, Expr::IntLiteral(0)),
+                std::mem::replace(&mut **right, Expr::IntLiteral(0)),
+            ],
+            return_type: return_type.clone(),
+        };
+        *expr = if negate {
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(call),
+                result_type: return_type,
+            }
+        } else {
+            call
+        };
+        true
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use crate::backend::lir::regalloc::RegWidth;
-use crate::frontend::ast::{Expr, Type};
+use crate::frontend::ast::{AsmReg, Expr, Type};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct VReg {
@@ -107,6 +107,7 @@ pub enum IRInstruction {
     Cast {
         reg: VReg,
         src: Value,
+        from: Type,
         ty: Type,
     },
 
@@ -133,8 +134,10 @@ pub enum IRInstruction {
 
     Call {
         reg: Option<VReg>,
+        reg_hi: Option<VReg>, // second return register of a register-pair aggregate
         func: String,
         args: Vec<Value>,
+        sret: Option<Value>, // where a struct returned in memory is written
     },
 
     Move {
@@ -154,9 +157,221 @@ pub enum IRInstruction {
         align: usize,
     },
 
+    InlineAsm {
+        template: String,
+        operands: Vec<IRAsmOperand>,
+        clobbers: Vec<String>,
+    },
+
+    // Volatile (order None) and atomic accesses are never elided, merged or
+    // moved across one another
+    AtomicLoad {
+        reg: VReg,
+        addr: Value,
+        ty: Type,
+        order: Option<MemOrder>,
+    },
+    AtomicStore {
+        value: Value,
+        addr: Value,
+        ty: Type,
+        order: Option<MemOrder>,
+    },
+    AtomicRmw {
+        reg: VReg, // value before the update
+        op: RmwOp,
+        addr: Value,
+        value: Value,
+        ty: Type,
+        order: MemOrder,
+    },
+    AtomicCmpXchg {
+        reg: VReg, // value before the exchange
+        addr: Value,
+        expected: Value,
+        desired: Value,
+        ty: Type,
+        order: MemOrder,
+    },
+
     Declaration(AtDecl), // holds things line inline assembly and imports, not function attributes or struct attributes
 }
 
+impl IRInstruction {
+    /// Registers written by this instruction.
+    pub fn defs(&self) -> Vec<VReg> {
+        use IRInstruction::*;
+        match self {
+            Add { reg, .. }
+            | Sub { reg, .. }
+            | Mul { reg, .. }
+            | Div { reg, .. }
+            | Mod { reg, .. }
+            | Eq { reg, .. }
+            | Ne { reg, .. }
+            | Lt { reg, .. }
+            | Le { reg, .. }
+            | Ge { reg, .. }
+            | Gt { reg, .. }
+            | Cast { reg, .. }
+            | Load { reg, .. }
+            | AtomicLoad { reg, .. }
+            | AtomicRmw { reg, .. }
+            | AtomicCmpXchg { reg, .. } => vec![*reg],
+            Gep { dest, .. } | Move { dest, .. } | AddressOf { dest, .. } => vec![*dest],
+            Call { reg, reg_hi, .. } => reg.iter().chain(reg_hi).copied().collect(),
+            InlineAsm { operands, .. } => operands.iter().filter_map(|op| op.output).collect(),
+            Store { .. } | AtomicStore { .. } | Memcpy { .. } | Declaration(_) => Vec::new(),
+        }
+    }
+
+    /// Values read by this instruction.
+    pub fn uses(&self) -> Vec<&Value> {
+        use IRInstruction::*;
+        match self {
+            Add { left, right, .. }
+            | Sub { left, right, .. }
+            | Mul { left, right, .. }
+            | Div { left, right, .. }
+            | Mod { left, right, .. }
+            | Eq { left, right, .. }
+            | Ne { left, right, .. }
+            | Lt { left, right, .. }
+            | Le { left, right, .. }
+            | Ge { left, right, .. }
+            | Gt { left, right, .. } => vec![left, right],
+            Cast { src, .. } | AddressOf { src, .. } => vec![src],
+            Load { addr, .. } | AtomicLoad { addr, .. } => vec![addr],
+            Store { value, addr, .. }
+            | AtomicStore { value, addr, .. }
+            | AtomicRmw { value, addr, .. } => vec![value, addr],
+            AtomicCmpXchg {
+                addr,
+                expected,
+                desired,
+                ..
+            } => vec![addr, expected, desired],
+            Gep { base, index, .. } => vec![base, index],
+            Call { args, sret, .. } => args.iter().chain(sret).collect(),
+            Move { from, .. } => vec![from],
+            Memcpy { dst, src, .. } => vec![dst, src],
+            InlineAsm { operands, .. } => operands.iter().filter_map(|op| op.input.as_ref()).collect(),
+            Declaration(_) => Vec::new(),
+        }
+    }
+
+    /// Like `defs`, for rewriting the written registers in place.
+    pub fn defs_mut(&mut self) -> Vec<&mut VReg> {
+        use IRInstruction::*;
+        match self {
+            Add { reg, .. }
+            | Sub { reg, .. }
+            | Mul { reg, .. }
+            | Div { reg, .. }
+            | Mod { reg, .. }
+            | Eq { reg, .. }
+            | Ne { reg, .. }
+            | Lt { reg, .. }
+            | Le { reg, .. }
+            | Ge { reg, .. }
+            | Gt { reg, .. }
+            | Cast { reg, .. }
+            | Load { reg, .. }
+            | AtomicLoad { reg, .. }
+            | AtomicRmw { reg, .. }
+            | AtomicCmpXchg { reg, .. } => vec![reg],
+            Gep { dest, .. } | Move { dest, .. } | AddressOf { dest, .. } => vec![dest],
+            Call { reg, reg_hi, .. } => reg.iter_mut().chain(reg_hi).collect(),
+            InlineAsm { operands, .. } => operands.iter_mut().filter_map(|op| op.output.as_mut()).collect(),
+            Store { .. } | AtomicStore { .. } | Memcpy { .. } | Declaration(_) => Vec::new(),
+        }
+    }
+
+    /// Like `uses`, for rewriting the read values in place.
+    pub fn uses_mut(&mut self) -> Vec<&mut Value> {
+        use IRInstruction::*;
+        match self {
+            Add { left, right, .. }
+            | Sub { left, right, .. }
+            | Mul { left, right, .. }
+            | Div { left, right, .. }
+            | Mod { left, right, .. }
+            | Eq { left, right, .. }
+            | Ne { left, right, .. }
+            | Lt { left, right, .. }
+            | Le { left, right, .. }
+            | Ge { left, right, .. }
+            | Gt { left, right, .. } => vec![left, right],
+            Cast { src, .. } | AddressOf { src, .. } => vec![src],
+            Load { addr, .. } | AtomicLoad { addr, .. } => vec![addr],
+            Store { value, addr, .. }
+            | AtomicStore { value, addr, .. }
+            | AtomicRmw { value, addr, .. } => vec![value, addr],
+            AtomicCmpXchg {
+                addr,
+                expected,
+                desired,
+                ..
+            } => vec![addr, expected, desired],
+            Gep { base, index, .. } => vec![base, index],
+            Call { args, sret, .. } => args.iter_mut().chain(sret).collect(),
+            Move { from, .. } => vec![from],
+            Memcpy { dst, src, .. } => vec![dst, src],
+            InlineAsm { operands, .. } => operands.iter_mut().filter_map(|op| op.input.as_mut()).collect(),
+            Declaration(_) => Vec::new(),
+        }
+    }
+}
+
+/// An inline assembly operand: `input` is placed in the operand's register
+/// before the template runs and `output` is read back from it afterwards.
+#[derive(Clone, Debug)]
+pub struct IRAsmOperand {
+    pub reg: AsmReg,
+    pub input: Option<Value>,
+    pub output: Option<VReg>,
+    pub width: RegWidth,
+}
+
+/// Memory ordering of an atomic access, numbered like the constants in
+/// `atomic.qu`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemOrder {
+    Relaxed,
+    Acquire,
+    Release,
+    AcqRel,
+    SeqCst,
+}
+
+impl MemOrder {
+    pub fn from_index(index: i64) -> Option<MemOrder> {
+        Some(match index {
+            0 => MemOrder::Relaxed,
+            1 => MemOrder::Acquire,
+            2 => MemOrder::Release,
+            3 => MemOrder::AcqRel,
+            4 => MemOrder::SeqCst,
+            _ => return None,
+        })
+    }
+
+    pub fn acquires(self) -> bool {
+        matches!(self, MemOrder::Acquire | MemOrder::AcqRel | MemOrder::SeqCst)
+    }
+
+    pub fn releases(self) -> bool {
+        matches!(self, MemOrder::Release | MemOrder::AcqRel | MemOrder::SeqCst)
+    }
+}
+
+/// Read-modify-write operations of `atomic_swap` and `atomic_fetch_add`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RmwOp {
+    Swap,
+    Add,
+}
+
 #[derive(Clone, Debug)]
 pub enum AtDecl {
     Import { path: String, local: bool },
@@ -167,6 +382,7 @@ pub enum AtDecl {
     Variadic,
     Inline,
     NoFrame,
+    Export { symbol: Option<String> },
 }
 
 impl AtDecl {
@@ -176,7 +392,13 @@ impl AtDecl {
             "variadic" => Some(AtDecl::Variadic),
             "inline" => Some(AtDecl::Inline),
             "no_frame" => Some(AtDecl::NoFrame),
-            _ => None,
+            "export" => Some(AtDecl::Export { symbol: None }),
+            _ => attribute
+                .strip_prefix("export(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .map(|symbol| AtDecl::Export {
+                    symbol: Some(symbol.to_string()),
+                }),
         }
     }
 }
@@ -195,6 +417,7 @@ pub struct IRBlock {
 pub enum Terminator {
     Return {
         value: Option<Value>,
+        value_hi: Option<Value>, // second return register of a register-pair aggregate
     },
     Jump {
         block: BlockId,
@@ -207,31 +430,127 @@ pub enum Terminator {
     TemporaryNone,
 }
 
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump { block } => vec![*block],
+            Terminator::Branch {
+                if_true, if_false, ..
+            } => vec![*if_true, *if_false],
+            Terminator::Return { .. } | Terminator::TemporaryNone => Vec::new(),
+        }
+    }
+
+    pub fn uses(&self) -> Vec<&Value> {
+        match self {
+            Terminator::Return { value, value_hi } => value.iter().chain(value_hi).collect(),
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Jump { .. } | Terminator::TemporaryNone => Vec::new(),
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Return { value, value_hi } => value.iter_mut().chain(value_hi).collect(),
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Jump { .. } | Terminator::TemporaryNone => Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct IRFunction {
     pub name: String,
     pub params: Vec<VReg>,
+    pub sret: Option<VReg>, // hidden return pointer of a struct returned in memory
     pub ret_type: Type,
     pub blocks: Vec<IRBlock>,
     pub entry: BlockId,
     pub attributes: Vec<AtDecl>,
     pub offset: i32,
+    pub local_layouts: HashMap<usize, (usize, usize)>, // (size, align) of each stack local, by local id
+}
+
+/// Sections a top-level `@asm` block can write into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Text,
+    Data,
+    Rodata,
+    Bss,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct IRProgram {
     pub externs: Vec<String>,
+    pub section_asm: Vec<(Section, String)>, // verbatim, in source order
+    pub extern_fns: HashMap<String, ExternFn>,
     pub functions: HashMap<String, IRFunction>,
     pub global_consts: Vec<GlobalDef>,
     pub structs: HashMap<String, StructDef>,
 }
 
+impl IRProgram {
+    /// The size, alignment and field offsets of every struct, sorted by name,
+    /// as printed by `--emit-layout`.
+    pub fn layout_report(&self) -> String {
+        let mut names: Vec<&String> = self.structs.keys().collect();
+        names.sort();
+
+        let mut out = String::new();
+        for name in names {
+            let def = &self.structs[name];
+            let keyword = if def.is_union { "union" } else { "struct" };
+            out.push_str(&format!(
+                "{keyword} {name}: size {}, align {}\n",
+                def.size, def.align
+            ));
+            for field in &def.order {
+                let (offset, ty) = &def.fields[field];
+                out.push_str(&format!("    {offset:>4}  {field}: {}\n", type_name(ty)));
+            }
+        }
+        out
+    }
+}
+
+/// `ty` as it is written in Quor source.
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::int => "int".to_string(),
+        Type::float => "float".to_string(),
+        Type::Long => "long".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Void => "void".to_string(),
+        Type::Pointer(inner) => format!("{}*", type_name(inner)),
+        Type::Array(elem, Some(n)) => format!("[{}, {n}]", type_name(elem)),
+        Type::Array(elem, None) => format!("[{}]", type_name(elem)),
+        Type::Struct { name, generics, .. } if !generics.is_empty() => format!(
+            "{name}<{}>",
+            generics.iter().map(type_name).collect::<Vec<_>>().join(", ")
+        ),
+        Type::Struct { name, .. } | Type::StructLiteral(name) => name.clone(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Signature of a C function declared with `@extern def`.
+#[derive(Debug, Clone)]
+pub struct ExternFn {
+    pub params: Vec<Type>,
+    pub ret_type: Type,
+    pub variadic: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct StructDef {
     pub name: String,
     pub fields: HashMap<String, (i32, Type)>,
+    pub order: Vec<String>, // field names in declaration order
     pub is_union: bool,
     pub size: usize,
+    pub align: usize,
 }
 
 #[derive(Debug, Clone)]
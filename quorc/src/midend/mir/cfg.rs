@@ -1,6 +1,18 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 
-use crate::{backend::lir::regalloc::RegWidth, frontend::ast::*, midend::mir::block::*};
+use crate::{
+    backend::lir::regalloc::RegWidth,
+    frontend::ast::*,
+    lint::{self, Lint},
+    midend::mir::{
+        block::*,
+        dataflow::{
+            HeapIssue, check_definite_assignment, check_returns, dead_stores, heap_misuse,
+        },
+        expr::{StructReturn, struct_def_name},
+    },
+    target::{alloc_fn, free_fn, opt_level},
+};
 
 #[derive(Default, Debug, Clone)]
 pub struct BlockIdGen {
@@ -22,11 +34,17 @@ impl BlockIdGen {
 #[derive(Clone, Debug, Default)]
 pub struct ScopeHandler {
     pub closed: HashSet<BlockId>,
-    pub break_stack: VecDeque<BlockId>,
-    pub continue_stack: VecDeque<BlockId>,
+    pub break_stack: VecDeque<(Option<String>, BlockId)>,
+    pub continue_stack: VecDeque<(Option<String>, BlockId)>,
     pub instructions: Vec<IRInstruction>,
     pub current_offset: i32,
     pub current: BlockId,
+    pub sret: Option<VReg>, // hidden return pointer of a function returning a struct in memory
+    pub local_layouts: HashMap<usize, (usize, usize)>,
+    pub uninit: HashMap<usize, String>, // vregs of `let x: T;` declarations, by name
+    pub var_regs: HashMap<usize, String>, // vregs of scalar variables, for dead-store warnings
+    pub address_taken: HashMap<usize, Type>, // vregs of variables used with `&`, by type
+    pub block_notes: HashMap<BlockId, &'static str>, // what each block is, for diagnostics
 }
 
 #[derive(Default)]
@@ -133,12 +151,46 @@ impl IRGenerator {
     pub fn generate(stmts: Vec<Stmt>) -> Result<IRProgram, String> {
         let mut ir_generator = IRGenerator::default();
 
+        // Skip generic templates and forward declarations; only concrete
+        // structs are laid out
+        let struct_decls: HashMap<String, Stmt> = stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::StructDecl {
+                    name,
+                    generics,
+                    opaque: false,
+                    ..
+                } if generics.is_empty() => Some((name.clone(), stmt.clone())),
+                _ => None,
+            })
+            .collect();
+
         for stmt in stmts.clone() {
-            if let Stmt::StructDecl { generics, .. } = &stmt {
-                if !generics.is_empty() {
-                    continue; // skip generic templates; only concrete monomorphized structs
-                }
-                ir_generator.generate_struct(&stmt)?
+            if let Stmt::StructDecl { name, .. } = &stmt
+                && struct_decls.contains_key(name)
+            {
+                ir_generator.generate_struct(name, &struct_decls)?
+            }
+            // C prototypes are known before any call to them is lowered
+            if let Stmt::AtDecl(decl, _, _, Some(proto)) = &stmt
+                && decl.as_str() == "extern"
+                && let Stmt::FunDecl {
+                    name,
+                    params,
+                    return_type,
+                    attributes,
+                    ..
+                } = &**proto
+            {
+                ir_generator.ir_program.extern_fns.insert(
+                    name.clone(),
+                    ExternFn {
+                        params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+                        ret_type: return_type.clone(),
+                        variadic: attributes.iter().any(|a| a == "variadic"),
+                    },
+                );
             }
         }
 
@@ -170,42 +222,59 @@ impl IRGenerator {
             })
             .collect::<Vec<_>>();
 
+        if opt_level() >= 1 {
+            ir_generator.inline_calls(opt_level() >= 2);
+        }
+
         Ok(ir_generator.ir_program)
     }
 
-    fn generate_struct(&mut self, stmt: &Stmt) -> Result<(), String> {
-        if let Stmt::StructDecl {
+    /// Lays out struct `name`, after any struct it holds by value, which may
+    /// be declared further down.
+    fn generate_struct(
+        &mut self,
+        name: &str,
+        decls: &HashMap<String, Stmt>,
+    ) -> Result<(), String> {
+        if self.ir_program.structs.contains_key(name) {
+            return Ok(());
+        }
+        if let Some(Stmt::StructDecl {
             name,
             instances,
             union,
+            layout,
             ..
-        } = stmt.clone()
+        }) = decls.get(name).cloned()
         {
-            let offsets = self.get_field_offsets(&instances, union);
-
-            let (max, max_typ) = offsets
-                .values()
-                .max_by_key(|(offset, _)| *offset)
-                .map(|(offset, ty)| (*offset, ty.clone()))
-                .unwrap_or((0, Type::Unknown));
-
-            if let Type::Unknown = max_typ {
-                let def = StructDef {
-                    name: name.clone(),
-                    fields: HashMap::new(),
-                    is_union: union,
-                    size: 0,
-                };
-
-                self.ir_program.structs.insert(name, def);
-                return Ok(());
+            for (_, ty) in &instances {
+                let mut ty = ty;
+                while let Type::Array(elem, _) = ty {
+                    ty = elem;
+                }
+                if let Type::Struct { name, generics, .. } = ty {
+                    self.generate_struct(&struct_def_name(name, generics), decls)?;
+                }
             }
 
+            let (offsets, size, align) = layout.arrange(
+                instances
+                    .iter()
+                    .map(|(field, ty)| (field.as_str(), self.field_layout(ty))),
+                union,
+            );
+
             let def = StructDef {
                 name: name.clone(),
-                fields: offsets,
+                fields: instances
+                    .iter()
+                    .zip(offsets)
+                    .map(|((field, ty), off)| (field.clone(), (off as i32, ty.clone())))
+                    .collect(),
+                order: instances.iter().map(|(field, _)| field.clone()).collect(),
                 is_union: union,
-                size: max as usize + max_typ.size(),
+                size,
+                align,
             };
 
             self.ir_program.structs.insert(name, def);
@@ -222,7 +291,20 @@ impl IRGenerator {
         if let Stmt::AtDecl(decl, param, val, _content) = stmt {
             match decl.as_str() {
                 "extern" => {
-                    self.ir_program.externs.push(param.clone().unwrap());
+                    let name = param.clone().unwrap();
+                    if !self.ir_program.externs.contains(&name) {
+                        self.ir_program.externs.push(name);
+                    }
+                }
+                "__asm__" | "_asm_" | "asm" => self.push_section_asm(Section::Text, param),
+                "__asm_data__" | "_asm_data_" | "asm_data" => {
+                    self.push_section_asm(Section::Data, param)
+                }
+                "__asm_ro__" | "_asm_ro_" | "asm_ro" => {
+                    self.push_section_asm(Section::Rodata, param)
+                }
+                "__asm_bss__" | "_asm_bss_" | "asm_bss" => {
+                    self.push_section_asm(Section::Bss, param)
                 }
                 "const" => {
                     // let const_value = match val.clone().unwrap() {
@@ -271,6 +353,14 @@ impl IRGenerator {
         Ok(())
     }
 
+    fn push_section_asm(&mut self, section: Section, content: &Option<String>) {
+        if let Some(content) = content {
+            self.ir_program
+                .section_asm
+                .push((section, content.trim().to_string()));
+        }
+    }
+
     fn get_const_value(expr: Expr) -> GlobalValue {
         match expr {
             Expr::IntLiteral(i) => GlobalValue::Int(i.into()),
@@ -291,6 +381,90 @@ impl IRGenerator {
         }
     }
 
+    /// Moves the variables whose address is taken from their vreg to a stack
+    /// slot: every read loads from the slot and every write stores to it.
+    fn spill_address_taken(&mut self, func: &mut IRFunction) {
+        let mut vars: Vec<(usize, Type)> =
+            std::mem::take(&mut self.scope_handler.address_taken).into_iter().collect();
+        vars.sort_by_key(|(id, _)| *id);
+
+        for (id, ty) in vars {
+            let layout = self.field_layout(&ty);
+            let local = self.var_gen.fresh();
+            func.offset += layout.0 as i32;
+            func.local_layouts.insert(local, layout);
+            let slot = Value::Local(local);
+
+            let load = |generator: &mut Self, reg: VReg, out: &mut Vec<IRInstruction>| {
+                let tmp = generator.vreg_gen.fresh(reg.is_fpr(), reg.width);
+                out.push(IRInstruction::Load {
+                    reg: tmp,
+                    addr: slot.clone(),
+                    offset: 0,
+                    ty: ty.clone(),
+                });
+                tmp
+            };
+
+            for block in &mut func.blocks {
+                let mut instructions = Vec::with_capacity(block.instructions.len());
+                for mut inst in std::mem::take(&mut block.instructions) {
+                    if let IRInstruction::AddressOf { src, .. } = &mut inst
+                        && matches!(src, Value::Reg(var) if var.id == id)
+                    {
+                        *src = slot.clone();
+                        instructions.push(inst);
+                        continue;
+                    }
+                    for value in inst.uses_mut() {
+                        if let Value::Reg(reg) = value
+                            && reg.id == id
+                        {
+                            *value = Value::Reg(load(self, *reg, &mut instructions));
+                        }
+                    }
+                    let mut stores = Vec::new();
+                    for reg in inst.defs_mut() {
+                        if reg.id == id {
+                            *reg = self.vreg_gen.fresh(reg.is_fpr(), reg.width);
+                            stores.push(IRInstruction::Store {
+                                value: Value::Reg(*reg),
+                                addr: slot.clone(),
+                                offset: 0,
+                                ty: ty.clone(),
+                            });
+                        }
+                    }
+                    instructions.push(inst);
+                    instructions.extend(stores);
+                }
+                for value in block.terminator.uses_mut() {
+                    if let Value::Reg(reg) = value
+                        && reg.id == id
+                    {
+                        *value = Value::Reg(load(self, *reg, &mut instructions));
+                    }
+                }
+                block.instructions = instructions;
+            }
+
+            // A parameter arrives in its vreg and is stored once on entry
+            if let Some(param) = func.params.iter().find(|param| param.id == id)
+                && let Some(entry) = func.blocks.iter_mut().find(|b| b.id == func.entry)
+            {
+                entry.instructions.insert(
+                    0,
+                    IRInstruction::Store {
+                        value: Value::Reg(*param),
+                        addr: slot.clone(),
+                        offset: 0,
+                        ty: ty.clone(),
+                    },
+                );
+            }
+        }
+    }
+
     fn generate_function(&mut self, func: &Stmt) -> Result<(), String> {
         if let Stmt::FunDecl {
             name,
@@ -307,12 +481,20 @@ impl IRGenerator {
             self.scope_handler.current = entry;
 
             let mut params = Vec::with_capacity(func_params.len());
+            if let Some(StructReturn::Memory) = self.struct_return(return_type) {
+                self.scope_handler.sret = Some(self.vreg_gen.fresh(false, RegWidth::W64));
+            }
+
             for (param_name, param_ty) in func_params.clone() {
                 let param_reg = self
                     .vreg_gen
                     .fresh(Type::float == param_ty, type_to_reg_width(&param_ty));
-                if param_ty.fits_in_register() {
-                    // Primitive or pointer: keep in VReg, no stack allocation needed
+                if param_ty.fits_in_register() || matches!(param_ty, Type::Array(_, None)) {
+                    // Primitive or pointer: keep in VReg, no stack allocation needed.
+                    // Unsized arrays are passed by reference and indexed through it.
+                    self.scope_handler
+                        .var_regs
+                        .insert(param_reg.id, param_name.clone());
                     self.var_map
                         .insert(param_name, (param_ty.clone(), Value::Reg(param_reg)));
                 } else if let Type::Struct {
@@ -320,7 +502,7 @@ impl IRGenerator {
                 } = &param_ty
                 {
                     // Struct: allocate stack slot and copy from param (param_reg holds address)
-                    let local = self.var_gen.fresh();
+                    let local = self.new_local(self.field_layout(&param_ty));
                     self.var_map
                         .insert(param_name.clone(), (param_ty.clone(), Value::Local(local)));
                     self.allocate_struct_on_stack(
@@ -342,7 +524,7 @@ impl IRGenerator {
                 //     });
                 // }
                 } else {
-                    let local = self.var_gen.fresh();
+                    let local = self.new_local(self.field_layout(&param_ty));
                     self.var_map
                         .insert(param_name.clone(), (param_ty.clone(), Value::Local(local)));
 
@@ -352,8 +534,8 @@ impl IRGenerator {
                     self.scope_handler.instructions.push(IRInstruction::Memcpy {
                         dst: dst_addr,
                         src: src_addr,
-                        size: param_ty.size(),
-                        align: param_ty.align(),
+                        size: self.field_layout(&param_ty).0,
+                        align: self.field_layout(&param_ty).1,
                     });
                 }
 
@@ -364,11 +546,25 @@ impl IRGenerator {
 
             self.lower_block(body);
 
+            // @trust_ret functions leave their result in the return register themselves
+            let current = self.scope_handler.current;
+            if (*return_type == Type::Void || attributes.contains(&"trust_ret".to_string()))
+                && let Terminator::TemporaryNone = self.blocks[current.0].terminator
+            {
+                self.set_terminator(
+                    current,
+                    Terminator::Return {
+                        value: None,
+                        value_hi: None,
+                    },
+                );
+            }
+
             self.blocks[self.scope_handler.current.0]
                 .instructions
                 .append(&mut self.scope_handler.instructions);
 
-            let mut blocks: Vec<IRBlock> = self
+            let blocks: Vec<IRBlock> = self
                 .scope_handler
                 .closed
                 .iter()
@@ -376,20 +572,15 @@ impl IRGenerator {
                 .collect();
 
             self.scope_handler.closed = HashSet::new();
-
-            if let Some(last) = blocks.last_mut()
-                && *return_type == Type::Void
-                && let Terminator::TemporaryNone = last.terminator
-            {
-                last.terminator = Terminator::Return { value: None };
-            }
+            let sret = self.scope_handler.sret.take();
 
             let offset = self.scope_handler.current_offset;
             self.scope_handler.current_offset = 0;
 
-            let ir_func = IRFunction {
+            let mut ir_func = IRFunction {
                 name: name.clone(),
                 params,
+                sret,
                 ret_type: return_type.clone(),
                 blocks,
                 entry,
@@ -398,8 +589,40 @@ impl IRGenerator {
                     .filter_map(|attr| AtDecl::parse_attribute(attr.as_str()))
                     .collect(),
                 offset,
+                local_layouts: std::mem::take(&mut self.scope_handler.local_layouts),
             };
 
+            check_returns(&ir_func, &std::mem::take(&mut self.scope_handler.block_notes))?;
+            check_definite_assignment(&ir_func, &std::mem::take(&mut self.scope_handler.uninit))?;
+            let var_regs = std::mem::take(&mut self.scope_handler.var_regs);
+            for var in dead_stores(&ir_func, &var_regs) {
+                lint::emit(
+                    Lint::DeadStores,
+                    attributes,
+                    &format!("value assigned to '{var}' in function '{name}' is never read"),
+                );
+            }
+            let (alloc, free) = ([alloc_fn(), "malloc"], [free_fn(), "free"]);
+            for issue in heap_misuse(&ir_func, &var_regs, &alloc, &free) {
+                let (lint, message) = match issue {
+                    HeapIssue::UseAfterFree(var) => (
+                        Lint::UseAfterFree,
+                        format!("'{var}' is used after being freed in function '{name}'"),
+                    ),
+                    HeapIssue::DoubleFree(var) => (
+                        Lint::DoubleFree,
+                        format!("'{var}' is freed twice in function '{name}'"),
+                    ),
+                    HeapIssue::Leak(var) => (
+                        Lint::MemoryLeak,
+                        format!("memory held by '{var}' is never freed in function '{name}'"),
+                    ),
+                };
+                lint::emit(lint, attributes, &message);
+            }
+
+            self.spill_address_taken(&mut ir_func);
+
             self.var_map = HashMap::new();
             self.ir_program.functions.insert(name.to_string(), ir_func);
         }
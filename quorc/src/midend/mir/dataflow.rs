@@ -0,0 +1,540 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::midend::mir::block::*;
+
+fn reachable(func: &IRFunction) -> HashSet<BlockId> {
+    let blocks: HashMap<BlockId, &IRBlock> = func.blocks.iter().map(|b| (b.id, b)).collect();
+    let mut seen = HashSet::from([func.entry]);
+    let mut stack = vec![func.entry];
+    while let Some(id) = stack.pop() {
+        let Some(block) = blocks.get(&id) else {
+            continue;
+        };
+        for next in block.terminator.successors() {
+            if seen.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+    seen
+}
+
+/// Reports a reachable block that falls off the end of a non-void function,
+/// along with the path that reaches it.
+pub fn check_returns(
+    func: &IRFunction,
+    notes: &HashMap<BlockId, &'static str>,
+) -> Result<(), String> {
+    let blocks: HashMap<BlockId, &IRBlock> = func.blocks.iter().map(|b| (b.id, b)).collect();
+
+    // Breadth-first, so the reported path is a shortest one
+    let mut parent: HashMap<BlockId, BlockId> = HashMap::new();
+    let mut seen = HashSet::from([func.entry]);
+    let mut queue = VecDeque::from([func.entry]);
+
+    while let Some(id) = queue.pop_front() {
+        let Some(block) = blocks.get(&id) else {
+            continue;
+        };
+
+        if let Terminator::TemporaryNone = block.terminator {
+            let mut path = vec![id];
+            while let Some(prev) = parent.get(path.last().unwrap()) {
+                path.push(*prev);
+            }
+            let path = path
+                .iter()
+                .rev()
+                .filter_map(|id| notes.get(id).copied())
+                .chain(["end of function"])
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(format!(
+                "Function '{}': not all paths return a value (missing return on path: {path})",
+                func.name
+            ));
+        }
+
+        for next in block.terminator.successors() {
+            if seen.insert(next) {
+                parent.insert(next, id);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The registers `inst` reads and writes. Taking the address of a variable
+/// counts as writing it, since whatever receives the pointer may store through it.
+fn reads_writes(inst: &IRInstruction) -> (Vec<usize>, Vec<usize>) {
+    let mut defined: Vec<usize> = inst.defs().iter().map(|r| r.id).collect();
+    if let IRInstruction::AddressOf {
+        src: Value::Reg(var),
+        ..
+    } = inst
+    {
+        defined.push(var.id);
+        return (Vec::new(), defined);
+    }
+    let used = inst
+        .uses()
+        .into_iter()
+        .filter_map(|value| match value {
+            Value::Reg(reg) => Some(reg.id),
+            _ => None,
+        })
+        .collect();
+    (used, defined)
+}
+
+/// Forward must-analysis over `tracked` vregs (variables declared without an
+/// initializer): a use is an error unless every path to it assigns the variable.
+pub fn check_definite_assignment(
+    func: &IRFunction,
+    tracked: &HashMap<usize, String>,
+) -> Result<(), String> {
+    if tracked.is_empty() {
+        return Ok(());
+    }
+
+    let all: HashSet<usize> = tracked.keys().copied().collect();
+    let reachable = reachable(func);
+    let mut blocks: Vec<&IRBlock> = func
+        .blocks
+        .iter()
+        .filter(|b| reachable.contains(&b.id))
+        .collect();
+    blocks.sort_by_key(|b| b.id);
+
+    let mut preds: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+    for block in &blocks {
+        for next in block.terminator.successors() {
+            preds.entry(next).or_default().push(block.id);
+        }
+    }
+
+    let assigned_in = |block: &IRBlock, mut set: HashSet<usize>| {
+        for inst in &block.instructions {
+            let (_, defined) = reads_writes(inst);
+            set.extend(defined.into_iter().filter(|id| all.contains(id)));
+        }
+        set
+    };
+
+    // Everything starts out assigned and is narrowed down to a fixpoint
+    let mut outs: HashMap<BlockId, HashSet<usize>> =
+        blocks.iter().map(|b| (b.id, all.clone())).collect();
+    let entry_in = |id: BlockId, outs: &HashMap<BlockId, HashSet<usize>>| {
+        if id == func.entry {
+            return HashSet::new();
+        }
+        preds
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|p| outs.get(p))
+            .fold(None, |acc: Option<HashSet<usize>>, out| match acc {
+                None => Some(out.clone()),
+                Some(acc) => Some(acc.intersection(out).copied().collect()),
+            })
+            .unwrap_or_default()
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &blocks {
+            let out = assigned_in(block, entry_in(block.id, &outs));
+            if outs[&block.id] != out {
+                outs.insert(block.id, out);
+                changed = true;
+            }
+        }
+    }
+
+    for block in &blocks {
+        let mut assigned = entry_in(block.id, &outs);
+        let terminator_reads = block
+            .terminator
+            .uses()
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Reg(reg) => Some(reg.id),
+                _ => None,
+            })
+            .collect();
+        let uses = block
+            .instructions
+            .iter()
+            .map(reads_writes)
+            .chain([(terminator_reads, Vec::new())]);
+
+        for (used, defined) in uses {
+            for id in used {
+                if let Some(name) = tracked.get(&id)
+                    && !assigned.contains(&id)
+                {
+                    return Err(format!(
+                        "Function '{}': variable '{name}' used before being assigned",
+                        func.name
+                    ));
+                }
+            }
+            assigned.extend(defined);
+        }
+    }
+
+    Ok(())
+}
+
+/// Backward liveness over `vars` (vregs of named scalar variables): returns the
+/// names of variables that are assigned a value no path ever reads. Variables
+/// that are never read at all are left to the unused-variable lint.
+pub fn dead_stores(func: &IRFunction, vars: &HashMap<usize, String>) -> Vec<String> {
+    if vars.is_empty() {
+        return Vec::new();
+    }
+
+    let reachable = reachable(func);
+    let blocks: Vec<&IRBlock> = func
+        .blocks
+        .iter()
+        .filter(|b| reachable.contains(&b.id))
+        .collect();
+
+    let read_regs = |values: Vec<&Value>| -> Vec<usize> {
+        values
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Reg(reg) if vars.contains_key(&reg.id) => Some(reg.id),
+                _ => None,
+            })
+            .collect()
+    };
+    let read_anywhere: HashSet<usize> = blocks
+        .iter()
+        .flat_map(|b| {
+            b.instructions
+                .iter()
+                .flat_map(|inst| inst.uses())
+                .chain(b.terminator.uses())
+        })
+        .filter_map(|value| match value {
+            Value::Reg(reg) => Some(reg.id),
+            _ => None,
+        })
+        .collect();
+
+    // Walks `block` backwards from `live` at its end, calling `dead` on each
+    // move into a variable that is not live afterwards
+    let step = |block: &IRBlock, mut live: HashSet<usize>, dead: &mut dyn FnMut(usize)| {
+        live.extend(read_regs(block.terminator.uses()));
+        for inst in block.instructions.iter().rev() {
+            if let IRInstruction::Move { dest, .. } = inst
+                && vars.contains_key(&dest.id)
+                && !live.contains(&dest.id)
+            {
+                dead(dest.id);
+            }
+            for def in inst.defs() {
+                live.remove(&def.id);
+            }
+            live.extend(read_regs(inst.uses()));
+        }
+        live
+    };
+
+    let mut live_in: HashMap<BlockId, HashSet<usize>> = HashMap::new();
+    let live_out = |block: &IRBlock, live_in: &HashMap<BlockId, HashSet<usize>>| {
+        block
+            .terminator
+            .successors()
+            .iter()
+            .filter_map(|next| live_in.get(next))
+            .flatten()
+            .copied()
+            .collect::<HashSet<usize>>()
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in blocks.iter().rev() {
+            let live = step(block, live_out(block, &live_in), &mut |_| {});
+            if live_in.get(&block.id) != Some(&live) {
+                live_in.insert(block.id, live);
+                changed = true;
+            }
+        }
+    }
+
+    let mut dead = HashSet::new();
+    for block in &blocks {
+        step(block, live_out(block, &live_in), &mut |id| {
+            if read_anywhere.contains(&id) {
+                dead.insert(id);
+            }
+        });
+    }
+
+    let mut names: Vec<String> = dead.iter().map(|id| vars[id].clone()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// A definite misuse of heap memory held in a local, by variable name.
+pub enum HeapIssue {
+    UseAfterFree(String),
+    DoubleFree(String),
+    Leak(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Heap {
+    Live,
+    Freed,
+    Escaped,
+    Unknown,
+}
+
+/// Points-to facts (vreg -> allocating call's vreg) and the status of each
+/// allocation along every path reaching a program point.
+#[derive(Clone, PartialEq, Default)]
+struct HeapState {
+    points: HashMap<usize, usize>,
+    status: HashMap<usize, Heap>,
+}
+
+impl HeapState {
+    fn meet(&self, other: &HeapState) -> HeapState {
+        let points = self
+            .points
+            .iter()
+            .filter(|(reg, alloc)| other.points.get(reg) == Some(alloc))
+            .map(|(reg, alloc)| (*reg, *alloc))
+            .collect();
+        let status = self
+            .status
+            .keys()
+            .chain(other.status.keys())
+            .map(|alloc| match (self.status.get(alloc), other.status.get(alloc)) {
+                (Some(a), Some(b)) if a == b => (*alloc, *a),
+                _ => (*alloc, Heap::Unknown),
+            })
+            .collect();
+        HeapState { points, status }
+    }
+
+    fn target(&self, value: &Value) -> Option<usize> {
+        match value {
+            Value::Reg(reg) => self.points.get(&reg.id).copied(),
+            _ => None,
+        }
+    }
+}
+
+/// Forward must-analysis of pointers returned by `alloc` and released by
+/// `free`: reports dereferences of memory freed on every path, second frees,
+/// and allocations that reach a return still live without ever escaping.
+/// Only allocations assigned to one of the named `vars` are reported.
+pub fn heap_misuse(
+    func: &IRFunction,
+    vars: &HashMap<usize, String>,
+    alloc: &[&str],
+    free: &[&str],
+) -> Vec<HeapIssue> {
+    let reachable = reachable(func);
+    let mut blocks: Vec<&IRBlock> = func
+        .blocks
+        .iter()
+        .filter(|b| reachable.contains(&b.id))
+        .collect();
+    blocks.sort_by_key(|b| b.id);
+
+    // The first variable each allocation is assigned to names it
+    let mut names: HashMap<usize, &String> = HashMap::new();
+    let mut origin: HashMap<usize, usize> = HashMap::new();
+    for inst in blocks.iter().flat_map(|b| &b.instructions) {
+        match inst {
+            IRInstruction::Call {
+                reg: Some(reg),
+                func,
+                ..
+            } if alloc.contains(&func.as_str()) => {
+                origin.insert(reg.id, reg.id);
+            }
+            IRInstruction::Move {
+                dest,
+                from: Value::Reg(src),
+            } => {
+                if let Some(site) = origin.get(&src.id).copied() {
+                    origin.insert(dest.id, site);
+                    if let Some(name) = vars.get(&dest.id) {
+                        names.entry(site).or_insert(name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if names.is_empty() {
+        return Vec::new();
+    }
+
+    let mut preds: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+    for block in &blocks {
+        for next in block.terminator.successors() {
+            preds.entry(next).or_default().push(block.id);
+        }
+    }
+
+    // Names a report after the variable used, falling back to the allocation
+    let name_of = |value: &Value, site: usize| match value {
+        Value::Reg(reg) if vars.contains_key(&reg.id) => vars[&reg.id].clone(),
+        _ => names[&site].clone(),
+    };
+
+    let step = |block: &IRBlock, mut state: HeapState, report: &mut dyn FnMut(HeapIssue)| {
+        for inst in &block.instructions {
+            let derefs: Vec<&Value> = match inst {
+                IRInstruction::Load { addr, .. }
+                | IRInstruction::Store { addr, .. }
+                | IRInstruction::AtomicLoad { addr, .. }
+                | IRInstruction::AtomicStore { addr, .. }
+                | IRInstruction::AtomicRmw { addr, .. }
+                | IRInstruction::AtomicCmpXchg { addr, .. } => vec![addr],
+                IRInstruction::Memcpy { dst, src, .. } => vec![dst, src],
+                _ => Vec::new(),
+            };
+            for addr in &derefs {
+                if let Some(site) = state.target(addr)
+                    && state.status.get(&site) == Some(&Heap::Freed)
+                    && names.contains_key(&site)
+                {
+                    report(HeapIssue::UseAfterFree(name_of(addr, site)));
+                }
+            }
+
+            let mut alias = None;
+            match inst {
+                IRInstruction::Call { func, args, .. } if free.contains(&func.as_str()) => {
+                    if let Some(arg) = args.first()
+                        && let Some(site) = state.target(arg)
+                    {
+                        if state.status.get(&site) == Some(&Heap::Freed)
+                            && names.contains_key(&site)
+                        {
+                            report(HeapIssue::DoubleFree(name_of(arg, site)));
+                        }
+                        state.status.insert(site, Heap::Freed);
+                    }
+                }
+                IRInstruction::Move { from, .. } => alias = state.target(from),
+                IRInstruction::Gep { base, .. } => alias = state.target(base),
+                IRInstruction::Eq { .. }
+                | IRInstruction::Ne { .. }
+                | IRInstruction::Lt { .. }
+                | IRInstruction::Le { .. }
+                | IRInstruction::Ge { .. }
+                | IRInstruction::Gt { .. } => {}
+                // Anything else handed the pointer may keep it
+                _ => {
+                    let escaped: Vec<usize> = inst
+                        .uses()
+                        .into_iter()
+                        .filter(|value| !derefs.iter().any(|addr| std::ptr::eq(*addr, *value)))
+                        .filter_map(|value| state.target(value))
+                        .collect();
+                    for site in escaped {
+                        if state.status.get(&site) == Some(&Heap::Live) {
+                            state.status.insert(site, Heap::Escaped);
+                        }
+                    }
+                }
+            }
+
+            for def in inst.defs() {
+                state.points.remove(&def.id);
+            }
+            if let IRInstruction::Call {
+                reg: Some(reg),
+                func,
+                ..
+            } = inst
+                && alloc.contains(&func.as_str())
+            {
+                alias = Some(reg.id);
+                state.status.insert(reg.id, Heap::Live);
+            }
+            if let (Some(site), Some(def)) = (alias, inst.defs().first()) {
+                state.points.insert(def.id, site);
+            }
+        }
+
+        if let Terminator::Return { value, value_hi } = &block.terminator {
+            for value in value.iter().chain(value_hi) {
+                if let Some(site) = state.target(value)
+                    && state.status.get(&site) == Some(&Heap::Live)
+                {
+                    state.status.insert(site, Heap::Escaped);
+                }
+            }
+            for (site, status) in &state.status {
+                if *status == Heap::Live
+                    && let Some(name) = names.get(site)
+                {
+                    report(HeapIssue::Leak((*name).clone()));
+                }
+            }
+        }
+        state
+    };
+
+    // Blocks not yet visited don't constrain their successors
+    let mut outs: HashMap<BlockId, HeapState> = HashMap::new();
+    let entry_in = |id: BlockId, outs: &HashMap<BlockId, HeapState>| {
+        let start = (id == func.entry).then(HeapState::default);
+        preds
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|p| outs.get(p))
+            .fold(start, |acc: Option<HeapState>, out| match acc {
+                None => Some(out.clone()),
+                Some(acc) => Some(acc.meet(out)),
+            })
+            .unwrap_or_default()
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &blocks {
+            let out = step(block, entry_in(block.id, &outs), &mut |_| {});
+            if outs.get(&block.id) != Some(&out) {
+                outs.insert(block.id, out);
+                changed = true;
+            }
+        }
+    }
+
+    let mut issues = Vec::new();
+    let mut seen = HashSet::new();
+    for block in &blocks {
+        step(block, entry_in(block.id, &outs), &mut |issue| {
+            let key = match &issue {
+                HeapIssue::UseAfterFree(name) => (0, name.clone()),
+                HeapIssue::DoubleFree(name) => (1, name.clone()),
+                HeapIssue::Leak(name) => (2, name.clone()),
+            };
+            if seen.insert(key) {
+                issues.push(issue);
+            }
+        });
+    }
+    issues
+}
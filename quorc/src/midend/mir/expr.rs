@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use crate::{
     backend::lir::regalloc::RegWidth,
     frontend::ast::{BinaryOp, Expr, Type, UnaryOp},
@@ -7,156 +5,368 @@ use crate::{
         analyzer::mangle_name,
         mir::{block::*, cfg::*},
     },
+    target::alloc_fn,
 };
 
-fn round_up(x: usize, align: usize) -> usize {
-    debug_assert!(align.is_power_of_two());
-    (x + align - 1) & !(align - 1)
+/// How a struct travels back from a call.
+pub enum StructReturn {
+    /// Up to two integer or pointer fields, returned in the first two return registers.
+    Regs(Vec<(i32, Type)>),
+    /// Written through a hidden pointer the caller passes alongside the arguments.
+    Memory,
+}
+
+/// Name of the struct definition backing `name<generics>`.
+pub(crate) fn struct_def_name(name: &str, generics: &[Type]) -> String {
+    if generics.is_empty() {
+        name.to_owned()
+    } else {
+        mangle_name(name, generics)
+    }
 }
 
 impl IRGenerator {
     pub fn allocate_struct_on_stack(&mut self, local: Value, param_reg: VReg, struct_name: String) {
-        let fields = {
-            let struct_def = self
-                .ir_program
-                .structs
-                .get(&struct_name)
-                .expect("struct definition not found for parameter");
-            struct_def.fields.clone()
-        };
+        let ty = self.type_struct(&struct_name);
+        self.copy_bytes(Value::Reg(param_reg), 0, local, 0, &ty);
+    }
 
-        self.copy_struct_fields(Value::Reg(param_reg), local, &fields);
+    /// Allocates a stack local with the `(size, align)` layout in the current
+    /// function's frame.
+    pub fn new_local(&mut self, layout: (usize, usize)) -> usize {
+        let id = self.var_gen.fresh();
+        self.scope_handler.current_offset += layout.0 as i32;
+        self.scope_handler.local_layouts.insert(id, layout);
+        id
     }
 
-    fn copy_struct_fields(
-        &mut self,
-        src: Value,
-        dst: Value,
-        fields: &HashMap<String, (i32, Type)>,
-    ) {
-        self.copy_struct_fields_with_base_offset(src, dst, fields, 0);
+    /// `(size, align)` of `ty`, taking nested struct layouts from their definitions.
+    pub fn field_layout(&self, ty: &Type) -> (usize, usize) {
+        match ty {
+            Type::Struct { name, generics, .. } => {
+                match self.ir_program.structs.get(&struct_def_name(name, generics)) {
+                    Some(def) => (def.size, def.align),
+                    None => (ty.size(), ty.align()),
+                }
+            }
+            Type::Array(elem, Some(n)) => {
+                let (size, align) = self.field_layout(elem);
+                (size * n, align)
+            }
+            _ => (ty.size(), ty.align()),
+        }
     }
 
-    fn copy_struct_fields_with_base_offset(
-        &mut self,
-        src: Value,
-        dst: Value,
-        fields: &HashMap<String, (i32, Type)>,
-        base_offset: i32,
-    ) {
-        for (field_offset, field_ty) in fields.values() {
-            let total_offset = base_offset + *field_offset;
-
-            match field_ty {
-                Type::Struct {
-                    name: nested_struct_name,
-                    ..
-                } => {
-                    let nested_fields = {
-                        let nested_struct_def = self
-                            .ir_program
-                            .structs
-                            .get(nested_struct_name)
-                            .expect("nested struct definition not found");
-                        nested_struct_def.fields.clone()
-                    };
+    /// Stores `value` into the field at `offset` of `addr`, copying nested
+    /// structs and arrays as a block.
+    fn store_field(&mut self, value: Value, addr: Value, offset: i32, ty: &Type) {
+        if let Type::Struct { .. } | Type::Array(..) = ty {
+            self.copy_bytes(value, 0, addr, offset, ty);
+        } else {
+            self.scope_handler.instructions.push(IRInstruction::Store {
+                value,
+                addr,
+                offset,
+                ty: ty.clone(),
+            });
+        }
+    }
 
-                    self.copy_struct_fields_with_base_offset(
-                        src.clone(),
-                        dst.clone(),
-                        &nested_fields,
-                        total_offset,
-                    );
+    /// Stores each listed field of struct `name` at `addr`, zeroing the rest
+    /// when the literal asked for defaults.
+    fn init_struct(&mut self, name: &str, params: Vec<(String, Expr)>, default: bool, addr: Value) {
+        let def = &self.ir_program.structs[name];
+        let (size, is_union, layout) = (def.size, def.is_union, def.fields.clone());
+
+        if default && is_union && params.is_empty() {
+            self.zero_fill(addr.clone(), 0, size);
+        }
+        if default && !is_union {
+            for (field_name, (field_off, field_ty)) in &layout {
+                if !params.iter().any(|(name, _)| name == field_name) {
+                    let field_size = self.field_layout(field_ty).0;
+                    self.zero_fill(addr.clone(), *field_off, field_size);
                 }
-                _ => {
-                    let temp_reg = self
-                        .vreg_gen
-                        .fresh(field_ty.clone() == Type::float, type_to_reg_width(field_ty));
+            }
+        }
 
-                    self.scope_handler.instructions.push(IRInstruction::Load {
-                        reg: temp_reg,
-                        addr: src.clone(),
-                        offset: total_offset,
-                        ty: field_ty.clone(),
-                    });
+        for (field_name, field_expr) in params {
+            let (field_off, field_ty) = &layout[&field_name];
+            let (value, value_ty) = self.first_pass_parse_expr(field_expr).unwrap();
+            let value = self.ensure_rvalue(value, &value_ty);
+            self.store_field(value, addr.clone(), *field_off, field_ty);
+        }
+    }
 
-                    self.scope_handler.instructions.push(IRInstruction::Store {
-                        value: Value::Reg(temp_reg),
-                        addr: dst.clone(),
-                        offset: total_offset,
-                        ty: field_ty.clone(),
-                    });
+    /// Copies a value of aggregate type `ty` between two addresses.
+    fn copy_bytes(&mut self, src: Value, src_off: i32, dst: Value, dst_off: i32, ty: &Type) {
+        let (size, align) = self.field_layout(ty);
+        let src = self.offset_address(src, src_off);
+        let dst = self.offset_address(dst, dst_off);
+        self.scope_handler
+            .instructions
+            .push(IRInstruction::Memcpy {
+                dst,
+                src,
+                size,
+                align,
+            });
+    }
+
+    /// Byte ranges of `ty` that hold data, skipping padding. Adjacent ranges
+    /// are merged and a union counts as a single range over its whole size.
+    fn data_regions(&self, ty: &Type, base: usize, out: &mut Vec<(usize, usize)>) {
+        let (start, len) = match ty {
+            Type::Struct { name, generics, .. } => {
+                let def = &self.ir_program.structs[&struct_def_name(name, generics)];
+                if !def.is_union {
+                    for field in &def.order {
+                        let (offset, field_ty) = &def.fields[field];
+                        self.data_regions(field_ty, base + *offset as usize, out);
+                    }
+                    return;
                 }
+                (base, def.size)
             }
+            Type::Array(elem, Some(n)) => {
+                let stride = self.field_layout(elem).0;
+                for i in 0..*n {
+                    self.data_regions(elem, base + i * stride, out);
+                }
+                return;
+            }
+            _ => (base, self.field_layout(ty).0),
+        };
+        match out.last_mut() {
+            Some(last) if last.0 + last.1 == start => last.1 += len,
+            _ => out.push((start, len)),
         }
     }
 
-    pub fn get_field_offsets(
-        &self,
-        fields: &Vec<(String, Type)>,
-        is_union: bool,
-    ) -> HashMap<String, (i32, Type)> {
-        let mut map = HashMap::new();
-
-        if is_union {
-            for (name, ty) in fields {
-                map.insert(name.clone(), (0, ty.clone()));
+    /// Compares two structs of type `ty` byte for byte over their fields,
+    /// leaving the `==` (or `!=` when `negate`) result in a bool register.
+    fn compare_structs(&mut self, left: Value, right: Value, ty: &Type, negate: bool) -> VReg {
+        let mut regions = Vec::new();
+        self.data_regions(ty, 0, &mut regions);
+
+        // Sum of the chunks that differ
+        let mut diff = None;
+        for (start, len) in regions {
+            let mut done = 0;
+            for (width, chunk_ty) in [(8, Type::Long), (4, Type::int), (1, Type::Char)] {
+                while len - done >= width {
+                    let offset = (start + done) as i32;
+                    let [l, r] = [&left, &right].map(|addr| {
+                        let reg = self.vreg_gen.fresh(false, type_to_reg_width(&chunk_ty));
+                        self.scope_handler.instructions.push(IRInstruction::Load {
+                            reg,
+                            addr: addr.clone(),
+                            offset,
+                            ty: chunk_ty.clone(),
+                        });
+                        Value::Reg(reg)
+                    });
+                    let ne = self.vreg_gen.fresh(false, RegWidth::W32);
+                    self.scope_handler.instructions.push(IRInstruction::Ne {
+                        reg: ne,
+                        left: l,
+                        right: r,
+                    });
+                    diff = Some(match diff {
+                        None => Value::Reg(ne),
+                        Some(acc) => {
+                            let sum = self.vreg_gen.fresh(false, RegWidth::W32);
+                            self.scope_handler.instructions.push(IRInstruction::Add {
+                                reg: sum,
+                                left: acc,
+                                right: Value::Reg(ne),
+                            });
+                            Value::Reg(sum)
+                        }
+                    });
+                    done += width;
+                }
             }
-            return map;
         }
 
-        let mut off: usize = 0;
+        let reg = self.vreg_gen.fresh(false, type_to_reg_width(&Type::Bool));
+        let instr = match diff {
+            None => IRInstruction::Move {
+                dest: reg,
+                from: Value::Const(!negate as i64),
+            },
+            Some(diff) if negate => IRInstruction::Ne {
+                reg,
+                left: diff,
+                right: Value::Const(0),
+            },
+            Some(diff) => IRInstruction::Eq {
+                reg,
+                left: diff,
+                right: Value::Const(0),
+            },
+        };
+        self.scope_handler.instructions.push(instr);
+        reg
+    }
+
+    /// Zeroes `size` bytes at `offset` of `addr`, widest stores first.
+    fn zero_fill(&mut self, addr: Value, offset: i32, size: usize) {
+        let mut done = 0;
+        for (width, ty) in [(8, Type::Long), (4, Type::int), (1, Type::Char)] {
+            while size - done >= width {
+                self.scope_handler.instructions.push(IRInstruction::Store {
+                    value: Value::Const(0),
+                    addr: addr.clone(),
+                    offset: offset + done as i32,
+                    ty: ty.clone(),
+                });
+                done += width;
+            }
+        }
+    }
 
-        for (name, ty) in fields {
-            let a = ty.align();
-            off = round_up(off, a);
-            map.insert(name.clone(), (off as i32, ty.clone()));
-            off += ty.size();
+    /// C takes `long` where this language passes `int`: sign-extend the
+    /// argument so the callee sees the full 64-bit register.
+    fn widen_extern_arg(&mut self, value: Value, ty: &Type, param: &Type) -> Value {
+        if *param != Type::Long || !matches!(ty, Type::int | Type::Char) {
+            return value;
         }
+        if let Value::Const(_) = value {
+            return value;
+        }
+        let reg = self.vreg_gen.fresh(false, RegWidth::W64);
+        self.scope_handler.instructions.push(IRInstruction::Cast {
+            reg,
+            src: value,
+            from: ty.clone(),
+            ty: Type::Long,
+        });
+        Value::Reg(reg)
+    }
+
+    /// Calls the `new` allocator for `size` bytes.
+    fn call_allocator(&mut self, size: Value) -> Value {
+        let reg = self.vreg_gen.fresh(false, RegWidth::W64);
+        self.scope_handler.instructions.push(IRInstruction::Call {
+            reg: Some(reg),
+            reg_hi: None,
+            func: alloc_fn().to_string(),
+            args: vec![size],
+            sret: None,
+        });
+        Value::Reg(reg)
+    }
+
+    /// `volatile_*` and `atomic_*` builtins. The analyzer has already folded
+    /// the trailing memory order to a literal.
+    fn lower_memory_builtin(&mut self, name: &str, mut args: Vec<Expr>) -> Option<(Value, Type)> {
+        let order = match name.starts_with("atomic_").then(|| args.pop()) {
+            Some(Some(Expr::IntLiteral(i))) => MemOrder::from_index(i as i64),
+            _ => None,
+        };
+        let mut args = args.into_iter();
 
-        map
+        let (ptr, ptr_ty) = self.first_pass_parse_expr(args.next().unwrap()).unwrap();
+        let addr = self.ensure_rvalue(ptr, &ptr_ty);
+        let Type::Pointer(ty) = ptr_ty else {
+            unreachable!("memory builtin on non-pointer")
+        };
+        let ty = *ty;
+        let values: Vec<Value> = args
+            .map(|arg| {
+                let (v, arg_ty) = self.first_pass_parse_expr(arg).unwrap();
+                let v = self.ensure_rvalue(v, &arg_ty);
+                self.widen_extern_arg(v, &arg_ty, &ty)
+            })
+            .collect();
+        let mut values = values.into_iter();
+        let mut value = || values.next().unwrap();
+
+        let reg = self.vreg_gen.fresh(false, type_to_reg_width(&ty));
+        let instr = match name {
+            "volatile_load" | "atomic_load" => IRInstruction::AtomicLoad {
+                reg,
+                addr,
+                ty: ty.clone(),
+                order,
+            },
+            "volatile_store" | "atomic_store" => IRInstruction::AtomicStore {
+                value: value(),
+                addr,
+                ty: ty.clone(),
+                order,
+            },
+            "atomic_swap" | "atomic_fetch_add" => IRInstruction::AtomicRmw {
+                reg,
+                op: if name == "atomic_swap" {
+                    RmwOp::Swap
+                } else {
+                    RmwOp::Add
+                },
+                addr,
+                value: value(),
+                ty: ty.clone(),
+                order: order.unwrap(),
+            },
+            _ => IRInstruction::AtomicCmpXchg {
+                reg,
+                addr,
+                expected: value(),
+                desired: value(),
+                ty: ty.clone(),
+                order: order.unwrap(),
+            },
+        };
+        let stores = matches!(instr, IRInstruction::AtomicStore { .. });
+        self.scope_handler.instructions.push(instr);
+        (!stores).then_some((Value::Reg(reg), ty))
+    }
+
+    /// `let x: T;` -- scalars get a vreg with no definition yet, which the
+    /// definite-assignment check follows; aggregates get their stack slot.
+    pub fn declare_uninit(&mut self, var_name: String, ty: Type) {
+        let value = if ty.fits_in_register() {
+            let vreg = self
+                .vreg_gen
+                .fresh(ty == Type::float, type_to_reg_width(&ty));
+            self.scope_handler.uninit.insert(vreg.id, var_name.clone());
+            self.scope_handler.var_regs.insert(vreg.id, var_name.clone());
+            Value::Reg(vreg)
+        } else {
+            Value::Local(self.new_local(self.field_layout(&ty)))
+        };
+        self.var_map.insert(var_name, (ty, value));
     }
 
     pub fn emit_into_local(&mut self, var_name: String, ty: Type, expr: Expr) {
         match expr {
-            Expr::StructInit { name, params } => {
+            Expr::StructInit {
+                name,
+                params,
+                default,
+            } => {
                 let def = self
                     .ir_program
                     .structs
                     .get(&name)
                     .expect("no known struct: '{name}'");
-
-                let layout = def.fields.clone();
-                let local = self.var_gen.fresh();
-
-                self.scope_handler.current_offset += def.size as i32;
+                let local = self.new_local((def.size, def.align));
 
                 self.var_map
                     .insert(var_name, (ty.clone(), Value::Local(local)));
-
-                for (field_name, field_expr) in params.iter() {
-                    let (field_off, field_ty) = &layout.get(field_name).unwrap();
-
-                    let (value, field_expr_ty) =
-                        self.first_pass_parse_expr(field_expr.clone()).unwrap();
-                    let value = self.ensure_rvalue(value, &field_expr_ty);
-
-                    self.scope_handler.instructions.push(IRInstruction::Store {
-                        value,
-                        addr: Value::Local(local),
-                        offset: *field_off,
-                        ty: field_ty.clone(),
-                    });
-                }
+                self.init_struct(&name, params, default, Value::Local(local));
             }
             other => {
                 let (v, expr_ty) = self.first_pass_parse_expr(other).unwrap();
+                let (v, expr_ty) = self.convert_implicitly(v, expr_ty, &ty);
 
                 if expr_ty.fits_in_register() {
                     // primitive or pointer: use vReg
                     let vreg = self
                         .vreg_gen
                         .fresh(expr_ty == Type::float, type_to_reg_width(&expr_ty));
+                    self.scope_handler.var_regs.insert(vreg.id, var_name.clone());
                     self.var_map
                         .insert(var_name, (ty.clone(), Value::Reg(vreg)));
                     let v = self.ensure_rvalue(v, &expr_ty);
@@ -165,33 +375,11 @@ impl IRGenerator {
                         from: v,
                     });
                 } else {
-                    // struct or array: use stack local, copy fields
-                    let local = self.var_gen.fresh();
+                    // struct or array: use stack local, copy the bytes
+                    let local = self.new_local(self.field_layout(&expr_ty));
                     self.var_map
                         .insert(var_name, (ty.clone(), Value::Local(local)));
-
-                    if let Type::Struct {
-                        name: struct_name, ..
-                    } = &expr_ty
-                    {
-                        let fields = self
-                            .ir_program
-                            .structs
-                            .get(struct_name)
-                            .expect("struct not found")
-                            .fields
-                            .clone();
-                        self.copy_struct_fields(v, Value::Local(local), &fields);
-                    } else {
-                        // Array or other aggregate: single store
-                        let v = self.ensure_rvalue(v, &expr_ty);
-                        self.scope_handler.instructions.push(IRInstruction::Store {
-                            value: v,
-                            addr: Value::Local(local),
-                            offset: 0,
-                            ty: expr_ty,
-                        });
-                    }
+                    self.copy_bytes(v, 0, Value::Local(local), 0, &expr_ty);
                 }
             }
         }
@@ -214,9 +402,93 @@ impl IRGenerator {
         }
     }
 
+    /// Where `base.field` lives, as an address and byte offset, loading
+    /// through every pointer on the way to the struct.
+    fn field_place(&mut self, base: Expr, field: &str) -> (Value, i32, Type) {
+        let (mut addr, mut offset, mut ty) = match base {
+            Expr::InstanceVar(inner, inner_field) => self.field_place(*inner, &inner_field),
+            other => {
+                let (value, ty) = self.first_pass_parse_expr(other).unwrap();
+                match ty {
+                    Type::Pointer(pointee) => {
+                        let value = self.ensure_rvalue(value, &Type::Pointer(pointee.clone()));
+                        (value, 0, *pointee)
+                    }
+                    ty => (value, 0, ty),
+                }
+            }
+        };
+
+        while let Type::Pointer(pointee) = ty {
+            let reg = self.vreg_gen.fresh(false, RegWidth::W64);
+            self.scope_handler.instructions.push(IRInstruction::Load {
+                reg,
+                addr,
+                offset,
+                ty: Type::Pointer(pointee.clone()),
+            });
+            (addr, offset, ty) = (Value::Reg(reg), 0, *pointee);
+        }
+
+        let Type::Struct { name, generics, .. } = &ty else {
+            unreachable!("field access on non-struct type {ty:?}")
+        };
+        let (field_offset, field_ty) =
+            self.ir_program.structs[&struct_def_name(name, generics)].fields[field].clone();
+        (addr, offset + field_offset, field_ty)
+    }
+
+    /// `addr + offset` as a single value, for aggregates used in place.
+    fn offset_address(&mut self, addr: Value, offset: i32) -> Value {
+        if offset == 0 {
+            return addr;
+        }
+        let reg = self.vreg_gen.fresh(false, RegWidth::W64);
+        self.scope_handler.instructions.push(IRInstruction::Gep {
+            dest: reg,
+            base: addr,
+            index: Value::Const(offset as i64),
+            scale: 1,
+        });
+        Value::Reg(reg)
+    }
+
+    fn address_of(&mut self, expr: Expr) -> (Value, Type) {
+        let is_variable = matches!(expr, Expr::Variable(..));
+        let (place, inner_ty) = self
+            .lower_place(expr)
+            .expect("cannot take address of non-place");
+        let reg = self.vreg_gen.fresh(false, RegWidth::W64);
+        let inst = match place {
+            // A variable kept in a register; it is moved to the stack once the
+            // function is lowered
+            Value::Reg(var) if is_variable => {
+                self.scope_handler
+                    .address_taken
+                    .insert(var.id, inner_ty.clone());
+                IRInstruction::AddressOf {
+                    dest: reg,
+                    src: place,
+                }
+            }
+            // Already an address, e.g. a field at offset 0 through a pointer
+            Value::Reg(_) => IRInstruction::Move {
+                dest: reg,
+                from: place,
+            },
+            _ => IRInstruction::AddressOf {
+                dest: reg,
+                src: place,
+            },
+        };
+        self.scope_handler.instructions.push(inst);
+        (Value::Reg(reg), Type::Pointer(Box::new(inner_ty)))
+    }
+
     pub fn lower_place(&mut self, expr: Expr) -> Option<(Value, Type)> {
         match expr {
             Expr::BoolLiteral(b) => Some((Value::Const(b as i64), Type::Bool)),
+            Expr::NullLiteral => Some((Value::Const(0), Type::null)),
             Expr::IntLiteral(i) => Some((Value::Const(i as i64), Type::int)),
             Expr::LongLiteral(i) => Some((Value::Const(i), Type::Long)),
             Expr::FloatLiteral(f) => Some((Value::ConstFloat(f), Type::float)),
@@ -241,15 +513,11 @@ impl IRGenerator {
                 index: _,
                 value: _,
             } => None,
-            Expr::InstanceVar(struct_name, _field) => {
-                let (ty, local) = self.var_map.get(&struct_name).unwrap();
-                Some((local.clone(), ty.clone()))
+            Expr::InstanceVar(base, field) => {
+                let (addr, offset, ty) = self.field_place(*base, &field);
+                Some((self.offset_address(addr, offset), ty))
             }
-            Expr::FieldAssign {
-                class_name: _,
-                field: _,
-                value: _,
-            } => None,
+            Expr::FieldAssign { .. } => None,
             _ => None, // not an lvalue
         }
     }
@@ -260,6 +528,7 @@ impl IRGenerator {
             Expr::LongLiteral(l) => Some((Value::Const(l), Type::Long)),
             Expr::FloatLiteral(f) => Some((Value::ConstFloat(f), Type::float)),
             Expr::BoolLiteral(b) => Some((Value::Const(b as i64), Type::Bool)),
+            Expr::NullLiteral => Some((Value::Const(0), Type::null)),
             Expr::StringLiteral(s) => {
                 let mut def = self.globals.get(&s);
                 if def.is_none() {
@@ -270,41 +539,57 @@ impl IRGenerator {
                 Some((Value::Global(def.id), def.ty.clone()))
             }
             Expr::CharLiteral(c) => Some((Value::Const(c as i64), Type::Char)),
-            Expr::StructInit { name, params } => {
+            Expr::StructInit {
+                name,
+                params,
+                default,
+            } => {
                 let def = self.ir_program.structs.get(&name).expect("unknown struct");
+                let loc = self.new_local((def.size, def.align));
 
-                let layout = def.fields.clone();
-
-                let loc = self.var_gen.fresh();
-
-                for (field_name, field_expr) in params.iter() {
-                    let (field_off, field_ty) = &layout.get(field_name).unwrap();
-
-                    let (value, _ty) = self.first_pass_parse_expr(field_expr.clone()).unwrap();
-
-                    self.scope_handler.instructions.push(IRInstruction::Store {
-                        value,
-                        addr: Value::Local(loc),
-                        offset: *field_off,
-                        ty: field_ty.clone(),
-                    });
-                }
-
+                self.init_struct(&name, params, default, Value::Local(loc));
                 Some((Value::Local(loc), self.type_struct(&name)))
             }
-            Expr::AddressOf(expression) => {
-                let (place, inner_ty) = self.lower_place(*expression).unwrap();
-                let reg = self.vreg_gen.fresh(false, RegWidth::W64);
-                self.scope_handler
-                    .instructions
-                    .push(IRInstruction::AddressOf {
-                        dest: reg,
-                        src: place,
-                    });
-                Some((Value::Reg(reg), Type::Pointer(Box::new(inner_ty))))
+            Expr::New(init) => {
+                let Expr::StructInit {
+                    name,
+                    params,
+                    default,
+                } = *init
+                else {
+                    unreachable!("new without a struct initializer")
+                };
+                let size = self.ir_program.structs[&name].size;
+
+                let ptr = self.call_allocator(Value::Const(size as i64));
+                self.init_struct(&name, params, default, ptr.clone());
+                Some((ptr, Type::Pointer(Box::new(self.type_struct(&name)))))
             }
+            Expr::NewArray { elem, count } => {
+                let (count, count_ty) = self.first_pass_parse_expr(*count).unwrap();
+                let count = self.ensure_rvalue(count, &count_ty);
+                let elem_size = self.field_layout(&elem).0 as i64;
+
+                let size = match count {
+                    Value::Const(n) => Value::Const(n * elem_size),
+                    count => {
+                        let count = self.widen_extern_arg(count, &count_ty, &Type::Long);
+                        let reg = self.vreg_gen.fresh(false, RegWidth::W64);
+                        self.scope_handler.instructions.push(IRInstruction::Mul {
+                            reg,
+                            left: count,
+                            right: Value::Const(elem_size),
+                        });
+                        Value::Reg(reg)
+                    }
+                };
+
+                Some((self.call_allocator(size), Type::Pointer(Box::new(elem))))
+            }
+            Expr::AddressOf(expression) => Some(self.address_of(*expression)),
             Expr::DerefAssign { target, value } => {
-                let (rhs_val, _) = self.lower_place(*value).unwrap();
+                let (rhs_val, rhs_ty) = self.first_pass_parse_expr(*value).unwrap();
+                let rhs_val = self.ensure_rvalue(rhs_val, &rhs_ty);
                 let (ptr_val, ptr_ty) = self.lower_place(*target).unwrap();
 
                 let pointee_ty = ptr_ty
@@ -320,37 +605,22 @@ impl IRGenerator {
                 });
                 None
             }
-            Expr::InstanceVar(struct_var_name, field_name) => {
-                let mut field_type = Type::Void;
-                let vreg = self.vreg_gen.fresh(false, RegWidth::W64);
-                if let Some((ty, id)) = self.var_map.get(&struct_var_name) {
-                    if let Type::Struct { name, generics, .. } = ty {
-                        let mangled_name = mangle_name(name, generics);
-                        let struct_def = self.ir_program.structs.get(&mangled_name).unwrap();
-                        let offset = struct_def.fields.get(&field_name).unwrap().0;
-                        field_type = struct_def.fields.get(&field_name).unwrap().1.clone();
-                        self.scope_handler.instructions.push(IRInstruction::Load {
-                            reg: vreg,
-                            addr: id.clone(),
-                            offset,
-                            ty: field_type.clone(),
-                        });
-                    }
-                } else if let Some(global_def) = self.globals.get(&struct_var_name)
-                    && let GlobalValue::Struct(expr) = &global_def.value
-                    && let Expr::StructInit { name, .. } = expr
-                {
-                    let struct_def = self.ir_program.structs.get(name).unwrap();
-                    let offset = struct_def.fields.get(&field_name).unwrap().0;
-                    field_type = struct_def.fields.get(&field_name).unwrap().1.clone();
-                    self.scope_handler.instructions.push(IRInstruction::Load {
-                        reg: vreg,
-                        addr: Value::Global(global_def.id),
-                        offset,
-                        ty: field_type.clone(),
-                    });
+            Expr::InstanceVar(base, field) => {
+                let (addr, offset, field_type) = self.field_place(*base, &field);
+                if !field_type.fits_in_register() {
+                    return Some((self.offset_address(addr, offset), field_type));
                 }
 
+                let vreg = self
+                    .vreg_gen
+                    .fresh(field_type == Type::float, type_to_reg_width(&field_type));
+                self.scope_handler.instructions.push(IRInstruction::Load {
+                    reg: vreg,
+                    addr,
+                    offset,
+                    ty: field_type.clone(),
+                });
+
                 Some((Value::Reg(vreg), field_type))
             }
             Expr::Variable(name, ty) => {
@@ -392,6 +662,7 @@ impl IRGenerator {
                     (var_info.1.clone(), var_info.0.clone())
                 };
                 let (rhs, rhs_ty) = self.first_pass_parse_expr(*value).unwrap();
+                let (rhs, rhs_ty) = self.convert_implicitly(rhs, rhs_ty, &var_ty);
 
                 if var_ty.fits_in_register() {
                     let rhs = self.ensure_rvalue(rhs, &rhs_ty);
@@ -412,18 +683,8 @@ impl IRGenerator {
                         }
                         _ => {}
                     }
-                } else if let Type::Struct {
-                    name: struct_name, ..
-                } = &var_ty
-                {
-                    let fields = self
-                        .ir_program
-                        .structs
-                        .get(struct_name)
-                        .expect("struct not found")
-                        .fields
-                        .clone();
-                    self.copy_struct_fields(rhs, lhs_value, &fields);
+                } else if let Type::Struct { .. } | Type::Array(..) = &var_ty {
+                    self.copy_bytes(rhs, 0, lhs_value, 0, &var_ty);
                 } else {
                     let rhs = self.ensure_rvalue(rhs, &rhs_ty);
                     self.scope_handler.instructions.push(IRInstruction::Store {
@@ -442,6 +703,18 @@ impl IRGenerator {
                 result_type,
             } => {
                 let (left_rvalue, left_type) = self.first_pass_parse_expr(*left).unwrap();
+                if let Type::Struct { .. } = left_type
+                    && matches!(op, BinaryOp::Equal | BinaryOp::NotEqual)
+                {
+                    let (right, _) = self.first_pass_parse_expr(*right).unwrap();
+                    let reg = self.compare_structs(
+                        left_rvalue,
+                        right,
+                        &left_type,
+                        matches!(op, BinaryOp::NotEqual),
+                    );
+                    return Some((Value::Reg(reg), Type::Bool));
+                }
                 let left = self.ensure_rvalue(left_rvalue, &left_type);
 
                 let (right_rvalue, right_type) = self.first_pass_parse_expr(*right).unwrap();
@@ -544,27 +817,21 @@ impl IRGenerator {
                     let reg = self
                         .vreg_gen
                         .fresh(result_type == Type::float, type_to_reg_width(&result_type));
+                    // -0.0 - x flips the sign of zero too, which 0.0 - x would not
+                    let zero = if result_type == Type::float {
+                        Value::ConstFloat(-0.0)
+                    } else {
+                        Value::Const(0)
+                    };
                     self.scope_handler.instructions.push(IRInstruction::Sub {
                         reg,
-                        left: Value::Const(0),
+                        left: zero,
                         right: v,
                     });
                     Some((Value::Reg(reg), result_type))
                 }
 
-                UnaryOp::AddressOf => {
-                    let (place, inner_ty) = self
-                        .lower_place(*expr)
-                        .expect("cannot take address of non-place");
-                    let reg = self.vreg_gen.fresh(false, RegWidth::W64);
-                    self.scope_handler
-                        .instructions
-                        .push(IRInstruction::AddressOf {
-                            dest: reg,
-                            src: place,
-                        });
-                    Some((Value::Reg(reg), Type::Pointer(Box::new(inner_ty))))
-                }
+                UnaryOp::AddressOf => Some(self.address_of(*expr)),
 
                 UnaryOp::Dereference => {
                     let (ptr, ptr_ty) = self.first_pass_parse_expr(*expr).unwrap();
@@ -587,104 +854,188 @@ impl IRGenerator {
                     Some((Value::Reg(reg), pointee))
                 }
             },
+            Expr::Call { name, args, .. }
+                if matches!(
+                    name.as_str(),
+                    "volatile_load"
+                        | "volatile_store"
+                        | "atomic_load"
+                        | "atomic_store"
+                        | "atomic_swap"
+                        | "atomic_fetch_add"
+                        | "atomic_compare_exchange"
+                ) =>
+            {
+                self.lower_memory_builtin(&name, args)
+            }
             Expr::Call {
                 name,
                 args,
                 return_type,
             } => {
-                let reg = if return_type == Type::Void {
-                    None
-                } else {
-                    Some(
-                        self.vreg_gen
-                            .fresh(return_type == Type::float, type_to_reg_width(&return_type)),
-                    )
-                };
+                let ret = self.struct_return(&return_type);
 
-                let mut value_args: Vec<Value> = vec![];
+                // Struct results land in a fresh local owned by the caller
+                let ret_local = ret
+                    .as_ref()
+                    .map(|_| self.new_local(self.field_layout(&return_type)));
 
-                if "sizeof" == name
-                    && let Expr::Variable(name, _) = &args[0]
-                    && self.ir_program.structs.contains_key(name)
-                {
-                    value_args.push(Value::Const(
-                        self.ir_program.structs.get(name).unwrap().size as i64,
-                    ));
-                } else {
-                    value_args = args
+                let mut value_args = Vec::with_capacity(args.len());
+                let extern_params = self
+                    .ir_program
+                    .extern_fns
+                    .get(&name)
+                    .map(|ext| ext.params.clone())
+                    .unwrap_or_default();
+                for (i, arg) in args.into_iter().enumerate() {
+                    let (v, ty) = self.first_pass_parse_expr(arg).unwrap();
+                    let v = self.materialize_call_arg(v, &ty);
+                    let v = match extern_params.get(i) {
+                        Some(param) => self.widen_extern_arg(v, &ty, param),
+                        None => v,
+                    };
+                    value_args.push(v);
+                }
+
+                // Taken after the arguments, which may be calls themselves
+                let sret = match (&ret, ret_local) {
+                    (Some(StructReturn::Memory), Some(local)) => {
+                        let sret = self.vreg_gen.fresh(false, RegWidth::W64);
+                        self.scope_handler
+                            .instructions
+                            .push(IRInstruction::AddressOf {
+                                dest: sret,
+                                src: Value::Local(local),
+                            });
+                        Some(Value::Reg(sret))
+                    }
+                    _ => None,
+                };
+
+                let ret_regs: Vec<(VReg, i32, Type)> = match &ret {
+                    Some(StructReturn::Regs(fields)) => fields
                         .iter()
-                        .map(|arg| {
-                            let (v, ty) = self.first_pass_parse_expr(arg.clone()).unwrap();
-                            self.materialize_call_arg(v, &ty)
+                        .map(|(offset, ty)| {
+                            let reg = self.vreg_gen.fresh(false, type_to_reg_width(ty));
+                            (reg, *offset, ty.clone())
                         })
-                        .collect();
-                }
+                        .collect(),
+                    Some(StructReturn::Memory) => Vec::new(),
+                    None if return_type == Type::Void => Vec::new(),
+                    None => vec![(
+                        self.vreg_gen
+                            .fresh(return_type == Type::float, type_to_reg_width(&return_type)),
+                        0,
+                        return_type.clone(),
+                    )],
+                };
 
                 let instr = IRInstruction::Call {
-                    reg,
+                    reg: ret_regs.first().map(|(reg, _, _)| *reg),
+                    reg_hi: ret_regs.get(1).map(|(reg, _, _)| *reg),
                     func: name,
                     args: value_args,
+                    sret,
                 };
 
                 self.scope_handler.instructions.push(instr);
 
-                if let Some(reg) = reg {
-                    return Some((Value::Reg(reg), return_type));
+                if let Some(local) = ret_local {
+                    if let Some(StructReturn::Regs(_)) = ret {
+                        for (reg, offset, ty) in ret_regs {
+                            self.scope_handler.instructions.push(IRInstruction::Store {
+                                value: Value::Reg(reg),
+                                addr: Value::Local(local),
+                                offset,
+                                ty,
+                            });
+                        }
+                    }
+                    return Some((Value::Local(local), return_type));
                 }
-                None
+
+                ret_regs
+                    .first()
+                    .map(|(reg, _, _)| (Value::Reg(*reg), return_type))
             }
             Expr::Cast { expr, target_type } => {
                 let (from_val, from_ty) = self.first_pass_parse_expr(*expr).unwrap();
+                let (from_val, from_ty) = self.convert_implicitly(from_val, from_ty, &target_type);
                 let from_val = self.ensure_rvalue(from_val, &from_ty);
-                let result_reg = self
-                    .vreg_gen
-                    .fresh(target_type == Type::float, type_to_reg_width(&target_type));
-                self.scope_handler.instructions.push(IRInstruction::Cast {
-                    reg: result_reg,
-                    src: from_val,
-                    ty: target_type.clone(),
-                });
-                Some((Value::Reg(result_reg), target_type))
+                Some((self.lower_cast(from_val, &from_ty, &target_type), target_type))
             }
             Expr::Array(exprs, ty) => {
-                let local_id = self.var_gen.fresh();
-                let size_per = ty.size();
+                let (size_per, align) = self.field_layout(&ty);
+                let local_id = self.new_local((size_per * exprs.len(), align));
                 for (i, index_expression) in exprs.iter().enumerate() {
                     let (index_val, _) = self
                         .first_pass_parse_expr(index_expression.clone())
                         .unwrap();
                     let index_val = self.ensure_rvalue(index_val, &ty);
-                    self.scope_handler.instructions.push(IRInstruction::Store {
-                        value: index_val,
-                        addr: Value::Local(local_id),
-                        offset: (i * size_per) as i32,
-                        ty: ty.clone(),
-                    });
+                    self.store_field(
+                        index_val,
+                        Value::Local(local_id),
+                        (i * size_per) as i32,
+                        &ty,
+                    );
                 }
                 Some((
                     Value::Local(local_id),
                     Type::Array(Box::new(ty), Some(exprs.len())),
                 ))
             }
+            Expr::ArrayRepeat { value, count } => {
+                let Expr::IntLiteral(count) = *count else {
+                    unreachable!("repeat count was not folded")
+                };
+                let count = count as usize;
+                let (value, ty) = self.first_pass_parse_expr(*value).unwrap();
+                let (size_per, align) = self.field_layout(&ty);
+                let local_id = self.new_local((size_per * count, align));
+
+                match self.ensure_rvalue(value, &ty) {
+                    Value::Const(0) => self.zero_fill(Value::Local(local_id), 0, size_per * count),
+                    Value::ConstFloat(f) if f.to_bits() == 0 => {
+                        self.zero_fill(Value::Local(local_id), 0, size_per * count)
+                    }
+                    value => {
+                        for i in 0..count {
+                            self.store_field(
+                                value.clone(),
+                                Value::Local(local_id),
+                                (i * size_per) as i32,
+                                &ty,
+                            );
+                        }
+                    }
+                }
+                Some((
+                    Value::Local(local_id),
+                    Type::Array(Box::new(ty), Some(count)),
+                ))
+            }
             Expr::ArrayAccess { array, index } => {
                 let (addr_val, array_ty) = self.first_pass_parse_expr(*array).unwrap();
-                let elem_ty = if let Type::Array(ty, _) = array_ty {
-                    *ty
-                } else {
-                    array_ty
-                };
+                let elem_ty = array_ty.element_type().cloned().unwrap_or(array_ty);
 
                 let (index_val, index_ty) = self.first_pass_parse_expr(*index).unwrap();
                 let index_val = self.ensure_rvalue(index_val, &index_ty);
+                // Addresses are computed in 64 bits, so the index is sign-extended first
+                let index_val = self.lower_cast(index_val, &index_ty, &Type::Long);
 
                 let addr_reg = self.vreg_gen.fresh(false, RegWidth::W64);
                 self.scope_handler.instructions.push(IRInstruction::Gep {
                     dest: addr_reg,
                     base: addr_val,
                     index: index_val,
-                    scale: elem_ty.size(),
+                    scale: self.field_layout(&elem_ty).0,
                 });
 
+                // Aggregate elements are used in place
+                if !elem_ty.fits_in_register() {
+                    return Some((Value::Reg(addr_reg), elem_ty));
+                }
+
                 let result_reg = self
                     .vreg_gen
                     .fresh(elem_ty == Type::float, type_to_reg_width(&elem_ty));
@@ -702,14 +1053,11 @@ impl IRGenerator {
                 value,
             } => {
                 let (base, base_ty) = self.first_pass_parse_expr(*array).unwrap();
-                let elem_ty = if let Type::Array(ty, _) = base_ty {
-                    *ty
-                } else {
-                    base_ty
-                };
+                let elem_ty = base_ty.element_type().cloned().unwrap_or(base_ty);
 
                 let (idx, idx_ty) = self.first_pass_parse_expr(*index).unwrap();
                 let idx = self.ensure_rvalue(idx, &idx_ty);
+                let idx = self.lower_cast(idx, &idx_ty, &Type::Long);
 
                 let base_ptr = match base {
                     Value::Local(_) | Value::Global(_) => {
@@ -730,73 +1078,236 @@ impl IRGenerator {
                     dest: addr_reg,
                     base: base_ptr,
                     index: idx,
-                    scale: elem_ty.size(),
+                    scale: self.field_layout(&elem_ty).0,
                 });
 
                 let (rhs, rhs_ty) = self.first_pass_parse_expr(*value).unwrap();
                 let rhs = self.ensure_rvalue(rhs, &rhs_ty);
+                self.store_field(rhs, Value::Reg(addr_reg), 0, &elem_ty);
 
-                self.scope_handler.instructions.push(IRInstruction::Store {
-                    value: rhs,
-                    addr: Value::Reg(addr_reg),
-                    offset: 0,
-                    ty: elem_ty,
-                });
+                None
+            }
+            Expr::FieldAssign { base, field, value } => {
+                let (addr, offset, field_ty) = self.field_place(*base, &field);
 
+                let (rhs, rhs_ty) = self.first_pass_parse_expr(*value).unwrap();
+                let rhs = self.ensure_rvalue(rhs, &rhs_ty);
+
+                self.store_field(rhs, addr, offset, &field_ty);
                 None
             }
-            Expr::FieldAssign {
-                class_name,
-                field,
-                value,
-            } => {
-                let var_map = self.var_map.clone();
+            Expr::PreIncrement { name } => Some(self.step_variable(&name, BinaryOp::Add, false)),
+            Expr::PostIncrement { name } => Some(self.step_variable(&name, BinaryOp::Add, true)),
+            Expr::PreDecrement { name } => Some(self.step_variable(&name, BinaryOp::Sub, false)),
+            Expr::PostDecrement { name } => Some(self.step_variable(&name, BinaryOp::Sub, true)),
+            Expr::SizeOf(_) => None,
+            Expr::Tuple(_) => unreachable!("tuples are lowered to struct initializers"),
+        }
+    }
+
+    /// Adds or subtracts one from the variable `name`, giving its value from
+    /// before the update for `x++`/`x--` and from after it for `++x`/`--x`.
+    fn step_variable(&mut self, name: &str, op: BinaryOp, postfix: bool) -> (Value, Type) {
+        let (ty, var) = self
+            .var_map
+            .get(name)
+            .map(|(ty, var)| (ty.clone(), var.clone()))
+            .expect("variable not found in increment");
+        let width = type_to_reg_width(&ty);
+
+        let (current, _) = self
+            .first_pass_parse_expr(Expr::Variable(name.to_string(), ty.clone()))
+            .unwrap();
+        let old = self.vreg_gen.fresh(false, width);
+        self.scope_handler.instructions.push(IRInstruction::Move {
+            dest: old,
+            from: current,
+        });
+
+        let new = self.vreg_gen.fresh(false, width);
+        let (left, right) = (Value::Reg(old), Value::Const(1));
+        self.scope_handler.instructions.push(match op {
+            BinaryOp::Add => IRInstruction::Add {
+                reg: new,
+                left,
+                right,
+            },
+            _ => IRInstruction::Sub {
+                reg: new,
+                left,
+                right,
+            },
+        });
 
-                let (local_id, offset, field_ty) = {
-                    let (struct_type, local_id) =
-                        var_map.get(&class_name).expect("struct variable not found");
+        self.scope_handler.instructions.push(match var {
+            Value::Reg(reg) => IRInstruction::Move {
+                dest: reg,
+                from: Value::Reg(new),
+            },
+            addr => IRInstruction::Store {
+                value: Value::Reg(new),
+                addr,
+                offset: 0,
+                ty: ty.clone(),
+            },
+        });
 
-                    if let Type::Struct { name, .. } = struct_type {
-                        let struc = self.ir_program.structs.get(name).expect("struct not found");
+        (Value::Reg(if postfix { old } else { new }), ty)
+    }
 
-                        let offset = struc
-                            .fields
-                            .get(&field)
-                            .expect("field not found in struct")
-                            .0;
-                        let field_ty = struc.fields.get(&field).unwrap().1.clone();
+    /// Return convention for `ty`, or `None` when it isn't a struct.
+    pub fn struct_return(&self, ty: &Type) -> Option<StructReturn> {
+        let Type::Struct { name, generics, .. } = ty else {
+            return None;
+        };
+        let def = self.ir_program.structs.get(&struct_def_name(name, generics))?;
 
-                        Some((local_id, offset, field_ty))
-                    } else {
-                        None
-                    }
-                }
-                .unwrap();
+        let mut fields: Vec<(i32, Type)> = def.fields.values().cloned().collect();
+        fields.sort_by_key(|(offset, _)| *offset);
 
-                let (rhs, rhs_ty) = self.first_pass_parse_expr(*value).unwrap();
-                let rhs = self.ensure_rvalue(rhs, &rhs_ty);
+        let in_regs = !def.is_union
+            && fields.len() <= 2
+            && fields
+                .iter()
+                .all(|(_, ty)| ty.fits_in_register() && *ty != Type::float);
 
-                self.scope_handler.instructions.push(IRInstruction::Store {
-                    value: rhs,
-                    addr: local_id.clone(),
+        Some(if in_regs {
+            StructReturn::Regs(fields)
+        } else {
+            StructReturn::Memory
+        })
+    }
+
+    /// Splits a returned value into the registers it is returned in. Structs
+    /// returned through memory are copied out via the hidden return pointer.
+    pub fn return_value(&mut self, v: Value, ty: &Type) -> (Option<Value>, Option<Value>) {
+        match self.struct_return(ty) {
+            Some(StructReturn::Regs(fields)) => {
+                let mut regs = fields.into_iter().map(|(offset, field_ty)| {
+                    let reg = self
+                        .vreg_gen
+                        .fresh(false, type_to_reg_width(&field_ty));
+                    self.scope_handler.instructions.push(IRInstruction::Load {
+                        reg,
+                        addr: v.clone(),
+                        offset,
+                        ty: field_ty,
+                    });
+                    Value::Reg(reg)
+                });
+                (regs.next(), regs.next())
+            }
+            Some(StructReturn::Memory) => {
+                let sret = self
+                    .scope_handler
+                    .sret
+                    .expect("struct returned through memory without a return pointer");
+
+                self.copy_bytes(v, 0, Value::Reg(sret), 0, ty);
+                (Some(Value::Reg(sret)), None)
+            }
+            None => (Some(v), None),
+        }
+    }
+
+    /// Binds each name in `let (a, b, ..) = value;` to the matching tuple element.
+    pub fn lower_destructure(&mut self, names: &[String], value: Expr) {
+        let (tuple, ty) = self.first_pass_parse_expr(value).unwrap();
+        let Type::Struct { name, .. } = &ty else {
+            panic!("cannot destructure non-tuple type {ty:?}");
+        };
+        let fields = self
+            .ir_program
+            .structs
+            .get(name)
+            .expect("tuple struct not found")
+            .fields
+            .clone();
+
+        for (i, var_name) in names.iter().enumerate() {
+            if var_name == "_" {
+                continue;
+            }
+
+            let (offset, field_ty) = fields[&i.to_string()].clone();
+
+            if let Type::Struct { .. } = &field_ty {
+                let local = self.new_local(self.field_layout(&field_ty));
+                self.copy_bytes(tuple.clone(), offset, Value::Local(local), 0, &field_ty);
+                self.var_map
+                    .insert(var_name.clone(), (field_ty, Value::Local(local)));
+            } else {
+                let reg = self
+                    .vreg_gen
+                    .fresh(field_ty == Type::float, type_to_reg_width(&field_ty));
+                self.scope_handler.instructions.push(IRInstruction::Load {
+                    reg,
+                    addr: tuple.clone(),
                     offset,
-                    ty: field_ty,
+                    ty: field_ty.clone(),
                 });
-                None
+                self.var_map
+                    .insert(var_name.clone(), (field_ty, Value::Reg(reg)));
             }
-            Expr::CompoundAssign {
-                name: _,
-                op: _,
-                value: _,
-            } => None,
-            Expr::PreIncrement { name: _ } => None,
-            Expr::PostIncrement { name: _ } => None,
-            Expr::PreDecrement { name: _ } => None,
-            Expr::PostDecrement { name: _ } => None,
-            Expr::SizeOf(_) => None,
         }
     }
 
+    /// An array used where `target` is a pointer becomes the address of its
+    /// first element.
+    fn convert_implicitly(&mut self, v: Value, ty: Type, target: &Type) -> (Value, Type) {
+        match (&ty, target) {
+            (Type::Array(..), Type::Pointer(_)) => {
+                (self.materialize_call_arg(v, &ty), target.clone())
+            }
+            (Type::null, Type::Pointer(_)) => (v, target.clone()),
+            _ => (v, ty),
+        }
+    }
+
+    /// Lowers `v as to`. Constants are folded, conversions that keep the bits
+    /// (same width integers, pointers, `null`) become a move and `as bool`
+    /// compares with zero; the rest is a `Cast` the backend extends, truncates
+    /// or converts.
+    fn lower_cast(&mut self, v: Value, from: &Type, to: &Type) -> Value {
+        let truncate = |n: i64| match to {
+            Type::int => n as i32 as i64,
+            Type::Char => n as u8 as i64,
+            Type::Bool => (n != 0) as i64,
+            _ => n,
+        };
+        match (&v, to) {
+            (Value::Const(n), Type::float) => return Value::ConstFloat(*n as f64),
+            (Value::Const(n), _) => return Value::Const(truncate(*n)),
+            (Value::ConstFloat(f), _) if *to != Type::float => return Value::Const(truncate(*f as i64)),
+            _ => {}
+        }
+        let width = type_to_reg_width(to);
+        let reg = self.vreg_gen.fresh(*to == Type::float, width);
+        let keeps_bits = from == to
+            || (*from != Type::float
+                && *to != Type::float
+                && *to != Type::Bool
+                && type_to_reg_width(from) == width);
+        let instr = if keeps_bits {
+            IRInstruction::Move { dest: reg, from: v }
+        } else if *to == Type::Bool {
+            IRInstruction::Ne {
+                reg,
+                left: v,
+                right: Value::Const(0),
+            }
+        } else {
+            IRInstruction::Cast {
+                reg,
+                src: v,
+                from: from.clone(),
+                ty: to.clone(),
+            }
+        };
+        self.scope_handler.instructions.push(instr);
+        Value::Reg(reg)
+    }
+
     fn materialize_call_arg(&mut self, v: Value, ty: &Type) -> Value {
         if ty.fits_in_register() {
             self.ensure_rvalue(v, ty)
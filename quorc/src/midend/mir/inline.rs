@@ -0,0 +1,404 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::midend::mir::{block::*, cfg::IRGenerator};
+
+/// Leaf functions with at most this many instructions are inlined at -O2.
+const INLINE_THRESHOLD: usize = 16;
+
+/// Fresh names for one inlined copy of a callee.
+#[derive(Default)]
+struct Renamer {
+    vregs: HashMap<usize, VReg>,
+    locals: HashMap<usize, usize>,
+    blocks: HashMap<BlockId, BlockId>,
+}
+
+impl Renamer {
+    fn reg(&mut self, generator: &mut IRGenerator, reg: VReg) -> VReg {
+        *self.vregs.entry(reg.id).or_insert_with(|| VReg {
+            id: generator.vreg_gen.fresh(reg.is_fpr(), reg.width).id,
+            ..reg
+        })
+    }
+
+    fn value(&mut self, generator: &mut IRGenerator, value: &Value) -> Value {
+        match value {
+            Value::Reg(reg) => Value::Reg(self.reg(generator, *reg)),
+            Value::Local(id) => Value::Local(
+                *self
+                    .locals
+                    .entry(*id)
+                    .or_insert_with(|| generator.var_gen.fresh()),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    fn block(&mut self, generator: &mut IRGenerator, block: BlockId) -> BlockId {
+        *self
+            .blocks
+            .entry(block)
+            .or_insert_with(|| generator.block_gen.fresh())
+    }
+
+    fn inst(&mut self, generator: &mut IRGenerator, inst: &IRInstruction) -> IRInstruction {
+        use IRInstruction::*;
+
+        macro_rules! binary {
+            ($variant:ident, $reg:expr, $left:expr, $right:expr) => {
+                $variant {
+                    reg: self.reg(generator, *$reg),
+                    left: self.value(generator, $left),
+                    right: self.value(generator, $right),
+                }
+            };
+        }
+
+        match inst {
+            Add { reg, left, right } => binary!(Add, reg, left, right),
+            Sub { reg, left, right } => binary!(Sub, reg, left, right),
+            Mul { reg, left, right } => binary!(Mul, reg, left, right),
+            Div { reg, left, right } => binary!(Div, reg, left, right),
+            Mod { reg, left, right } => binary!(Mod, reg, left, right),
+            Eq { reg, left, right } => binary!(Eq, reg, left, right),
+            Ne { reg, left, right } => binary!(Ne, reg, left, right),
+            Lt { reg, left, right } => binary!(Lt, reg, left, right),
+            Le { reg, left, right } => binary!(Le, reg, left, right),
+            Ge { reg, left, right } => binary!(Ge, reg, left, right),
+            Gt { reg, left, right } => binary!(Gt, reg, left, right),
+            Cast { reg, src, from, ty } => Cast {
+                reg: self.reg(generator, *reg),
+                src: self.value(generator, src),
+                from: from.clone(),
+                ty: ty.clone(),
+            },
+            Load {
+                reg,
+                addr,
+                offset,
+                ty,
+            } => Load {
+                reg: self.reg(generator, *reg),
+                addr: self.value(generator, addr),
+                offset: *offset,
+                ty: ty.clone(),
+            },
+            Store {
+                value,
+                addr,
+                offset,
+                ty,
+            } => Store {
+                value: self.value(generator, value),
+                addr: self.value(generator, addr),
+                offset: *offset,
+                ty: ty.clone(),
+            },
+            Gep {
+                dest,
+                base,
+                index,
+                scale,
+            } => Gep {
+                dest: self.reg(generator, *dest),
+                base: self.value(generator, base),
+                index: self.value(generator, index),
+                scale: *scale,
+            },
+            Call {
+                reg,
+                reg_hi,
+                func,
+                args,
+                sret,
+            } => Call {
+                reg: reg.map(|reg| self.reg(generator, reg)),
+                reg_hi: reg_hi.map(|reg| self.reg(generator, reg)),
+                func: func.clone(),
+                args: args.iter().map(|arg| self.value(generator, arg)).collect(),
+                sret: sret.as_ref().map(|sret| self.value(generator, sret)),
+            },
+            Move { dest, from } => Move {
+                dest: self.reg(generator, *dest),
+                from: self.value(generator, from),
+            },
+            AddressOf { dest, src } => AddressOf {
+                dest: self.reg(generator, *dest),
+                src: self.value(generator, src),
+            },
+            Memcpy {
+                dst,
+                src,
+                size,
+                align,
+            } => Memcpy {
+                dst: self.value(generator, dst),
+                src: self.value(generator, src),
+                size: *size,
+                align: *align,
+            },
+            InlineAsm {
+                template,
+                operands,
+                clobbers,
+            } => InlineAsm {
+                template: template.clone(),
+                operands: operands
+                    .iter()
+                    .map(|op| IRAsmOperand {
+                        input: op.input.as_ref().map(|v| self.value(generator, v)),
+                        output: op.output.map(|reg| self.reg(generator, reg)),
+                        ..op.clone()
+                    })
+                    .collect(),
+                clobbers: clobbers.clone(),
+            },
+            AtomicLoad {
+                reg,
+                addr,
+                ty,
+                order,
+            } => AtomicLoad {
+                reg: self.reg(generator, *reg),
+                addr: self.value(generator, addr),
+                ty: ty.clone(),
+                order: *order,
+            },
+            AtomicStore {
+                value,
+                addr,
+                ty,
+                order,
+            } => AtomicStore {
+                value: self.value(generator, value),
+                addr: self.value(generator, addr),
+                ty: ty.clone(),
+                order: *order,
+            },
+            AtomicRmw {
+                reg,
+                op,
+                addr,
+                value,
+                ty,
+                order,
+            } => AtomicRmw {
+                reg: self.reg(generator, *reg),
+                op: *op,
+                addr: self.value(generator, addr),
+                value: self.value(generator, value),
+                ty: ty.clone(),
+                order: *order,
+            },
+            AtomicCmpXchg {
+                reg,
+                addr,
+                expected,
+                desired,
+                ty,
+                order,
+            } => AtomicCmpXchg {
+                reg: self.reg(generator, *reg),
+                addr: self.value(generator, addr),
+                expected: self.value(generator, expected),
+                desired: self.value(generator, desired),
+                ty: ty.clone(),
+                order: *order,
+            },
+            Declaration(decl) => Declaration(decl.clone()),
+        }
+    }
+}
+
+fn instruction_count(func: &IRFunction) -> usize {
+    func.blocks.iter().map(|b| b.instructions.len()).sum()
+}
+
+fn calls(func: &IRFunction) -> impl Iterator<Item = &str> {
+    func.blocks
+        .iter()
+        .flat_map(|b| &b.instructions)
+        .filter_map(|inst| match inst {
+            IRInstruction::Call { func, .. } => Some(func.as_str()),
+            _ => None,
+        })
+}
+
+/// Whether calls to `func` can be replaced by its body: `@inline` functions
+/// always, small leaf functions when `aggressive`.
+fn inlinable(func: &IRFunction, aggressive: bool) -> bool {
+    let mut marked = false;
+    for attribute in &func.attributes {
+        match attribute {
+            AtDecl::Inline => marked = true,
+            AtDecl::Variadic | AtDecl::NoFrame | AtDecl::TrustRet => return false,
+            _ => {}
+        }
+    }
+
+    // Raw inline assembly may depend on the callee's own frame and registers;
+    // `asm(...)` statements only touch their operands and clobbers
+    let has_asm = func
+        .blocks
+        .iter()
+        .flat_map(|b| &b.instructions)
+        .any(|inst| matches!(inst, IRInstruction::Declaration(_)));
+    if has_asm || func.name == "main" || calls(func).any(|callee| callee == func.name) {
+        return false;
+    }
+
+    marked
+        || (aggressive
+            && calls(func).next().is_none()
+            && instruction_count(func) <= INLINE_THRESHOLD)
+}
+
+impl IRGenerator {
+    /// Splices the bodies of inlinable callees into their callers. Callees are
+    /// inlined as they were before this pass, so one level deep per call site.
+    pub fn inline_calls(&mut self, aggressive: bool) {
+        let mut functions = std::mem::take(&mut self.ir_program.functions);
+
+        let callees: HashMap<String, IRFunction> = functions
+            .values()
+            .filter(|func| inlinable(func, aggressive))
+            .map(|func| (func.name.clone(), func.clone()))
+            .collect();
+        if callees.is_empty() {
+            self.ir_program.functions = functions;
+            return;
+        }
+
+        for caller in functions.values_mut() {
+            let mut i = 0;
+            while i < caller.blocks.len() {
+                let site = caller.blocks[i].instructions.iter().position(|inst| {
+                    matches!(inst, IRInstruction::Call { func, .. }
+                        if func != &caller.name && callees.contains_key(func))
+                });
+                // Skip over the spliced body so it is not inlined into again
+                if let Some(site) = site {
+                    i += self.inline_call_site(caller, i, site, &callees);
+                }
+                i += 1;
+            }
+        }
+
+        // `@inline` functions nobody calls any more need no body of their own,
+        // unless C can still call them through an `@export` symbol
+        let called: HashSet<String> = functions
+            .values()
+            .flat_map(|func| calls(func).map(str::to_string).collect::<Vec<_>>())
+            .collect();
+        functions.retain(|name, func| {
+            called.contains(name)
+                || func
+                    .attributes
+                    .iter()
+                    .any(|a| matches!(a, AtDecl::Export { .. }))
+                || !func.attributes.iter().any(|a| matches!(a, AtDecl::Inline))
+        });
+
+        self.ir_program.functions = functions;
+    }
+
+    /// Replaces the call at `caller.blocks[block].instructions[site]` with a
+    /// renamed copy of the callee. The rest of the block moves to a new
+    /// continuation block that the callee's returns jump to. Returns the number
+    /// of callee blocks spliced in before the continuation.
+    fn inline_call_site(
+        &mut self,
+        caller: &mut IRFunction,
+        block: usize,
+        site: usize,
+        callees: &HashMap<String, IRFunction>,
+    ) -> usize {
+        let mut rest = caller.blocks[block].instructions.split_off(site);
+        let IRInstruction::Call {
+            reg,
+            reg_hi,
+            func,
+            args,
+            sret,
+        } = rest.remove(0)
+        else {
+            unreachable!()
+        };
+        let callee = &callees[&func];
+
+        let continuation = IRBlock {
+            id: self.block_gen.fresh(),
+            instructions: rest,
+            terminator: std::mem::replace(
+                &mut caller.blocks[block].terminator,
+                Terminator::TemporaryNone,
+            ),
+        };
+
+        let mut renamer = Renamer::default();
+        let hidden = callee.sret.zip(sret);
+        for (param, arg) in callee.params.iter().copied().zip(args).chain(hidden) {
+            let dest = renamer.reg(self, param);
+            caller.blocks[block]
+                .instructions
+                .push(IRInstruction::Move { dest, from: arg });
+        }
+        caller.blocks[block].terminator = Terminator::Jump {
+            block: renamer.block(self, callee.entry),
+        };
+
+        let mut inlined = Vec::new();
+        for callee_block in &callee.blocks {
+            let id = renamer.block(self, callee_block.id);
+            let mut instructions: Vec<IRInstruction> = callee_block
+                .instructions
+                .iter()
+                .map(|inst| renamer.inst(self, inst))
+                .collect();
+
+            let terminator = match &callee_block.terminator {
+                Terminator::Return { value, value_hi } => {
+                    for (dest, value) in [(reg, value), (reg_hi, value_hi)] {
+                        if let (Some(dest), Some(value)) = (dest, value) {
+                            let from = renamer.value(self, value);
+                            instructions.push(IRInstruction::Move { dest, from });
+                        }
+                    }
+                    Terminator::Jump {
+                        block: continuation.id,
+                    }
+                }
+                Terminator::Jump { block } => Terminator::Jump {
+                    block: renamer.block(self, *block),
+                },
+                Terminator::Branch {
+                    condition,
+                    if_true,
+                    if_false,
+                } => Terminator::Branch {
+                    condition: renamer.value(self, condition),
+                    if_true: renamer.block(self, *if_true),
+                    if_false: renamer.block(self, *if_false),
+                },
+                Terminator::TemporaryNone => Terminator::TemporaryNone,
+            };
+
+            inlined.push(IRBlock {
+                id,
+                instructions,
+                terminator,
+            });
+        }
+
+        for (old, new) in &renamer.locals {
+            let layout = callee.local_layouts.get(old).copied().unwrap_or((8, 8));
+            caller.local_layouts.insert(*new, layout);
+        }
+        caller.offset += callee.offset;
+
+        let spliced = inlined.len();
+        inlined.push(continuation);
+        caller.blocks.splice(block + 1..block + 1, inlined);
+        spliced
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::VecDeque;
+
 use crate::{
-    frontend::ast::{Expr, Stmt},
+    frontend::ast::{AsmDir, AsmOperand, Expr, Stmt, Type},
     midend::mir::{block::*, cfg::*},
+    target::free_fn,
 };
 
 impl IRGenerator {
@@ -12,7 +15,7 @@ impl IRGenerator {
         self.blocks[block.0].terminator = terminator;
     }
 
-    pub fn lower_while(&mut self, cond: Expr, body: &[Stmt]) {
+    pub fn lower_while(&mut self, cond: Expr, body: &[Stmt], label: Option<String>) {
         let cond_block = self.new_block();
         let body_block = self.new_block();
         let after_block = self.new_block();
@@ -24,6 +27,40 @@ impl IRGenerator {
 
         self.set_current(cond_block);
 
+        // `loop { }` never tests its condition
+        if let Expr::BoolLiteral(true) = cond {
+            self.set_terminator(cond_block, Terminator::Jump { block: body_block });
+        } else {
+            let (cond_value, cond_ty) = self.first_pass_parse_expr(cond).unwrap();
+            let cond_value = self.ensure_rvalue(cond_value, &cond_ty);
+
+            self.set_terminator(
+                cond_block,
+                Terminator::Branch {
+                    condition: cond_value,
+                    if_true: body_block,
+                    if_false: after_block,
+                },
+            );
+        }
+
+        self.lower_loop_body(body, label, after_block, cond_block, body_block);
+        self.set_current(after_block);
+    }
+
+    pub fn lower_do_while(&mut self, body: &[Stmt], cond: Expr, label: Option<String>) {
+        let body_block = self.new_block();
+        let cond_block = self.new_block();
+        let after_block = self.new_block();
+
+        self.set_terminator(
+            self.scope_handler.current,
+            Terminator::Jump { block: body_block },
+        );
+
+        self.lower_loop_body(body, label, after_block, cond_block, body_block);
+
+        self.set_current(cond_block);
         let (cond_value, cond_ty) = self.first_pass_parse_expr(cond).unwrap();
         let cond_value = self.ensure_rvalue(cond_value, &cond_ty);
 
@@ -36,8 +73,74 @@ impl IRGenerator {
             },
         );
 
-        self.scope_handler.break_stack.push_front(after_block);
-        self.scope_handler.continue_stack.push_front(cond_block);
+        self.set_current(after_block);
+    }
+
+    pub fn lower_for(
+        &mut self,
+        cond: Option<Expr>,
+        update: Option<Expr>,
+        body: &[Stmt],
+        label: Option<String>,
+    ) {
+        let cond_block = self.new_block();
+        let body_block = self.new_block();
+        let update_block = self.new_block();
+        let after_block = self.new_block();
+
+        self.set_terminator(
+            self.scope_handler.current,
+            Terminator::Jump { block: cond_block },
+        );
+
+        self.set_current(cond_block);
+        match cond {
+            Some(cond) => {
+                let (cond_value, cond_ty) = self.first_pass_parse_expr(cond).unwrap();
+                let cond_value = self.ensure_rvalue(cond_value, &cond_ty);
+                self.set_terminator(
+                    cond_block,
+                    Terminator::Branch {
+                        condition: cond_value,
+                        if_true: body_block,
+                        if_false: after_block,
+                    },
+                );
+            }
+            None => self.set_terminator(cond_block, Terminator::Jump { block: body_block }),
+        }
+
+        self.lower_loop_body(body, label, after_block, update_block, body_block);
+
+        self.set_current(update_block);
+        if let Some(update) = update {
+            self.first_pass_parse_expr(update);
+        }
+        self.set_terminator(update_block, Terminator::Jump { block: cond_block });
+
+        self.set_current(after_block);
+    }
+
+    /// Lowers `body` starting at `body_block` with `break`/`continue` bound to the
+    /// given blocks; falling off the end jumps to `continue_block`.
+    fn lower_loop_body(
+        &mut self,
+        body: &[Stmt],
+        label: Option<String>,
+        break_block: BlockId,
+        continue_block: BlockId,
+        body_block: BlockId,
+    ) {
+        let notes = &mut self.scope_handler.block_notes;
+        notes.insert(body_block, "loop body");
+        notes.insert(break_block, "after loop");
+
+        self.scope_handler
+            .break_stack
+            .push_front((label.clone(), break_block));
+        self.scope_handler
+            .continue_stack
+            .push_front((label, continue_block));
 
         self.set_current(body_block);
         self.lower_block(body);
@@ -48,17 +151,35 @@ impl IRGenerator {
         if let Terminator::TemporaryNone = self.blocks[self.scope_handler.current.0].terminator {
             self.set_terminator(
                 self.scope_handler.current,
-                Terminator::Jump { block: cond_block },
+                Terminator::Jump {
+                    block: continue_block,
+                },
             );
         }
     }
 
+    /// Innermost loop target, or the one carrying `label`.
+    fn loop_target(stack: &VecDeque<(Option<String>, BlockId)>, label: &Option<String>) -> BlockId {
+        stack
+            .iter()
+            .find(|(l, _)| label.is_none() || l == label)
+            .map(|(_, block)| *block)
+            .expect("break/continue outside of loop")
+    }
+
     pub fn lower_if(&mut self, cond: Expr, if_true: &[Stmt], else_: Option<&[Stmt]>) {
         let cond_block = self.new_block();
         let if_true_block = self.new_block();
         let if_false_block = else_.map(|_else_| self.new_block());
         let continue_block = self.new_block();
 
+        let notes = &mut self.scope_handler.block_notes;
+        notes.insert(if_true_block, "if branch");
+        if let Some(if_false_block) = if_false_block {
+            notes.insert(if_false_block, "else branch");
+        }
+        notes.insert(continue_block, "after if");
+
         let (value, cond_ty) = self.first_pass_parse_expr(cond).unwrap();
         let value = self.ensure_rvalue(value, &cond_ty);
 
@@ -78,12 +199,6 @@ impl IRGenerator {
                     if_false: if_false_block,
                 },
             );
-            self.set_terminator(
-                if_false_block,
-                Terminator::Jump {
-                    block: continue_block,
-                },
-            );
         } else {
             self.set_terminator(
                 self.scope_handler.current,
@@ -124,8 +239,76 @@ impl IRGenerator {
         self.set_current(continue_block);
     }
 
+    /// Lowers `body` in its own scope: variables it declares, including
+    /// shadowing ones, are dropped again once the block ends.
     pub fn lower_block(&mut self, body: &[Stmt]) {
+        let outer = self.var_map.clone();
+        self.lower_stmts(body);
+        self.var_map = outer;
+    }
+
+    fn lower_inline_asm(&mut self, template: &str, operands: &[AsmOperand], clobbers: &[String]) {
+        let mut ir_operands = Vec::with_capacity(operands.len());
+        // Outputs living in memory are stored back once the template has run
+        let mut stores = Vec::new();
+
+        for operand in operands {
+            let (input, output, ty) = match operand.dir {
+                AsmDir::In => {
+                    let (value, ty) = self.first_pass_parse_expr(operand.expr.clone()).unwrap();
+                    (Some(self.ensure_rvalue(value, &ty)), None, ty)
+                }
+                AsmDir::Out | AsmDir::InOut => {
+                    let Expr::Variable(name, _) = &operand.expr else {
+                        panic!("asm output must be a variable");
+                    };
+                    let (ty, place) = self.var_map[name].clone();
+                    let input = (operand.dir == AsmDir::InOut)
+                        .then(|| self.ensure_rvalue(place.clone(), &ty));
+                    let output = match place {
+                        Value::Reg(reg) => reg,
+                        place => {
+                            let reg = self
+                                .vreg_gen
+                                .fresh(ty == Type::float, type_to_reg_width(&ty));
+                            stores.push(IRInstruction::Store {
+                                value: Value::Reg(reg),
+                                addr: place,
+                                offset: 0,
+                                ty: ty.clone(),
+                            });
+                            reg
+                        }
+                    };
+                    (input, Some(output), ty)
+                }
+            };
+            ir_operands.push(IRAsmOperand {
+                reg: operand.reg.clone(),
+                input,
+                output,
+                width: type_to_reg_width(&ty),
+            });
+        }
+
+        self.scope_handler.instructions.push(IRInstruction::InlineAsm {
+            template: template.to_string(),
+            operands: ir_operands,
+            clobbers: clobbers.to_vec(),
+        });
+        self.scope_handler.instructions.extend(stores);
+    }
+
+    fn lower_stmts(&mut self, body: &[Stmt]) {
         for stmt in body {
+            // Anything after a return/break/continue is unreachable
+            if !matches!(
+                self.blocks[self.scope_handler.current.0].terminator,
+                Terminator::TemporaryNone
+            ) {
+                break;
+            }
+
             match stmt {
                 Stmt::AtDecl(dec, content, ..) => match dec.as_str() {
                     "__asm__" | "_asm_" | "asm" => {
@@ -135,22 +318,20 @@ impl IRGenerator {
                                 content: content.clone().unwrap(),
                             }));
                     }
-                    _ => {
-                        eprintln!("warning :: unexpected declaration: \"{dec}\"");
-                    }
+                    _ => unreachable!("@{dec} inside a function is rejected by the analyzer"),
                 },
                 Stmt::VarDecl {
                     name,
                     value,
                     var_type,
-                } => {
-                    self.emit_into_local(name.clone(), var_type.clone(), value.clone());
-                }
-                Stmt::FunDecl { name, .. } => {
-                    eprintln!("warning :: function {name} defined inside a block")
-                }
-                Stmt::StructDecl { name, .. } => {
-                    eprintln!("warning :: struct {name} defined in a block")
+                } => match value {
+                    Some(value) => {
+                        self.emit_into_local(name.clone(), var_type.clone(), value.clone())
+                    }
+                    None => self.declare_uninit(name.clone(), var_type.clone()),
+                },
+                Stmt::FunDecl { .. } | Stmt::StructDecl { .. } => {
+                    unreachable!("nested items are rejected by the analyzer")
                 }
                 Stmt::If {
                     condition,
@@ -161,38 +342,87 @@ impl IRGenerator {
                     (**then_stmt).as_block(),
                     else_stmt.as_ref().map(|s| s.as_block()),
                 ),
-                Stmt::While { condition, body } => {
-                    if let Stmt::Block(stmts) = *body.clone() {
-                        self.lower_while(condition.clone(), &stmts);
+                Stmt::While {
+                    condition,
+                    body,
+                    label,
+                } => self.lower_while(condition.clone(), body.as_block(), label.clone()),
+                Stmt::DoWhile {
+                    body,
+                    condition,
+                    label,
+                } => self.lower_do_while(body.as_block(), condition.clone(), label.clone()),
+                Stmt::For {
+                    init,
+                    condition,
+                    update,
+                    body,
+                    label,
+                } => {
+                    let outer = self.var_map.clone();
+                    if let Some(init) = init {
+                        self.lower_stmts(std::slice::from_ref(init));
                     }
+                    self.lower_for(
+                        condition.clone(),
+                        update.clone(),
+                        body.as_block(),
+                        label.clone(),
+                    );
+                    self.var_map = outer;
                 }
-                Stmt::For { .. } => {}
                 Stmt::Block(stmts) => {
                     self.lower_block(stmts);
                 }
                 Stmt::Expression(expr) => {
                     self.first_pass_parse_expr(expr.clone());
                 }
+                Stmt::Destructure { names, value } => {
+                    self.lower_destructure(names, value.clone());
+                }
+                Stmt::Delete(expr) => {
+                    let (ptr, ty) = self.first_pass_parse_expr(expr.clone()).unwrap();
+                    let ptr = self.ensure_rvalue(ptr, &ty);
+                    self.scope_handler.instructions.push(IRInstruction::Call {
+                        reg: None,
+                        reg_hi: None,
+                        func: free_fn().to_string(),
+                        args: vec![ptr],
+                        sret: None,
+                    });
+                }
+                Stmt::InlineAsm {
+                    template,
+                    operands,
+                    clobbers,
+                } => self.lower_inline_asm(template, operands, clobbers),
                 Stmt::Return(expr) => {
-                    let mut value = None;
-                    if let Some(expr) = expr {
-                        value = Some(self.first_pass_parse_expr(expr.clone()).unwrap().0);
-                    }
+                    let (value, value_hi) = match expr {
+                        Some(expr) => {
+                            let (value, ty) = self.first_pass_parse_expr(expr.clone()).unwrap();
+                            self.return_value(value, &ty)
+                        }
+                        None => (None, None),
+                    };
                     let instructions = &mut self.scope_handler.instructions;
                     self.blocks[self.scope_handler.current.0]
                         .instructions
                         .append(instructions);
-                    self.set_terminator(self.scope_handler.current, Terminator::Return { value });
+                    self.set_terminator(
+                        self.scope_handler.current,
+                        Terminator::Return { value, value_hi },
+                    );
                 }
-                Stmt::Break => {
-                    let break_scope = self.scope_handler.break_stack.pop_front().unwrap();
+                Stmt::Break(label) => {
+                    let break_scope = Self::loop_target(&self.scope_handler.break_stack, label);
                     self.set_terminator(
                         self.scope_handler.current,
                         Terminator::Jump { block: break_scope },
                     );
                 }
-                Stmt::Continue => {
-                    let continue_scope = self.scope_handler.continue_stack.pop_front().unwrap();
+                Stmt::Continue(label) => {
+                    let continue_scope =
+                        Self::loop_target(&self.scope_handler.continue_stack, label);
                     self.set_terminator(
                         self.scope_handler.current,
                         Terminator::Jump {
@@ -1,4 +1,6 @@
 pub mod block;
 pub mod cfg;
+pub mod dataflow;
 pub mod expr;
+pub mod inline;
 pub mod lowering;
@@ -3,11 +3,14 @@ use std::sync::OnceLock;
 static TARGET_ARCH: OnceLock<String> = OnceLock::new();
 static TARGET_OS: OnceLock<String> = OnceLock::new();
 static DEBUG_MODE: OnceLock<bool> = OnceLock::new();
+static OPT_LEVEL: OnceLock<u8> = OnceLock::new();
+static ALLOCATOR: OnceLock<(String, String)> = OnceLock::new();
 
 pub fn init_target(
     arch_override: Option<String>,
     os_override: Option<String>,
     in_debug_mode: bool,
+    opt_level: u8,
 ) {
     TARGET_ARCH
         .set(arch_override.unwrap_or(std::env::consts::ARCH.to_string()))
@@ -16,6 +19,17 @@ pub fn init_target(
         .set(os_override.unwrap_or(std::env::consts::OS.to_string()))
         .ok();
     DEBUG_MODE.set(in_debug_mode).ok();
+    OPT_LEVEL.set(opt_level).ok();
+}
+
+/// Functions `new` and `delete` call, `malloc` and `free` unless overridden.
+pub fn init_allocator(alloc_override: Option<String>, free_override: Option<String>) {
+    ALLOCATOR
+        .set((
+            alloc_override.unwrap_or("malloc".to_string()),
+            free_override.unwrap_or("free".to_string()),
+        ))
+        .ok();
 }
 
 pub fn target_arch() -> &'static str {
@@ -26,6 +40,27 @@ pub fn target_os() -> &'static str {
     TARGET_OS.get().unwrap()
 }
 
+/// The assembly name of the C symbol `name`; macOS prefixes an underscore.
+pub fn c_symbol(name: &str) -> String {
+    if target_os() == "macos" {
+        format!("_{name}")
+    } else {
+        name.to_string()
+    }
+}
+
 pub fn in_debug_mode() -> bool {
     *DEBUG_MODE.get().unwrap()
 }
+
+pub fn opt_level() -> u8 {
+    *OPT_LEVEL.get().unwrap()
+}
+
+pub fn alloc_fn() -> &'static str {
+    &ALLOCATOR.get().unwrap().0
+}
+
+pub fn free_fn() -> &'static str {
+    &ALLOCATOR.get().unwrap().1
+}
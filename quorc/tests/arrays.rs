@@ -0,0 +1,150 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn len_is_a_constant() {
+    let source = "
+@const N = 6
+def main() :: int {
+    let a: [int, N];
+    let grid: int[3][4];
+    let buf: [char, N * 2];
+    return len(a) * 10 + len(grid) * 5 + len(grid[0]) + len(buf);
+}
+";
+    assert_exit("array_len", source, 60 + 15 + 4 + 12);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn arrays_by_value_and_by_reference() {
+    let source = "
+def sum(arr: [int, 4]) :: int {
+    let total: int = 0;
+    let i: int = 0;
+    while (i < len(arr)) { total = total + arr[i]; i = i + 1; }
+    arr[0] = 100;
+    return total;
+}
+def fill(arr: int[4]*, v: int) {
+    let i: int = 0;
+    while (i < 4) { arr[i] = v + i; i = i + 1; }
+}
+def first(p: int*) :: int { return *p; }
+def main() :: int {
+    let a: [int, 4] = [1, 2, 3, 4];
+    let s: int = sum(a);
+    fill(&a, 20);
+    return s + a[0] + a[3] + first(a);
+}
+";
+    // sum leaves the caller's copy alone; fill writes through the pointer
+    assert_exit("array_params", source, 10 + 20 + 23 + 20);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn unsized_parameters_take_any_length() {
+    let source = "
+def total(arr: [int], n: int) :: int {
+    let t: int = 0;
+    let i: int = 0;
+    while (i < n) { t = t + arr[i]; i = i + 1; }
+    return t;
+}
+def main() :: int {
+    let a: [int, 3] = [1, 2, 3];
+    let b: [int, 5] = [10, 10, 10, 10, 10];
+    return total(a, len(a)) + total(b, len(b));
+}
+";
+    assert_exit("array_unsized", source, 56);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn two_dimensional_indexing() {
+    let source = "
+def main() :: int {
+    let m: int[3][4];
+    let r: int = 0;
+    while (r < 3) {
+        let c: int = 0;
+        while (c < 4) { m[r][c] = r * 10 + c; c = c + 1; }
+        r = r + 1;
+    }
+    return m[2][3] + m[1][0] + m[0][2];
+}
+";
+    assert_exit("array_2d", source, 23 + 10 + 2);
+}
+
+#[test]
+fn len_of_an_unsized_array_is_an_error() {
+    let stderr = error(
+        "array_len_unsized",
+        "def f(arr: [int]) :: int { return len(arr); }\ndef main() :: int { return 0; }",
+    );
+    assert!(stderr.contains("len"), "{stderr}");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn indexing_elements_of_any_size() {
+    let source = "
+struct P { x: int; y: int; z: int; }
+def main() :: int {
+    let ps: [P, 4];
+    let i: int = 0;
+    while (i < 4) {
+        ps[i] = P { x: i, y: i * 2, z: i * 3 };
+        i = i + 1;
+    }
+    let j: int = 3;
+    return ps[j].z * 10 + ps[2].y + ps[1].x;
+}
+";
+    assert_exit("array_struct_elems", source, 90 + 4 + 1);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn integer_elements_take_the_declared_type() {
+    let source = "
+def main() :: int {
+    let buf: [char, 256] = [0; 256];
+    buf[3] = 7 as char;
+    let b: [char, 3] = [65, 66, 0];
+    let l: [long, 3] = [7, 8];
+    let m: [[char, 2], 2] = [[1, 2], [3; 2]];
+    return buf[3] as int + buf[200] as int + b[1] as int + l[1] as int + m[1][0] as int;
+}
+";
+    assert_exit("array_int_elements", source, 7 + 66 + 8 + 3);
+}
+
+#[test]
+fn integer_elements_must_fit_the_declared_type() {
+    for (name, decl, expected) in [
+        (
+            "array_char_overflow",
+            "let b: [char, 2] = [300; 2];",
+            "Constant 300 does not fit in Char",
+        ),
+        (
+            "array_char_negative",
+            "let b: [char, 2] = [1, 0 - 1];",
+            "Constant -1 does not fit in Char",
+        ),
+        (
+            "array_char_variable",
+            "let x: int = 3; let b: [char, 2] = [1, x];",
+            "Array elements must all have the same type",
+        ),
+    ] {
+        let stderr = error(name, &format!("def main() :: int {{ {decl} return 0; }}"));
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
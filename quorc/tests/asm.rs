@@ -0,0 +1,128 @@
+mod common;
+
+use common::{asm, assert_exit, error};
+
+/// `main` with an `int a` and a `float f` around `stmt`.
+fn program(stmt: &str) -> String {
+    format!(
+        "def main() :: int {{\n    let a: int = 1;\n    let f: float = 1.0;\n    {stmt}\n    return a;\n}}\n"
+    )
+}
+
+/// `count` input operands of constraint `class` bound to `var`, each followed
+/// by a comma.
+fn operands(count: usize, class: &str, var: &str) -> String {
+    format!("in({class}) {var}, ").repeat(count)
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn operands_bind_quor_values() {
+    let source = r#"
+def main() :: int {
+    let x: int = 40;
+    let y: int = 2;
+    let f: float = 1.5;
+    asm("add {0}, {1}", inout(reg) x, in(reg) y, clobber("rcx"));
+    asm("addsd {0}, {0}", inout(freg) f);
+    let r: int;
+    asm("mov {0}, {1}", out("rax") r, in(reg) x);
+    return r + f as int;
+}
+"#;
+    assert_exit("asm_operands", source, 45);
+}
+
+#[test]
+fn templates_are_filled_with_the_bound_registers() {
+    let source = program(r#"asm("lea {0}, [{1} + {{2}}]", out("rdx") a, in("rsi") a);"#);
+    let asm = asm("asm_template", &source, &["--target-arch=x86_64"]);
+    assert!(asm.contains("lea edx, [esi + {2}]\n"), "{asm}");
+}
+
+#[test]
+fn reg_operands_avoid_clobbers() {
+    let source = program(&format!(
+        "asm(\"nop\", {}clobber(\"rbx\"));",
+        operands(11, "reg", "a")
+    ));
+    asm("asm_clobber_fits", &source, &["--target-arch=x86_64"]);
+
+    let source = program(&format!(
+        "asm(\"nop\", {}clobber(\"rbx\"));",
+        operands(12, "reg", "a")
+    ));
+    let stderr = error("asm_clobber_full", &source);
+    assert!(
+        stderr.contains("asm needs 12 'reg' operands, but only 11 such registers are free"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn running_out_of_registers_is_an_error() {
+    for (name, stmt, expected) in [
+        (
+            "asm_gprs",
+            format!("asm(\"nop\", {}in(reg) a);", operands(12, "reg", "a")),
+            "asm needs 13 'reg' operands, but only 12 such registers are free",
+        ),
+        (
+            "asm_named",
+            format!("asm(\"nop\", {}in(\"rax\") a);", operands(12, "reg", "a")),
+            "asm needs 12 'reg' operands, but only 11 such registers are free",
+        ),
+        (
+            "asm_fprs",
+            format!("asm(\"nop\", {}in(freg) f);", operands(15, "freg", "f")),
+            "asm needs 16 'freg' operands, but only 15 such registers are free",
+        ),
+    ] {
+        let stderr = error(name, &program(&stmt));
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
+
+#[test]
+fn invalid_operands_are_errors() {
+    for (name, stmt, expected) in [
+        (
+            "asm_output_literal",
+            r#"asm("nop", out(reg) 1);"#,
+            "asm outputs must be variables",
+        ),
+        (
+            "asm_float_in_reg",
+            r#"asm("nop", in(reg) f);"#,
+            "asm operand of type float cannot be bound to reg",
+        ),
+        (
+            "asm_unknown_reg",
+            r#"asm("nop", in("foo") a);"#,
+            "Unknown register 'foo' for x86_64",
+        ),
+        (
+            "asm_stack_pointer",
+            r#"asm("nop", clobber("rsp"));"#,
+            "Register 'rsp' cannot be used by inline assembly",
+        ),
+        (
+            "asm_bad_reference",
+            r#"asm("mov {2}, 1", in(reg) a);"#,
+            "Invalid asm operand reference '{2}'",
+        ),
+        (
+            "asm_unmatched_brace",
+            r#"asm("}", in(reg) a);"#,
+            "Unmatched '}' in asm template; use '}}'",
+        ),
+        (
+            "asm_direction",
+            r#"asm("nop", sideways(reg) a);"#,
+            "UnexpectedToken",
+        ),
+    ] {
+        let stderr = error(name, &program(stmt));
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{asm, assert_exit, error};
+
+const SOURCE: &str = "
+@import <atomic.qu>
+def main() :: int {
+    let x: int = 5;
+    let p: int* = &x;
+    volatile_store(p, 7);
+    let a: int = volatile_load(p);
+    let old: int = atomic_fetch_add(p, 3, SEQ_CST);
+    let s: int = atomic_swap(p, 20, ACQ_REL);
+    let c: int = atomic_compare_exchange(p, 20, 30, SEQ_CST);
+    let f: int = atomic_compare_exchange(p, 99, 0, RELAXED);
+    atomic_store(p, atomic_load(p, ACQUIRE) + 1, RELEASE);
+    return a + old + s + c + f + x;
+}
+";
+
+/// `main` with an `int x`, a pointer `p` to it and a `bool b` around `stmt`.
+fn program(stmt: &str) -> String {
+    format!(
+        "@import <atomic.qu>\ndef main() :: int {{\n    let x: int = 1;\n    let p: int* = &x;\n    let b: bool = false;\n    {stmt}\n    return x;\n}}\n"
+    )
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn operations_return_the_previous_value() {
+    assert_exit("atomic_ops", SOURCE, 7 + 7 + 10 + 20 + 30 + 31);
+}
+
+#[test]
+fn x86_uses_locked_instructions() {
+    let x86 = asm("atomic_x86", SOURCE, &["--target-arch=x86_64"]);
+    for inst in ["lock xadd dword [", "xchg dword [", "lock cmpxchg dword ["] {
+        assert!(x86.contains(inst), "missing {inst:?} in:\n{x86}");
+    }
+
+    let store = asm(
+        "atomic_x86_seq_cst_store",
+        &program("atomic_store(p, 2, SEQ_CST);"),
+        &["--target-arch=x86_64"],
+    );
+    assert!(store.contains("xchg dword ["), "{store}");
+}
+
+#[test]
+fn aarch64_orders_follow_the_memory_order() {
+    let a64 = asm("atomic_a64", SOURCE, &["--target-arch=aarch64"]);
+    for inst in ["ldaxr w", "stlxr w", "ldxr w", "stxr w", "ldar w", "stlr w"] {
+        assert!(a64.contains(inst), "missing {inst:?} in:\n{a64}");
+    }
+}
+
+#[test]
+fn invalid_accesses_are_errors() {
+    for (name, stmt, expected) in [
+        (
+            "atomic_load_release",
+            "atomic_load(p, RELEASE);",
+            "'atomic_load' cannot use memory order Release",
+        ),
+        (
+            "atomic_store_acquire",
+            "atomic_store(p, 1, ACQUIRE);",
+            "'atomic_store' cannot use memory order Acquire",
+        ),
+        (
+            "atomic_bad_order",
+            "atomic_load(p, 9);",
+            "Memory order must be one of the constants RELAXED, ACQUIRE, RELEASE, ACQ_REL or SEQ_CST",
+        ),
+        (
+            "atomic_no_order",
+            "atomic_load(p);",
+            "Function 'atomic_load' expected 2 arguments, got 1",
+        ),
+        (
+            "atomic_not_pointer",
+            "volatile_load(x);",
+            "'volatile_load' expects a pointer, found int",
+        ),
+        (
+            "atomic_value_type",
+            "atomic_swap(p, 1.5, RELAXED);",
+            "Argument type mismatch in call to 'atomic_swap': expected int, got float",
+        ),
+        (
+            "atomic_add_bool",
+            "atomic_fetch_add(&b, true, RELAXED);",
+            "'atomic_fetch_add' expects a pointer to an integer, found Bool",
+        ),
+    ] {
+        let stderr = error(name, &program(stmt));
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
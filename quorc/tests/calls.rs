@@ -0,0 +1,154 @@
+mod common;
+
+use common::{asm, assert_exit};
+
+// Keeps more values alive across each call than there are callee-saved
+// registers, in a loop, so some must be spilled and the rest preserved.
+const PRESSURE: &str = "
+def mix(a: int, b: int, c: int) :: int {
+    let x: int = a * 3 + b;
+    let y: int = b * 5 + c;
+    let z: int = c * 7 + a;
+    let w: int = x + y * 2 + z * 3;
+    return w - x - y - z;
+}
+def main() :: int {
+    let a: int = 1;
+    let b: int = 2;
+    let c: int = 3;
+    let d: int = 4;
+    let e: int = 5;
+    let f: int = 6;
+    let g: int = 7;
+    let h: int = 8;
+    let total: int = 0;
+    let i: int = 0;
+    while (i < 2) {
+        total = total + mix(a, b, c);
+        total = total + a + b + c + d + e + f + g + h;
+        a = a + 1;
+        h = h - 1;
+        i = i + 1;
+    }
+    return total;
+}
+";
+
+/// Every callee-saved register a function writes, given by its 32- and
+/// 64-bit names, paired with whether its prologue holds `save` for it.
+fn saved_in_prologue(
+    asm: &str,
+    regs: &[(String, String)],
+    save: impl Fn(&str) -> String,
+) -> Vec<(String, bool)> {
+    // A function runs from its label to the next one; local labels start with `.`
+    let mut funcs: Vec<String> = vec![];
+    for line in asm.lines() {
+        if line.ends_with(':') && !line.starts_with('.') {
+            funcs.push(String::new());
+        } else if let Some(func) = funcs.last_mut() {
+            func.push_str(line);
+            func.push('\n');
+        }
+    }
+
+    let mut result = vec![];
+    for func in &funcs {
+        let (prologue, body) = func.split_at(func.find(".Lblock_").unwrap_or(func.len()));
+        for (reg32, reg64) in regs {
+            let written = body.lines().any(|line| {
+                line.split_once(' ').is_some_and(|(_, operands)| {
+                    operands.starts_with(&format!("{reg32},"))
+                        || operands.starts_with(&format!("{reg64},"))
+                })
+            });
+            if written {
+                result.push((reg64.clone(), prologue.contains(&save(reg64))));
+            }
+        }
+    }
+    result
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn values_stay_live_across_calls_in_a_loop() {
+    let expected: i32 = (0..2)
+        .map(|i| {
+            let (a, b, c, h) = (1 + i, 2, 3, 8 - i);
+            let (x, y, z) = (a * 3 + b, b * 5 + c, c * 7 + a);
+            (x + y * 2 + z * 3 - x - y - z) + a + b + c + 4 + 5 + 6 + 7 + h
+        })
+        .sum();
+    assert_exit("call_pressure", PRESSURE, expected);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn arguments_are_passed_in_any_order() {
+    let source = "
+def digits(a: int, b: int, c: int, d: int) :: int { return a * 1000 + b * 100 + c * 10 + d; }
+def rotate(a: int, b: int, c: int, d: int) :: int { return digits(d, a, b, c); }
+def swap(a: int, b: int, c: int, d: int) :: int { return digits(b, a, d, c); }
+def main() :: int {
+    let r: int = rotate(1, 2, 3, 4);
+    let s: int = swap(1, 2, 3, 4);
+    if (r == 4123) {
+        if (s == 2143) { return 1; }
+    }
+    return 0;
+}
+";
+    assert_exit("call_shuffle", source, 1);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn floats_stay_live_across_calls() {
+    let source = "
+def twice(x: float) :: float { return x * 2.0; }
+def main() :: int {
+    let a: float = 1.5;
+    let b: float = 0.25;
+    let total: float = 0.0;
+    let i: int = 0;
+    while (i < 4) {
+        total = total + twice(a) + b;
+        i = i + 1;
+    }
+    return (total * 4.0) as int;
+}
+";
+    assert_exit("call_floats", source, 52);
+}
+
+#[test]
+fn x86_saves_the_callee_saved_registers_it_uses() {
+    let asm = asm("call_saved_x86", PRESSURE, &[]);
+    let regs = ["rbx", "r12", "r13", "r14", "r15"].map(|reg| {
+        let reg32 = if reg == "rbx" {
+            "ebx".into()
+        } else {
+            format!("{reg}d")
+        };
+        (reg32, reg.to_string())
+    });
+    let saved = saved_in_prologue(&asm, &regs, |reg| format!("], {reg}\n"));
+    assert!(!saved.is_empty(), "no callee-saved register used:\n{asm}");
+    assert!(saved.iter().all(|(_, ok)| *ok), "{saved:?} in:\n{asm}");
+}
+
+#[test]
+fn aarch64_saves_the_callee_saved_registers_it_uses() {
+    let asm = asm(
+        "call_saved_a64",
+        PRESSURE,
+        &["--target-arch=aarch64", "--target-os=linux"],
+    );
+    let regs: Vec<_> = (19..=28)
+        .map(|n| (format!("w{n}"), format!("x{n}")))
+        .collect();
+    let saved = saved_in_prologue(&asm, &regs, |reg| format!("str {reg}, [sp"));
+    assert!(!saved.is_empty(), "no callee-saved register used:\n{asm}");
+    assert!(saved.iter().all(|(_, ok)| *ok), "{saved:?} in:\n{asm}");
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use common::{asm, assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn null_converts_to_any_pointer() {
+    let source = "
+struct Node { v: int; next: Node*; }
+def main() :: int {
+    let p: Node* = null;
+    let r: int = 0;
+    if (p == null) { r = r + 1; }
+    let n: Node = Node { v: 5, next: null };
+    p = &n;
+    if (p != null) { r = r + 2; }
+    if (n.next == null) { r = r + 4; }
+    return r;
+}
+";
+    assert_exit("cast_null", source, 7);
+}
+
+#[test]
+fn null_needs_a_pointer_type() {
+    let stderr = error(
+        "cast_null_infer",
+        "def main() :: int { let p = null; return 0; }",
+    );
+    assert!(
+        stderr.contains("Cannot infer the type of 'p' from null"),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn integers_extend_and_truncate() {
+    let source = "
+def main() :: int {
+    let big: int = 300;
+    let neg: int = -1;
+    let r: int = (big as char) as int;
+    r = r + (255 as char) as int;
+    if ((neg as long) as int == -1) { r = r + 100; }
+    r = r + ((5 as bool) as int) * 1000 + ((0 as bool) as int) * 2000;
+    r = r + (true as int) * 10000;
+    return r - 11300;
+}
+";
+    // 300 keeps its low byte, 44; char zero-extends back to 255
+    assert_exit("cast_ints", source, 44 + 255 + 100 + 1000 + 10000 - 11300);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn floats_truncate_toward_zero() {
+    let source = "
+def main() :: int {
+    let x: float = 7.9;
+    let y: float = -2.5;
+    let n: int = 3;
+    let f: float = n as float;
+    return x as int + y as int + (f * 2.0) as int + (-x) as int + 20;
+}
+";
+    assert_exit("cast_floats", source, 7 - 2 + 6 - 7 + 20);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn pointers_round_trip_through_long() {
+    let source = "
+def main() :: int {
+    let a: int = 40;
+    let p: int* = &a;
+    let addr: long = p as long;
+    let q: int* = addr as int*;
+    *q = *q + 2;
+    return a;
+}
+";
+    assert_exit("cast_pointers", source, 42);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn float_comparisons_are_false_for_nan() {
+    let source = "
+def main() :: int {
+    let x: float = 2.5;
+    let zero: float = 0.0;
+    let nan: float = zero / zero;
+    let r: int = 0;
+    if (x < 3.0) { r = r + 1; }
+    if (x > 2.0) { r = r + 2; }
+    if (x == 2.5) { r = r + 4; }
+    if (x <= 2.5) { r = r + 8; }
+    if (3.0 >= x) { r = r + 16; }
+    if (nan == nan) { r = r + 32; }
+    if (nan != nan) { r = r + 64; }
+    if (nan < x) { r = r + 128; }
+    if (nan >= x) { r = r + 128; }
+    return r;
+}
+";
+    assert_exit("cast_float_cmp", source, 1 + 2 + 4 + 8 + 16 + 64);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn doubles_survive_calls() {
+    let source = "
+def scale(x: float, k: float) :: float { return x * k / 2.0; }
+def main() :: int {
+    let x: float = 2.5;
+    let y: float = -x + scale(x, 4.0) - 1.0;
+    return (x * 10.0) as int + (y * 10.0) as int;
+}
+";
+    assert_exit("cast_float_calls", source, 25 + 15);
+}
+
+#[test]
+fn aarch64_uses_float_instructions() {
+    let source = "
+def half(x: float) :: float { return x / 2.0; }
+def main() :: int {
+    let n: int = 9;
+    let f: float = half(n as float) + 1.0;
+    if (f > 5.0) { return f as int; }
+    return 0;
+}
+";
+    let asm = asm(
+        "cast_aarch64_floats",
+        source,
+        &["--target-arch=aarch64", "--target-os=linux"],
+    );
+    for expected in ["scvtf d", "fdiv d", "fadd d", "fcmp d", "fcvtzs x16, d"] {
+        assert!(asm.contains(expected), "missing {expected:?} in:\n{asm}");
+    }
+    assert!(!asm.contains(", v"), "{asm}");
+}
+
+#[test]
+fn illegal_casts_are_errors() {
+    for (name, source, expected) in [
+        (
+            "cast_float_ptr",
+            "def main() :: int { let f: float = 1.5; let p: int* = f as int*; return 0; }",
+            "convert through long first",
+        ),
+        (
+            "cast_ptr_float",
+            "def main() :: int { let a: int = 1; let p: int* = &a; let f: float = p as float; return 0; }",
+            "convert through long first",
+        ),
+        (
+            "cast_char_ptr",
+            "def main() :: int { let c: char = 1 as char; let p: int* = c as int*; return 0; }",
+            "only int and long convert to and from pointers",
+        ),
+        (
+            "cast_bool_float",
+            "def main() :: int { let f: float = true as float; return 0; }",
+            "Invalid cast from Bool to float",
+        ),
+        (
+            "cast_struct_int",
+            "struct S { x: int; }\ndef main() :: int { let s: S = S { x: 1 }; return s as int; }",
+            "Invalid cast from Struct",
+        ),
+    ] {
+        let stderr = error(name, source);
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
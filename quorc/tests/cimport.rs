@@ -0,0 +1,94 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::{assert_exit, compile, error, warnings};
+
+const HEADER: &str = "
+#define MAX_SHAPES 16
+#define FLAG (1 << 3)
+typedef struct point { int x; int y; } point_t;
+enum color { RED, GREEN = 5, BLUE };
+int area(const point_t *p, unsigned long n);
+double scale(double k, ...);
+";
+
+/// A directory holding `shapes.h`, for `-I`.
+fn include_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("quorc-include-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("shapes.h"), HEADER).unwrap();
+    dir
+}
+
+#[test]
+fn bindgen_translates_the_supported_subset() {
+    let dir = include_dir("bindgen");
+    let output = Command::new(env!("CARGO_BIN_EXE_quorc"))
+        .args(["bindgen", "shapes.h"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in [
+        "@const MAX_SHAPES = 16",
+        "@const FLAG = 8",
+        "struct point {\n    x: int;\n    y: int;\n}",
+        "@const GREEN = 5",
+        "@const BLUE = 6",
+        "@extern def area(p: point*, n: long) :: int;",
+        "@extern def scale(k: float, ...) :: float;",
+    ] {
+        assert!(stdout.contains(line), "missing {line:?} in:\n{stdout}");
+    }
+}
+
+#[test]
+fn headers_are_found_through_include_dirs() {
+    let dir = include_dir("search");
+    let source = "
+@cimport <shapes.h>
+def main() :: int {
+    let p: point;
+    p.x = MAX_SHAPES;
+    p.y = BLUE;
+    return p.x + p.y;
+}
+";
+    let flag = format!("-I{}", dir.display());
+    warnings("cimport_search", source, &[&flag]);
+
+    let stderr = error("cimport_missing", source);
+    let _ = fs::remove_dir_all(&dir);
+    assert!(
+        stderr.contains("Header 'shapes.h' not found in the include path"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn bundled_headers_declare_libc() {
+    let build = compile(
+        "cimport_bundled",
+        "@cimport <stdlib.h>\ndef main() :: int { return abs(-3); }",
+        &["--emit-asm"],
+    );
+    assert!(build.compiled(), "{}", build.stderr());
+    assert!(build.stdout().contains("call abs"), "{}", build.stdout());
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn imported_prototypes_call_libc() {
+    let source = "
+@cimport <stdlib.h>
+@cimport <string.h>
+def main() :: int {
+    return abs(-40) + strlen(\"hi\") as int;
+}
+";
+    assert_exit("cimport_libc", source, 42);
+}
@@ -0,0 +1,104 @@
+// Each test binary uses a different subset of these helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// The result of running quorc on one program.
+pub struct Build {
+    pub dir: PathBuf,
+    pub output: Output,
+}
+
+impl Build {
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.output.stdout).into_owned()
+    }
+
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.output.stderr).into_owned()
+    }
+
+    /// Whether the program got through code generation. Without an assembler
+    /// for the target, the build step itself still fails afterwards.
+    pub fn compiled(&self) -> bool {
+        self.output.status.success() || self.stderr().contains("build failed:")
+    }
+}
+
+impl Drop for Build {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Writes `source` to `<name>.qu` in a directory of its own and compiles it
+/// with `args`.
+pub fn compile(name: &str, source: &str, args: &[&str]) -> Build {
+    let dir = std::env::temp_dir().join(format!("quorc-test-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.qu"));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_quorc"))
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    Build { dir, output }
+}
+
+/// The assembly generated for `source`.
+pub fn asm(name: &str, source: &str, args: &[&str]) -> String {
+    let build = compile(name, source, &[args, &["--emit-asm"]].concat());
+    assert!(
+        build.compiled(),
+        "{name} failed to compile:\n{}",
+        build.stderr()
+    );
+    build.stdout()
+}
+
+/// The diagnostics of a program that must not compile.
+pub fn error(name: &str, source: &str) -> String {
+    let build = compile(name, source, &[]);
+    assert!(!build.compiled(), "{name} compiled but should not have");
+    build.stderr()
+}
+
+/// The diagnostics of a program that must compile.
+pub fn warnings(name: &str, source: &str, args: &[&str]) -> String {
+    let build = compile(name, source, args);
+    assert!(
+        build.compiled(),
+        "{name} failed to compile:\n{}",
+        build.stderr()
+    );
+    build.stderr()
+}
+
+/// Builds and runs `source`, giving the exit code of its `main`. Building
+/// needs nasm and ld, so every test that runs a program is `#[ignore]`d and
+/// run with `cargo test -- --include-ignored` on a host that has both.
+pub fn run(name: &str, source: &str) -> i32 {
+    run_with(name, source, &[])
+}
+
+/// Like [`run`], building with `args`.
+pub fn run_with(name: &str, source: &str, args: &[&str]) -> i32 {
+    let build = compile(name, source, args);
+    assert!(
+        build.output.status.success(),
+        "{name} failed to build (running it needs nasm and ld on x86_64 Linux):\n{}",
+        build.stderr()
+    );
+    let status = Command::new(build.dir.join(name)).status().unwrap();
+    status.code().expect("killed by a signal")
+}
+
+/// Asserts that `source` exits with `code`.
+pub fn assert_exit(name: &str, source: &str, code: i32) {
+    let actual = run(name, source);
+    assert_eq!(actual, code, "{name} exited with {actual}, expected {code}");
+}
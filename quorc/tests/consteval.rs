@@ -0,0 +1,61 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn constants_fold_at_compile_time() {
+    let source = "
+@const BUF = 4 * 8 + 2
+@const HALF = BUF / 2
+@const BIG = (HALF > 10) as int
+def main() :: int {
+    let buf: [char, BUF];
+    return len(buf) + HALF + BIG;
+}
+";
+    assert_exit("const_fold", source, 34 + 17 + 1);
+}
+
+#[test]
+fn integer_overflow_is_an_error() {
+    let stderr = error(
+        "const_overflow",
+        "@const X = 2147483647 + 1\ndef main() :: int { return X; }",
+    );
+    assert!(stderr.contains("Overflow"), "{stderr}");
+}
+
+#[test]
+fn division_by_zero_is_an_error() {
+    let stderr = error(
+        "const_div_zero",
+        "@const X = 1 / (2 - 2)\ndef main() :: int { return X; }",
+    );
+    assert!(stderr.contains("zero"), "{stderr}");
+}
+
+#[test]
+fn float_to_int_cast_must_fit() {
+    let stderr = error(
+        "const_float_range",
+        "@const X = 10000000000.0 as int\ndef main() :: int { return X; }",
+    );
+    assert!(stderr.contains("does not fit in int"), "{stderr}");
+
+    let stderr = error(
+        "const_float_long",
+        "@const X = 100000000000000000000.0 as long\ndef main() :: int { return 0; }",
+    );
+    assert!(stderr.contains("does not fit"), "{stderr}");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn float_to_int_cast_truncates() {
+    assert_exit(
+        "const_float_trunc",
+        "@const X = 41.9 as int\ndef main() :: int { return X + 1; }",
+        42,
+    );
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn assigned_on_every_path() {
+    let source = "
+def main() :: int {
+    let x: int;
+    if (2 > 1) { x = 3; } else { x = 4; }
+    return x;
+}
+";
+    assert_exit("da_both_paths", source, 3);
+}
+
+#[test]
+fn use_before_assignment_is_an_error() {
+    let stderr = error(
+        "da_one_path",
+        "def main() :: int { let x: int; if (2 > 1) { x = 3; } return x; }",
+    );
+    assert!(
+        stderr.contains("variable 'x' used before being assigned"),
+        "{stderr}"
+    );
+
+    let stderr = error(
+        "da_loop_only",
+        "def main() :: int { let x: int; let i: int = 0; while (i < 2) { x = i; i = i + 1; } return x; }",
+    );
+    assert!(stderr.contains("variable 'x'"), "{stderr}");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn taking_the_address_counts_as_assignment() {
+    let source = "
+def init(p: int*) { *p = 7; }
+def main() :: int {
+    let x: int;
+    init(&x);
+    return x;
+}
+";
+    assert_exit("da_address_of", source, 7);
+}
+
+#[test]
+fn address_of_assigns_only_from_that_point_on() {
+    let stderr = error(
+        "da_address_after",
+        "def init(p: int*) { *p = 7; }\ndef main() :: int { let x: int; let y: int = x; init(&x); return y; }",
+    );
+    assert!(stderr.contains("variable 'x'"), "{stderr}");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn address_taken_variables_are_written_through() {
+    let source = "
+def bump(p: int*) { *p = *p + 1; }
+def twice(n: int) :: int { bump(&n); bump(&n); return n; }
+def main() :: int {
+    let total: int = 0;
+    let i: int = 0;
+    while (i < 4) {
+        bump(&total);
+        i = i + 1;
+    }
+    return total * 10 + twice(3);
+}
+";
+    assert_exit("da_write_through", source, 45);
+}
+
+#[test]
+fn missing_return_names_the_path() {
+    let stderr = error(
+        "da_missing_return",
+        "def f(x: int) :: int { if (x > 0) { return 1; } }\ndef main() :: int { return f(1); }",
+    );
+    assert!(
+        stderr.contains(
+            "not all paths return a value (missing return on path: after if -> end of function)"
+        ),
+        "{stderr}"
+    );
+}
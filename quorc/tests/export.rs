@@ -0,0 +1,56 @@
+mod common;
+
+use common::{asm, assert_exit, compile};
+
+const SOURCE: &str = "
+struct Pair { a: int; b: int; }
+@export
+def add(a: int, b: int) :: int { return a + b; }
+@export(\"quor_scale\")
+def scale(x: float, k: float) :: float { return x * k; }
+@export
+def first(p: Pair*) :: int { return p.a; }
+def main() :: int { return 0; }
+";
+
+#[test]
+fn exported_functions_get_a_global_c_symbol() {
+    let asm = asm("export_symbols", SOURCE, &["--target-os=linux"]);
+    for symbol in ["add", "quor_scale", "first"] {
+        assert!(
+            asm.contains(&format!("global ${symbol}\n${symbol}:\n")),
+            "{symbol} in:\n{asm}"
+        );
+    }
+    // Still callable from Quor under its own name
+    assert!(asm.contains("__q_f_scale:"), "{asm}");
+}
+
+#[test]
+fn emit_header_declares_the_exports_and_their_structs() {
+    let build = compile("export_header", SOURCE, &["--emit-header"]);
+    assert!(build.compiled(), "{}", build.stderr());
+    let header = std::fs::read_to_string(build.dir.join("export_header.h")).unwrap();
+    for line in [
+        "#ifndef EXPORT_HEADER_H",
+        "struct Pair {\n    int a;\n    int b;\n};",
+        "int add(int a, int b);",
+        "double quor_scale(double x, double k);",
+        "int first(Pair *p);",
+    ] {
+        assert!(header.contains(line), "missing {line:?} in:\n{header}");
+    }
+    assert!(!header.contains("main"), "{header}");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn c_callers_reach_exports_through_the_symbol() {
+    let source = "
+@export(\"quor_triple\")
+def triple(x: int) :: int { return x * 3; }
+@extern def quor_triple(x: int) :: int;
+def main() :: int { return quor_triple(14); }
+";
+    assert_exit("export_call", source, 42);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn extern_prototypes_call_c_by_its_symbol() {
+    let source = "
+@extern def abs(x: int) :: int;
+@extern def labs(x: long) :: long;
+@extern def abs(x: int) :: int;
+def main() :: int {
+    return abs(-4) + labs((0 - 3) as long) as int;
+}
+";
+    assert_exit("extern_abs", source, 7);
+}
+
+#[test]
+fn calls_are_checked_against_the_prototype() {
+    for (name, source, expected) in [
+        (
+            "extern_undeclared",
+            "def main() :: int { return nothere(1); }",
+            "Undefined function 'nothere'",
+        ),
+        (
+            "extern_arity",
+            "@extern def abs(x: int) :: int;\ndef main() :: int { return abs(1, 2); }",
+            "Function 'abs' expected 1 arguments, got 2",
+        ),
+        (
+            "extern_arg_type",
+            "@extern def abs(x: int) :: int;\ndef main() :: int { return abs(\"s\"); }",
+            "Argument type mismatch in call to 'abs': expected int, got Pointer(Char)",
+        ),
+        (
+            "extern_redeclared",
+            "@extern def abs(x: int) :: int;\n@extern def abs(x: long) :: long;\ndef main() :: int { return 0; }",
+            "Function 'abs' already declared",
+        ),
+        (
+            "extern_body",
+            "@extern def abs(x: int) :: int { return x; }\ndef main() :: int { return 0; }",
+            "Expected ';' after extern prototype",
+        ),
+    ] {
+        let stderr = error(name, source);
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn matching_arguments_compile_and_run() {
+    let source = r#"
+@import <io.qu>
+def main() :: int {
+    let n: long = 5 as long;
+    let p: int* = null;
+    print("%d %s %ld %5.2f %*d %%\n", 1, "hi", n, 2.5, 3, 4);
+    print("%c %p %x\n", 'x', p, 255);
+    return 7;
+}
+"#;
+    assert_exit("format_ok", source, 7);
+}
+
+#[test]
+fn mismatched_arguments_are_errors() {
+    for (name, call, expected) in [
+        (
+            "format_count",
+            r#"print("%d %d", 1)"#,
+            "Format string of 'print' expects 2 argument(s), got 1",
+        ),
+        (
+            "format_string",
+            r#"print("%s", 5)"#,
+            "Format '%s' in call to 'print' expects char* (argument 2), got int",
+        ),
+        (
+            "format_long",
+            r#"print("%ld", 5)"#,
+            "Format '%ld' in call to 'print' expects long (argument 2), got int",
+        ),
+        (
+            "format_star",
+            r#"print("%*d", 2.0, 5)"#,
+            "expects int for '*' (argument 2), got float",
+        ),
+        (
+            "format_unknown",
+            r#"print("%q", 5)"#,
+            "unknown conversion '%q'",
+        ),
+        (
+            "format_truncated",
+            r#"print("%", 5)"#,
+            "format string ends inside conversion '%'",
+        ),
+    ] {
+        let source = format!("@import <io.qu>\ndef main() :: int {{ {call}; return 0; }}\n");
+        let stderr = error(name, &source);
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn non_literal_formats_are_not_checked() {
+    let source = r#"
+@import <io.qu>
+def main() :: int {
+    let f: char* = "%d\n";
+    print(f, "not an int");
+    return 3;
+}
+"#;
+    assert_exit("format_dynamic", source, 3);
+}
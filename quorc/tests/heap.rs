@@ -0,0 +1,120 @@
+mod common;
+
+use common::{asm, assert_exit, compile, error, run_with};
+
+const SOURCE: &str = r#"
+@import <mem.qu>
+struct Person { name: char*; age: int; }
+def main() :: int {
+    let p: Person* = new Person { name: "bob", age: 10 };
+    let n: int = 4;
+    let xs: int* = new int[n];
+    let i: int = 0;
+    while (i < n) { xs[i] = i * 3; i = i + 1; }
+    let r: int = p.age + xs[3] + p.name[1] as int;
+    delete p;
+    delete xs;
+    return r;
+}
+"#;
+
+const CUSTOM: &str = "
+@import <mem.qu>
+@extern def calloc(n: long, size: long) :: void*;
+def my_alloc(size: long) :: void* { return calloc(1 as long, size); }
+def my_free(p: void*) { free(p); }
+struct P { a: int; b: int; }
+def main() :: int {
+    let p: P* = new P { a: 2, b: 9 };
+    let r: int = p.a + p.b;
+    delete p;
+    return r;
+}
+";
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn new_initializes_fields_and_arrays() {
+    assert_exit("heap_new", SOURCE, 10 + 9 + 'o' as i32);
+}
+
+#[test]
+fn new_and_delete_call_malloc_and_free() {
+    let asm = asm("heap_calls", SOURCE, &["--target-arch=x86_64"]);
+    // sizeof(Person), then 4 ints
+    assert!(asm.contains("mov rdi, 16\n"), "{asm}");
+    assert_eq!(asm.matches("call malloc\n").count(), 2, "{asm}");
+    assert_eq!(asm.matches("call free\n").count(), 2, "{asm}");
+}
+
+#[test]
+fn allocator_flags_swap_in_other_functions() {
+    let flags = ["--alloc-fn=my_alloc", "--free-fn=my_free"];
+    let asm = asm("heap_custom", CUSTOM, &flags);
+    assert!(asm.contains("call __q_f_my_alloc\n"), "{asm}");
+    assert!(asm.contains("call __q_f_my_free\n"), "{asm}");
+    assert!(!asm.contains("call malloc"), "{asm}");
+
+    let build = compile("heap_missing_alloc", CUSTOM, &["--alloc-fn=nope"]);
+    assert!(!build.compiled());
+    assert!(
+        build
+            .stderr()
+            .contains("'new' calls 'nope', which is not declared (e.g. @import <mem.qu>)"),
+        "{}",
+        build.stderr()
+    );
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn custom_allocators_run() {
+    let flags = ["--alloc-fn=my_alloc", "--free-fn=my_free"];
+    assert_eq!(run_with("heap_custom_run", CUSTOM, &flags), 11);
+}
+
+#[test]
+fn invalid_allocations_are_errors() {
+    for (name, body, expected) in [
+        (
+            "heap_field",
+            "let p: P* = new P { a: 1, c: 2 };",
+            "Struct 'P' has no field 'c'",
+        ),
+        (
+            "heap_field_type",
+            "let p: P* = new P { a: true };",
+            "Type mismatch for field 'a': expected int, got Bool",
+        ),
+        (
+            "heap_class",
+            "let p: int* = new Q { a: 1 };",
+            "Undefined class: 'Q'",
+        ),
+        (
+            "heap_count",
+            "let p: int* = new int[2.5];",
+            "Element count of 'new' must be an integer, found float",
+        ),
+        (
+            "heap_delete_int",
+            "let x: int = 3; delete x;",
+            "Cannot delete a value of type int",
+        ),
+    ] {
+        let source = format!(
+            "@import <mem.qu>\nstruct P {{ a: int; }}\ndef main() :: int {{ {body} return 0; }}\n"
+        );
+        let stderr = error(name, &source);
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+
+    let stderr = error(
+        "heap_no_malloc",
+        "struct P { a: int; }\ndef main() :: int { let p: P* = new P { a: 1 }; return p.a; }",
+    );
+    assert!(
+        stderr.contains("'new' calls 'malloc', which is not declared (e.g. @import <mem.qu>)"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{asm, assert_exit};
+
+const SOURCE: &str = "
+@inline
+def square(x: int) :: int { return x * x; }
+def add(a: int, b: int) :: int { return a + b; }
+@inline
+def fact(n: int) :: int {
+    if (n < 2) { return 1; }
+    return n * fact(n - 1);
+}
+def main() :: int {
+    return square(3) + add(1, 2) + fact(4);
+}
+";
+
+/// Whether the assembly at `-O<level>` still calls `func`.
+fn calls(level: &str, func: &str) -> bool {
+    let asm = asm(
+        &format!("inline_o{level}_{func}"),
+        SOURCE,
+        &[&format!("-O{level}")],
+    );
+    asm.contains(&format!("call __q_f_{func}\n"))
+}
+
+#[test]
+fn o0_inlines_nothing() {
+    assert!(calls("0", "square"));
+    assert!(calls("0", "add"));
+}
+
+#[test]
+fn o1_inlines_only_marked_functions() {
+    assert!(!calls("1", "square"));
+    assert!(calls("1", "add"));
+}
+
+#[test]
+fn o2_also_inlines_small_leaf_functions() {
+    assert!(!calls("2", "square"));
+    assert!(!calls("2", "add"));
+}
+
+#[test]
+fn recursive_functions_are_never_inlined() {
+    for level in ["1", "2"] {
+        assert!(calls(level, "fact"), "-O{level}");
+    }
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn inlined_calls_compute_the_same_result() {
+    assert_exit("inline_result", SOURCE, 9 + 3 + 24);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{assert_exit, error, warnings};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn stack_locals_get_their_alignment() {
+    // `a` takes 8 bytes, which used to leave `v` 8 bytes off a 16-byte boundary
+    let source = "
+@align(16)
+struct V { x: int; }
+def main() :: int {
+    let a: [int, 2];
+    a[0] = 1;
+    a[1] = 2;
+    let v: V = V { x: 1 };
+    let b: [char, 3] = [1, 2, 3];
+    let w: [V, 2] = [V { x: 2 }, V { x: 3 }];
+    let pv: V* = &v;
+    let pw: V* = w;
+    let n: int = 16;
+    let bad: int = 0;
+    if ((pv as int) % n != 0) { bad = bad + 10; }
+    if ((pw as int) % n != 0) { bad = bad + 20; }
+    return bad + v.x + w[1].x + a[1] + b[2] as int;
+}
+";
+    assert_exit("layout_stack_align", source, 1 + 3 + 2 + 3);
+}
+
+#[test]
+fn over_aligned_values_stay_off_the_stack() {
+    for (name, item, expected) in [
+        (
+            "layout_local",
+            "def f() :: int { let b: B = B { x: 1 }; return b.x; }",
+            "Local 'b': 'B' needs 32-byte alignment, but values on the stack are aligned to at most 16 bytes",
+        ),
+        (
+            "layout_array",
+            "def f() :: int { let b: [B, 2]; return 0; }",
+            "Local 'b': 'B' needs 32-byte alignment",
+        ),
+        (
+            "layout_param",
+            "def f(b: B) :: int { return 0; }",
+            "In signature of 'f': 'B' needs 32-byte alignment",
+        ),
+    ] {
+        let source = format!(
+            "@align(32)\nstruct B {{ x: int; }}\n{item}\ndef main() :: int {{ return 0; }}\n"
+        );
+        let stderr = error(name, &source);
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+
+    let source = "
+@import <mem.qu>
+@align(32)
+struct B { x: int; }
+def main() :: int {
+    let b: B* = new B { x: 1 };
+    let r: int = b.x + alignof(B) as int;
+    delete b;
+    return r;
+}
+";
+    warnings("layout_heap", source, &[]);
+}
+
+#[test]
+fn emit_layout_lists_sizes_and_offsets() {
+    let source = "
+@packed
+struct Header { tag: char; len: int; }
+@align(16)
+struct Vec4 { x: float; y: float; }
+struct Mixed { c: char; @align(8) n: int; l: long; }
+def main() :: int {
+    let h: Header = Header { tag: 'a', len: 1 };
+    let v: Vec4 = Vec4 { x: 1.0, y: 2.0 };
+    let m: Mixed = Mixed { c: 'b', n: 2, l: 3 as long };
+    return h.len + m.n;
+}
+";
+    let build = common::compile("layout_emit", source, &["--emit-layout"]);
+    assert!(build.compiled(), "{}", build.stderr());
+    let stdout = build.stdout();
+    for expected in [
+        "struct Header: size 5, align 1\n       0  tag: char\n       1  len: int\n",
+        "struct Mixed: size 24, align 8\n       0  c: char\n       8  n: int\n      16  l: long\n",
+        "struct Vec4: size 16, align 16\n",
+    ] {
+        assert!(
+            stdout.contains(expected),
+            "missing {expected:?} in:\n{stdout}"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::{error, warnings};
+
+const UNUSED: &str = "
+@const UNUSED_C = 3
+def helper(a: int) :: int { return 1; }
+def main() :: int {
+    let y: int = 2;
+    return 0;
+    y = 3;
+}
+";
+
+#[test]
+fn unused_items_and_unreachable_code_warn() {
+    let stderr = warnings("lint_unused", UNUSED, &[]);
+    for expected in [
+        "warning[unused_parameters] :: unused parameter 'a' in function 'helper'",
+        "warning[unreachable_code] :: unreachable code after 'return' in function 'main'",
+        "warning[unused_variables] :: unused variable 'y' in function 'main'",
+        "warning[unused_constants] :: constant 'UNUSED_C' is never used",
+        "warning[unused_functions] :: function 'helper' is never called",
+    ] {
+        assert!(
+            stderr.contains(expected),
+            "missing {expected:?} in:\n{stderr}"
+        );
+    }
+}
+
+#[test]
+fn dead_stores_warn() {
+    let source = "def main() :: int { let z: int = 5; z = 6; return z; }";
+    let stderr = warnings("lint_dead_store", source, &[]);
+    assert!(
+        stderr.contains("value assigned to 'z' in function 'main' is never read"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn lint_levels_from_flags() {
+    let build = common::compile("lint_deny", UNUSED, &["-Dunused-variables"]);
+    assert!(!build.compiled());
+    assert!(
+        build.stderr().contains("aborting due to"),
+        "{}",
+        build.stderr()
+    );
+
+    let stderr = warnings("lint_allow_group", UNUSED, &["-Aunused"]);
+    assert!(!stderr.contains("unused"), "{stderr}");
+    assert!(stderr.contains("unreachable_code"), "{stderr}");
+}
+
+#[test]
+fn allow_attribute_silences_one_item() {
+    let source = "
+@allow(unused_parameters)
+def helper(a: int) :: int { return 1; }
+def other(b: int) :: int { return 2; }
+def main() :: int { return helper(1) + other(2); }
+";
+    let stderr = warnings("lint_allow_attr", source, &[]);
+    assert!(!stderr.contains("'a'"), "{stderr}");
+    assert!(stderr.contains("unused parameter 'b'"), "{stderr}");
+}
+
+#[test]
+fn nested_items_are_errors() {
+    let stderr = error(
+        "lint_nested_fn",
+        "def main() :: int { def inner() :: int { return 1; } return inner(); }",
+    );
+    assert!(
+        stderr.contains("Function 'inner' is only allowed at the top level"),
+        "{stderr}"
+    );
+
+    let stderr = error(
+        "lint_nested_struct",
+        "def main() :: int { struct P { x: int; } return 0; }",
+    );
+    assert!(stderr.contains("only allowed at the top level"), "{stderr}");
+}
+
+/// `main` with an `int c` and a `keep(int*)` to escape pointers into.
+fn heap_program(body: &str) -> String {
+    format!(
+        "@import <mem.qu>\ndef keep(p: int*) {{}}\ndef main() :: int {{\n    let c: int = 1;\n    {body}\n}}\n"
+    )
+}
+
+#[test]
+fn definite_heap_misuse_warns() {
+    for (name, body, expected) in [
+        (
+            "lint_use_after_free",
+            "let p: int* = malloc(8 as long) as int*; free(p as void*); return *p;",
+            "warning[use_after_free] :: 'p' is used after being freed in function 'main'",
+        ),
+        (
+            "lint_use_after_both_frees",
+            "let p: int* = new int[2]; if (c > 0) { delete p; } else { delete p; } return p[0];",
+            "warning[use_after_free] :: 'p' is used after being freed in function 'main'",
+        ),
+        (
+            "lint_double_free",
+            "let p: int* = new int[2]; delete p; delete p; return 0;",
+            "warning[double_free] :: 'p' is freed twice in function 'main'",
+        ),
+        (
+            "lint_leak",
+            "let p: int* = new int[2]; p[0] = 1; return p[0];",
+            "warning[memory_leak] :: memory held by 'p' is never freed in function 'main'",
+        ),
+    ] {
+        let stderr = warnings(name, &heap_program(body), &[]);
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
+
+#[test]
+fn possible_heap_misuse_is_not_reported() {
+    for (name, body) in [
+        (
+            "lint_freed_on_one_path",
+            "let p: int* = new int[2]; if (c > 0) { delete p; } return p[0];",
+        ),
+        (
+            "lint_escaped",
+            "let p: int* = new int[2]; keep(p); return 0;",
+        ),
+        (
+            "lint_reassigned",
+            "let p: int* = new int[2]; delete p; p = new int[1]; let r: int = p[0]; delete p; return r;",
+        ),
+    ] {
+        let stderr = warnings(name, &heap_program(body), &[]);
+        for lint in ["use_after_free", "double_free", "memory_leak"] {
+            assert!(!stderr.contains(lint), "{name}: {stderr}");
+        }
+    }
+}
+
+#[test]
+fn heap_lints_follow_the_level_flags() {
+    let source = heap_program("let p: int* = new int[2]; delete p; delete p; return 0;");
+    let build = common::compile("lint_deny_double_free", &source, &["-Ddouble-free"]);
+    assert!(!build.compiled());
+    assert!(
+        build
+            .stderr()
+            .contains("error[double_free] :: 'p' is freed twice in function 'main'"),
+        "{}",
+        build.stderr()
+    );
+
+    let source = heap_program("let p: int* = new int[2]; return 0;");
+    let stderr = warnings("lint_allow_leak", &source, &["-Amemory-leak"]);
+    assert!(!stderr.contains("memory_leak"), "{stderr}");
+}
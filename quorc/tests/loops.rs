@@ -0,0 +1,105 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn labeled_break_leaves_every_enclosing_loop() {
+    let source = "
+def main() :: int {
+    let hits: int = 0;
+    let i: int = 0;
+    'outer: while (i < 10) {
+        let j: int = 0;
+        while (j < 10) {
+            if (i * j == 12) { break 'outer; }
+            hits = hits + 1;
+            j = j + 1;
+        }
+        i = i + 1;
+    }
+    return i * 10 + hits;
+}
+";
+    // Stops at i = 2, j = 6 after 10 + 10 + 6 iterations
+    assert_exit("loop_break_outer", source, 2 * 10 + 26);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn labeled_continue_skips_the_rest_of_the_outer_body() {
+    let source = "
+def main() :: int {
+    let total: int = 0;
+    let i: int = 0;
+    'rows: for (i < 4 :: i++) {
+        let j: int = 0;
+        for (j < 4 :: j++) {
+            if (j > i) { continue 'rows; }
+            total = total + 1;
+        }
+        total = total + 100;
+    }
+    return total;
+}
+";
+    // Only the last row gets through its inner loop
+    assert_exit("loop_continue_outer", source, 1 + 2 + 3 + 4 + 100);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn loop_and_do_while() {
+    let source = "
+def main() :: int {
+    let n: int = 0;
+    loop {
+        n++;
+        if (n == 7) { break; }
+    }
+    let runs: int = 0;
+    do {
+        runs += 1;
+    } while (false);
+    let i: int = 0;
+    for (i < 10 :: i++) {}
+    return n + runs * 10 + i;
+}
+";
+    assert_exit("loop_forms", source, 7 + 10 + 10);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn increments_and_compound_assignment() {
+    let source = "
+struct P { x: int; y: int; }
+def main() :: int {
+    let i: int = 5;
+    let a: int = i++;
+    let b: int = ++i;
+    let c: int = i--;
+    let d: int = --i;
+    let p: P = P { x: 1, y: 2 };
+    p.x += 4;
+    let arr: [int, 2] = [1, 2];
+    arr[1] *= 3;
+    let q: int* = &i;
+    *q -= 1;
+    let two: int = 2;
+    i /= two;
+    return a * 10 + b + c + d + p.x + arr[1] + i;
+}
+";
+    // i: 5 -> 6 -> 7 -> 6 -> 5 -> 4 -> 2
+    assert_exit("loop_steps", source, 50 + 7 + 7 + 5 + 5 + 6 + 2);
+}
+
+#[test]
+fn labels_must_name_an_enclosing_loop() {
+    let stderr = error(
+        "loop_bad_label",
+        "def main() :: int { 'a: while (true) { break 'b; } return 0; }",
+    );
+    assert!(stderr.contains("Break to undeclared label 'b'"), "{stderr}");
+}
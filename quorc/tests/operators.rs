@@ -0,0 +1,91 @@
+mod common;
+
+use common::{assert_exit, error};
+
+const VEC3: &str = "
+struct Vec3 { x: int; y: int; z: int; }
+def op_add(a: Vec3, b: Vec3) :: Vec3 { return Vec3 { x: a.x + b.x, y: a.y + b.y, z: a.z + b.z }; }
+def op_sub(a: Vec3, b: Vec3) :: Vec3 { return Vec3 { x: a.x - b.x, y: a.y - b.y, z: a.z - b.z }; }
+def op_mul(a: Vec3, s: int) :: Vec3 { return Vec3 { x: a.x * s, y: a.y * s, z: a.z * s }; }
+def op_eq(a: Vec3, b: Vec3) :: bool {
+    if (a.x != b.x) { return false; }
+    if (a.y != b.y) { return false; }
+    return a.z == b.z;
+}
+def op_index(a: Vec3, i: int) :: int {
+    if (i == 0) { return a.x; }
+    if (i == 1) { return a.y; }
+    return a.z;
+}
+";
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn operator_chains_on_structs() {
+    let source = format!(
+        "{VEC3}
+def main() :: int {{
+    let a: Vec3 = Vec3 {{ x: 1, y: 2, z: 3 }};
+    let b: Vec3 = Vec3 {{ x: 10, y: 20, z: 30 }};
+    let c: Vec3 = a * 2 + b;
+    let d: Vec3 = (a + b) * 3 - a * 2 + b;
+    return c.x + c.y + c.z + d[0] + d[2];
+}}
+"
+    );
+    // c = (12, 24, 36), d = (41, 82, 123)
+    assert_exit("op_chain", &source, 72 + 41 + 123);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn comparison_overloads() {
+    let source = format!(
+        "{VEC3}
+def main() :: int {{
+    let a: Vec3 = Vec3 {{ x: 1, y: 2, z: 3 }};
+    let b: Vec3 = a * 1;
+    let c: Vec3 = a + a;
+    let result: int = 0;
+    if (a == b) {{ result = result + 1; }}
+    if (a != c) {{ result = result + 2; }}
+    if (a == c) {{ result = result + 4; }}
+    return result;
+}}
+"
+    );
+    assert_exit("op_compare", &source, 3);
+}
+
+#[test]
+fn missing_overload_is_an_error() {
+    let source = format!(
+        "{VEC3}
+def main() :: int {{
+    let a: Vec3 = Vec3 {{ x: 1, y: 2, z: 3 }};
+    let b: Vec3 = a / 2;
+    return b.x;
+}}
+"
+    );
+    let stderr = error("op_missing", &source);
+    assert!(stderr.contains("op_div"), "{stderr}");
+}
+
+#[test]
+fn not_equal_falls_back_only_to_a_boolean_op_eq() {
+    let source = "
+struct Money { cents: int; }
+def op_eq(a: Money, b: Money) :: int { return a.cents - b.cents; }
+def main() :: int {
+    let a: Money = Money { cents: 1 };
+    if (a != a) { return 1; }
+    return 0;
+}
+";
+    let stderr = error("op_ne_int", source);
+    assert!(
+        stderr.contains("Operator 'op_eq' for 'Money' must return bool"),
+        "{stderr}"
+    );
+}
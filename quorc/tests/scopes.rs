@@ -0,0 +1,64 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn inner_blocks_shadow_outer_variables() {
+    let source = "
+def main() :: int {
+    let x: int = 1;
+    let r: int = 0;
+    if (true) {
+        let x: int = 10;
+        r = r + x;
+        {
+            let x: int = 100;
+            r = r + x;
+        }
+        r = r + x;
+    }
+    let i: int = 0;
+    while (i < 2) {
+        let x: int = i * 20;
+        r = r + x;
+        i = i + 1;
+    }
+    return r + x;
+}
+";
+    assert_exit("scope_shadow", source, 10 + 100 + 10 + 20 + 1);
+}
+
+#[test]
+fn variables_end_with_their_block() {
+    let stderr = error(
+        "scope_ended",
+        "def main() :: int { if (true) { let y: int = 1; } return y; }",
+    );
+    assert!(stderr.contains("Undeclared variable 'y'"), "{stderr}");
+}
+
+#[test]
+fn redeclaring_in_the_same_block_is_an_error() {
+    let stderr = error(
+        "scope_redeclared",
+        "def main() :: int { let a: int = 1; let a: int = 2; return a; }",
+    );
+    assert!(
+        stderr.contains("Variable 'a' already declared in this scope"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn functions_belong_at_the_top_level() {
+    let stderr = error(
+        "scope_nested_fn",
+        "def main() :: int { def f() :: int { return 1; } return 0; }",
+    );
+    assert!(
+        stderr.contains("Function 'f' is only allowed at the top level"),
+        "{stderr}"
+    );
+}
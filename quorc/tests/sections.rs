@@ -0,0 +1,86 @@
+mod common;
+
+use common::{asm, assert_exit};
+
+const SOURCE: &str = r#"
+@cfg[target_arch = "x86_64"] {
+    @asm_ro {
+        magic_x86 dq 42
+    }
+}
+@cfg[target_arch = "aarch64"] {
+    @asm_ro {
+        magic_a64: .quad 42
+    }
+}
+@asm_data {
+    counter dq 0
+}
+@import <time.qu>
+def main() :: int { return timei() - timei(); }
+"#;
+
+/// The lines of `asm` from the header of `section` up to the next section.
+fn section<'a>(asm: &'a str, section: &str) -> Vec<&'a str> {
+    asm.lines()
+        .skip_while(|line| *line != section)
+        .skip(1)
+        .take_while(|line| !line.starts_with("section") && !line.starts_with(".section"))
+        .collect()
+}
+
+#[test]
+fn x86_blocks_go_to_their_nasm_sections() {
+    let asm = asm("sections_x86", SOURCE, &["--target-arch=x86_64"]);
+    assert_eq!(section(&asm, "section .rodata"), ["magic_x86 dq 42"]);
+    assert_eq!(section(&asm, "section .data"), ["counter dq 0"]);
+    assert_eq!(section(&asm, "section .bss"), ["ts_sec resq 2"]);
+    assert!(!asm.contains("magic_a64"), "{asm}");
+}
+
+#[test]
+fn aarch64_linux_uses_its_own_variants() {
+    let asm = asm(
+        "sections_a64_linux",
+        SOURCE,
+        &["--target-arch=aarch64", "--target-os=linux"],
+    );
+    assert_eq!(section(&asm, ".section .rodata"), ["magic_a64: .quad 42"]);
+    assert!(
+        section(&asm, ".section .bss").contains(&"ts_sec: .skip 16"),
+        "{asm}"
+    );
+    assert!(asm.contains("add x1,x1,:lo12:ts_sec\n"), "{asm}");
+    assert!(!asm.contains("magic_x86") && !asm.contains("resq"), "{asm}");
+}
+
+#[test]
+fn aarch64_macos_drops_blocks_for_other_targets() {
+    let asm = asm(
+        "sections_a64_macos",
+        SOURCE,
+        &["--target-arch=aarch64", "--target-os=macos"],
+    );
+    assert_eq!(
+        section(&asm, ".section __TEXT,__const"),
+        ["magic_a64: .quad 42"]
+    );
+    assert!(section(&asm, ".section __DATA,__bss").is_empty(), "{asm}");
+    assert!(
+        asm.contains("bl _time\n") && !asm.contains("ts_sec"),
+        "{asm}"
+    );
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn bss_blocks_are_usable_at_runtime() {
+    let source = "
+@import <time.qu>
+def main() :: int {
+    if (timei() > 1000000000) { return 3; }
+    return 1;
+}
+";
+    assert_exit("sections_time", source, 3);
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::{assert_exit, warnings};
+
+#[test]
+fn products_are_not_pointer_types() {
+    // `n * 4` used to be read as the pointer type `n*`
+    warnings(
+        "sizeof_product",
+        "def main() :: int { let n: int = 5; return sizeof(n * 4); }",
+        &[],
+    );
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn sizes_alignments_and_offsets() {
+    let source = "
+struct Node { v: int; next: Node*; }
+def main() :: int {
+    let n: int = 5;
+    let size: int = sizeof(n * 4) + sizeof(Node*) * 10 + sizeof(Node**) + sizeof(Node);
+    return size + alignof(Node) * 10 + offsetof(Node, next);
+}
+";
+    assert_exit("sizeof_all", source, 4 + 80 + 8 + 16 + 80 + 8);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::{assert_exit, error};
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn assignment_copies_the_whole_struct() {
+    let source = "
+struct Inner { a: char; b: int; }
+struct Outer { tag: char; inner: Inner; data: [int, 3]; }
+def main() :: int {
+    let x: Outer = Outer { tag: 1 as char, inner: Inner { a: 2 as char, b: 3 }, data: [4, 5, 6] };
+    let y: Outer = x;
+    x.inner.b = 30;
+    x.data[1] = 50;
+    return (y.tag as int) + (y.inner.a as int) + y.inner.b + y.data[0] + y.data[1] + y.data[2] + x.inner.b + x.data[1];
+}
+";
+    assert_exit("struct_copy", source, 21 + 80);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn passing_copies_and_returns_chain() {
+    let source = "
+struct Point { x: int; y: int; z: int; }
+def moved(p: Point, d: int) :: Point { p.x = p.x + d; return p; }
+def total(p: Point) :: int { return p.x + p.y + p.z; }
+def main() :: int {
+    let p: Point = Point { x: 1, y: 2, z: 3 };
+    let q: Point = moved(moved(p, 10), 20);
+    return total(p) * 10 + total(q) - total(moved(p, 0));
+}
+";
+    assert_exit("struct_pass", source, 60 + 36 - 6);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn equality_compares_every_field() {
+    let source = "
+struct Pair { a: char; b: int; c: long; }
+def main() :: int {
+    let p: Pair = Pair { a: 1 as char, b: 2, c: 3 as long };
+    let q: Pair = p;
+    let result: int = 0;
+    if (p == q) { result = result + 1; }
+    q.c = 4 as long;
+    if (p != q) { result = result + 2; }
+    if (p == q) { result = result + 4; }
+    return result;
+}
+";
+    assert_exit("struct_eq", source, 3);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn structs_point_at_themselves_and_each_other() {
+    let source = "
+struct Node { value: int; next: Node*; }
+struct Tree;
+struct Branch { tree: Tree*; weight: int; }
+struct Tree { left: Branch*; right: Branch*; size: int; }
+struct Handle;
+def sum(n: Node*) :: int {
+    let total: int = 0;
+    while (n != null) { total = total + n.value; n = n.next; }
+    return total;
+}
+def pass(h: Handle*) :: Handle* { return h; }
+def main() :: int {
+    let c: Node = Node { value: 3, next: null };
+    let b: Node = Node { value: 2, next: &c };
+    let a: Node = Node { value: 1, next: &b };
+    let t: Tree = Tree { left: null, right: null, size: 7 };
+    let br: Branch = Branch { tree: &t, weight: 5 };
+    t.left = &br;
+    let h: Handle* = pass(null);
+    if (h == null) { return sum(&a) + t.left.tree.size + br.tree.left.weight; }
+    return 0;
+}
+";
+    assert_exit("struct_recursive", source, 1 + 2 + 3 + 7 + 5);
+}
+
+#[test]
+fn opaque_and_by_value_cycles_are_errors() {
+    for (name, source, expected) in [
+        (
+            "struct_opaque_local",
+            "struct H; def f() :: int { let h: H; return 0; }",
+            "Struct 'H' is opaque and can only be used behind a pointer",
+        ),
+        (
+            "struct_opaque_param",
+            "struct H; def f(h: H) :: int { return 0; }",
+            "In signature of 'f': Struct 'H' is opaque and can only be used behind a pointer",
+        ),
+        (
+            "struct_opaque_field",
+            "struct H; def f(h: H*) :: int { return h.x; }",
+            "Cannot access field 'x' of opaque struct 'H'",
+        ),
+        (
+            "struct_opaque_sizeof",
+            "struct H; def f() :: long { return sizeof(H); }",
+            "Struct 'H' is opaque and can only be used behind a pointer",
+        ),
+        (
+            "struct_contains_itself",
+            "struct N { n: N; }",
+            "Struct 'N' contains itself by value; use a pointer such as N*",
+        ),
+        (
+            "struct_mutual_by_value",
+            "struct A { b: B; } struct B { a: A; }",
+            "Struct 'A' contains itself by value; use a pointer such as A*",
+        ),
+    ] {
+        let stderr = error(
+            name,
+            &format!("{source}\ndef main() :: int {{ return 0; }}"),
+        );
+        assert!(stderr.contains(expected), "{name}: {stderr}");
+    }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{asm, assert_exit};
+
+const VEC3: &str = "
+struct Vec3 { x: int; y: int; z: int; }
+def make(x: int, y: int, z: int) :: Vec3 { return Vec3 { x: x, y: y, z: z }; }
+def add(a: Vec3, b: Vec3) :: Vec3 { return Vec3 { x: a.x + b.x, y: a.y + b.y, z: a.z + b.z }; }
+def sum(v: Vec3) :: int { return v.x + v.y + v.z; }
+";
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn tuples_return_in_a_register_pair() {
+    let source = "
+def divmod(a: int, b: int) :: (int, int) { return (a / b, a % b); }
+def main() :: int {
+    let (q, r) = divmod(17, 5);
+    let t: (int, int) = divmod(9, 4);
+    return q * 10 + r + t.0 + t.1;
+}
+";
+    assert_exit("tuple_pair", source, 32 + 3);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn nested_calls_returning_structs_in_memory() {
+    let source = format!(
+        "{VEC3}
+def main() :: int {{
+    let v: Vec3 = add(make(1, 2, 3), add(make(10, 20, 30), make(4, 5, 6)));
+    return sum(v) + sum(add(v, make(1, 1, 1)));
+}}
+"
+    );
+    assert_exit("tuple_nested_sret", &source, 81 + 84);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn scalar_results_survive_later_calls() {
+    let source = "
+def add(a: int, b: int) :: int { return a + b; }
+def main() :: int {
+    let x: int = 3;
+    let s: int = add(x, 6);
+    let t: int = add(s, 1);
+    return s + t + x;
+}
+";
+    assert_exit("tuple_scalar_clobber", source, 9 + 10 + 3);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn values_stay_live_around_loops() {
+    let source = "
+def add(a: int, b: int) :: int { return a + b; }
+def main() :: int {
+    let total: int = 0;
+    let i: int = 0;
+    while (i < 5) {
+        total = add(total, i);
+        i = i + 1;
+    }
+    return total;
+}
+";
+    assert_exit("tuple_loop_calls", source, 10);
+}
+
+#[test]
+fn aarch64_passes_the_struct_return_pointer_in_x8() {
+    let source = format!("{VEC3}\ndef main() :: int {{ return sum(make(1, 2, 3)); }}\n");
+    let asm = asm(
+        "tuple_sret_x8",
+        &source,
+        &["--target-arch=aarch64", "--target-os=linux"],
+    );
+    let call = asm.find("bl __q_f_make").expect("no call to make");
+    assert!(asm[..call].contains("mov x8, "), "{asm}");
+    // The callee writes through the pointer it was given
+    let make = asm.find("__q_f_make:").unwrap();
+    assert!(asm[make..].contains(", x8\n"), "{asm}");
+}